@@ -0,0 +1,195 @@
+//! The interface between echoflow and node kinds shipped as separate crates.
+//!
+//! A node kind implements [`NodeKind`] and is compiled into echoflow behind
+//! a cargo feature, which registers it in the toolbox. Its settings are a
+//! [`Config`] of string values described by a schema, so echoflow can save,
+//! hash and edit them without knowing the kind.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A custom node type.
+pub trait NodeKind: Send + Sync {
+    /// Stable identifier saved in flow files, e.g. `"acme.base64"`. Never
+    /// change it once flows use the kind.
+    fn id(&self) -> &'static str;
+
+    /// Name shown in the toolbox and the node type picker.
+    fn name(&self) -> &'static str;
+
+    /// Toolbox section the kind is listed under.
+    fn category(&self) -> &'static str {
+        "Plugins"
+    }
+
+    /// Tooltip for the toolbox button.
+    fn description(&self) -> &'static str {
+        ""
+    }
+
+    fn ports(&self) -> Ports {
+        Ports::Transform
+    }
+
+    /// The settings the kind takes; new nodes start from their defaults.
+    fn config_schema(&self) -> Vec<ConfigField>;
+
+    /// Fails if the settings cannot work. Runs before every flow run and
+    /// while the node is being edited.
+    fn check(&self, _config: &Config) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Short description shown on the node.
+    fn summary(&self, _config: &Config) -> String {
+        self.name().to_string()
+    }
+
+    /// Side-panel form for the settings. The default builds one from the schema.
+    fn editor(&self, ui: &mut egui::Ui, config: &mut Config) {
+        edit_config(ui, &self.config_schema(), config);
+    }
+
+    /// Runs the node over its input, on a worker thread. Source kinds get
+    /// an empty input.
+    fn execute(&self, config: &Config, input: &[u8]) -> Result<Output, String>;
+}
+
+/// How a kind connects to the rest of the flow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ports {
+    /// No input; produces data from elsewhere. Never cached.
+    Source,
+    /// One input, one output. Cached like any other node.
+    Transform,
+    /// One input, written somewhere outside the flow and passed on
+    /// unchanged. Never cached.
+    Sink,
+}
+
+/// What a run of the node produced.
+#[derive(Debug, Clone, Default)]
+pub struct Output {
+    pub stdout: Vec<u8>,
+    /// Diagnostics shown in the node's run record.
+    pub stderr: Vec<u8>,
+    /// Like a process exit code: the node fails unless it is one of the
+    /// node's success codes (0 by default).
+    pub exit_code: i32,
+}
+
+impl Output {
+    pub fn new(stdout: Vec<u8>) -> Self {
+        Self {
+            stdout,
+            ..Self::default()
+        }
+    }
+}
+
+/// One setting of a node kind.
+#[derive(Debug, Clone)]
+pub struct ConfigField {
+    pub key: &'static str,
+    pub label: &'static str,
+    pub kind: FieldKind,
+    pub default: &'static str,
+    /// Tooltip for the field; may be empty.
+    pub help: &'static str,
+}
+
+#[derive(Debug, Clone)]
+pub enum FieldKind {
+    Text,
+    MultilineText,
+    Number { min: f64, max: f64 },
+    /// Stored as `"true"` or `"false"`.
+    Toggle,
+    /// One of the listed values.
+    Choice(&'static [&'static str]),
+}
+
+/// Values of a node's settings, by key. Values are strings whatever the
+/// field kind; use the typed getters to read them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Config(BTreeMap<String, String>);
+
+impl Config {
+    /// The defaults of every field in the schema.
+    pub fn from_schema(schema: &[ConfigField]) -> Self {
+        Self(
+            schema
+                .iter()
+                .map(|field| (field.key.to_string(), field.default.to_string()))
+                .collect(),
+        )
+    }
+
+    /// The value of a setting; empty if it is not set.
+    pub fn get(&self, key: &str) -> &str {
+        self.0.get(key).map_or("", String::as_str)
+    }
+
+    pub fn flag(&self, key: &str) -> bool {
+        self.get(key) == "true"
+    }
+
+    pub fn number(&self, key: &str) -> Option<f64> {
+        self.get(key).trim().parse().ok()
+    }
+
+    pub fn set(&mut self, key: &str, value: impl Into<String>) {
+        self.0.insert(key.to_string(), value.into());
+    }
+}
+
+/// A form with one row per field of the schema.
+pub fn edit_config(ui: &mut egui::Ui, schema: &[ConfigField], config: &mut Config) {
+    for field in schema {
+        let value = config.0.entry(field.key.to_string()).or_insert_with(|| field.default.to_string());
+        let response = match &field.kind {
+            FieldKind::Text => {
+                ui.label(field.label);
+                ui.text_edit_singleline(value)
+            }
+            FieldKind::MultilineText => {
+                ui.label(field.label);
+                ui.add(egui::TextEdit::multiline(value).code_editor().desired_rows(4))
+            }
+            FieldKind::Number { min, max } => {
+                let mut number: f64 = value.trim().parse().unwrap_or(*min);
+                let response = ui.add(
+                    egui::DragValue::new(&mut number)
+                        .clamp_range(*min..=*max)
+                        .prefix(format!("{}: ", field.label)),
+                );
+                if response.changed() {
+                    *value = number.to_string();
+                }
+                response
+            }
+            FieldKind::Toggle => {
+                let mut on = value == "true";
+                let response = ui.checkbox(&mut on, field.label);
+                if response.changed() {
+                    *value = on.to_string();
+                }
+                response
+            }
+            FieldKind::Choice(choices) => {
+                egui::ComboBox::from_label(field.label)
+                    .selected_text(value.as_str())
+                    .show_ui(ui, |ui| {
+                        for choice in *choices {
+                            ui.selectable_value(value, choice.to_string(), *choice);
+                        }
+                    })
+                    .response
+            }
+        };
+        if !field.help.is_empty() {
+            response.on_hover_text(field.help);
+        }
+    }
+}
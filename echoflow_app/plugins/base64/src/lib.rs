@@ -0,0 +1,171 @@
+//! Base64 encoding and decoding as an echoflow node kind.
+
+use echoflow_node_api::{Config, ConfigField, FieldKind, NodeKind, Output};
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub struct Base64;
+
+impl NodeKind for Base64 {
+    fn id(&self) -> &'static str {
+        "echoflow.base64"
+    }
+
+    fn name(&self) -> &'static str {
+        "Base64"
+    }
+
+    fn category(&self) -> &'static str {
+        "Encoding"
+    }
+
+    fn description(&self) -> &'static str {
+        "Encode the input as base64, or decode base64 back to bytes"
+    }
+
+    fn config_schema(&self) -> Vec<ConfigField> {
+        vec![
+            ConfigField {
+                key: "direction",
+                label: "Direction",
+                kind: FieldKind::Choice(&["encode", "decode"]),
+                default: "encode",
+                help: "",
+            },
+            ConfigField {
+                key: "wrap",
+                label: "Line length",
+                kind: FieldKind::Number { min: 0.0, max: 1000.0 },
+                default: "76",
+                help: "Break encoded output into lines this long; 0 keeps one line",
+            },
+        ]
+    }
+
+    fn summary(&self, config: &Config) -> String {
+        format!("base64 {}", config.get("direction"))
+    }
+
+    fn execute(&self, config: &Config, input: &[u8]) -> Result<Output, String> {
+        match config.get("direction") {
+            "decode" => decode(input).map(Output::new),
+            _ => {
+                let wrap = config.number("wrap").unwrap_or(0.0) as usize;
+                Ok(Output::new(encode(input, wrap)))
+            }
+        }
+    }
+}
+
+fn encode(input: &[u8], wrap: usize) -> Vec<u8> {
+    let mut text = Vec::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &b)| bits | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(ALPHABET[(bits >> (18 - 6 * i) & 63) as usize]);
+            } else {
+                text.push(b'=');
+            }
+        }
+    }
+    let mut out = Vec::with_capacity(text.len() + text.len() / wrap.max(1) + 1);
+    for line in text.chunks(if wrap == 0 { text.len().max(1) } else { wrap }) {
+        out.extend_from_slice(line);
+        out.push(b'\n');
+    }
+    out
+}
+
+fn decode(input: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(input.len() / 4 * 3);
+    let mut bits = 0u32;
+    let mut count = 0;
+    let mut padded = false;
+    for &byte in input {
+        if padded {
+            // Only more padding and line breaks may follow the first `=`.
+            if byte == b'=' || byte.is_ascii_whitespace() {
+                continue;
+            }
+            return Err(format!("Input is not base64: unexpected byte 0x{:02x} after padding", byte));
+        }
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' => {
+                padded = true;
+                continue;
+            }
+            b if b.is_ascii_whitespace() => continue,
+            b => return Err(format!("Input is not base64: unexpected byte 0x{:02x}", b)),
+        };
+        bits = bits << 6 | value as u32;
+        count += 1;
+        if count == 4 {
+            out.extend_from_slice(&bits.to_be_bytes()[1..]);
+            bits = 0;
+            count = 0;
+        }
+    }
+    match count {
+        0 => {}
+        2 => out.push((bits >> 4) as u8),
+        3 => out.extend_from_slice(&((bits >> 2) as u16).to_be_bytes()),
+        _ => return Err("Input is not base64: truncated".into()),
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_every_tail_length() {
+        for input in [&b""[..], b"A", b"AB", b"ABC", b"ABCD", b"ABCDE", b"ABCDEF"] {
+            let encoded = encode(input, 0);
+            assert_eq!(decode(&encoded).unwrap(), input, "{}", String::from_utf8_lossy(&encoded));
+        }
+        assert_eq!(encode(b"A", 0), b"QQ==\n");
+        assert_eq!(encode(b"AB", 0), b"QUI=\n");
+        assert_eq!(encode(b"ABC", 0), b"QUJD\n");
+    }
+
+    #[test]
+    fn wraps_lines_and_decodes_them_back() {
+        let input = b"echoflow wraps long base64 output";
+        let encoded = encode(input, 8);
+        let text = String::from_utf8(encoded.clone()).unwrap();
+        assert!(text.lines().all(|line| line.len() <= 8), "{}", text);
+        assert!(text.lines().count() > 1);
+        assert_eq!(decode(&encoded).unwrap(), input);
+    }
+
+    #[test]
+    fn decodes_url_safe_input() {
+        // 0xfb 0xff encodes to "+/8=" in the standard alphabet.
+        assert_eq!(decode(b"-_8").unwrap(), [0xfb, 0xff]);
+        assert_eq!(decode(b"-_8=").unwrap(), decode(b"+/8=").unwrap());
+    }
+
+    #[test]
+    fn rejects_truncated_input() {
+        assert!(decode(b"QUJDR").unwrap_err().contains("truncated"));
+        assert!(decode(b"Q===").unwrap_err().contains("truncated"));
+    }
+
+    #[test]
+    fn rejects_data_after_padding() {
+        assert!(decode(b"QQ==QUJD").unwrap_err().contains("after padding"));
+        assert_eq!(decode(b"QQ==\n").unwrap(), b"A");
+    }
+
+    #[test]
+    fn rejects_bytes_outside_the_alphabet() {
+        assert!(decode(b"QU*D").is_err());
+    }
+}
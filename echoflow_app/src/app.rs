@@ -1,82 +1,385 @@
 use crate::commands::FlowChartCommand;
-use crate::flowchart::FlowChart;
+use crate::executor::NodeType;
+use crate::flowchart::{CanvasOptions, FlowChart, RunScope};
+use crate::keymap::{self, Keymap};
+use crate::palette::{self, Palette};
+use crate::presets::{self, Preset};
+use crate::registry::registry;
+use crate::subflow::{self, Subflow};
 use eframe::egui;
+use std::path::Path;
 
+#[derive(Default)]
 pub struct PipelineApp {
+    /// The main flow; runs always execute it as a whole.
     pub flowchart: FlowChart,
-    pub pipeline_output: String,
+    /// Final output of the last pipeline run, as raw bytes.
+    pub pipeline_output: Vec<u8>,
+    /// Error or status message from the last pipeline run, if any.
+    pub pipeline_error: Option<String>,
+    /// Subflows opened in their own canvas tab, as paths of subflow node IDs
+    /// from the main flow.
+    pub open_subflows: Vec<Vec<usize>>,
+    /// Index into `open_subflows` of the tab being edited; `None` for the main flow.
+    pub active_tab: Option<usize>,
+    /// Subflows saved in the library file, offered in the toolbox.
+    pub library: Vec<Subflow>,
+    pub library_path: String,
+    /// File the main flow is saved to and opened from.
+    pub flow_path: String,
+    /// Toolbox presets from the preset file.
+    pub presets: Vec<Preset>,
+    pub presets_path: String,
+    /// Whether `presets` was read from `presets_path`. The file is never
+    /// written otherwise, so a file that failed to parse is not clobbered.
+    pub presets_loaded: bool,
+    /// Preset being named in the "Save as preset" window.
+    pub preset_draft: Option<Preset>,
+    /// Filter for the toolbox buttons.
+    pub toolbox_search: String,
+    /// The command palette, while it is open.
+    pub palette: Option<Palette>,
+    /// Flow files saved or opened lately, most recent first.
+    pub recent_flows: Vec<String>,
+    /// Keyboard shortcuts, from the defaults and the keymap file.
+    pub keymap: Keymap,
+    pub keymap_path: String,
+    /// Whether the keyboard shortcuts window is open.
+    pub show_shortcuts: bool,
+    pub show_minimap: bool,
+    /// Size of the minimap on screen; the user can drag it larger.
+    pub minimap_size: egui::Vec2,
+    pub canvas: CanvasOptions,
 }
 
-impl Default for PipelineApp {
-    fn default() -> Self {
-        Self {
-            flowchart: FlowChart::default(),
-            pipeline_output: String::new(),
-        }
+impl PipelineApp {
+    pub fn new() -> Self {
+        let mut app = Self {
+            library_path: subflow::default_library_path().display().to_string(),
+            flow_path: "flow.json".into(),
+            presets_path: presets::default_presets_path().display().to_string(),
+            keymap_path: keymap::default_keymap_path().display().to_string(),
+            show_minimap: true,
+            minimap_size: egui::vec2(200.0, 150.0),
+            ..Self::default()
+        };
+        app.reload_library();
+        app.reload_presets();
+        app.reload_keymap();
+        app.recent_flows = palette::load_recent(&palette::default_recent_path());
+        app
     }
-}
 
-impl PipelineApp {
     /// Executes a given command that affects the flowchart.
     pub fn execute_command(&mut self, command: FlowChartCommand) {
         match command {
             FlowChartCommand::AddNode => {
-                self.flowchart.add_node();
+                self.active_chart_mut().add_node();
             }
             FlowChartCommand::RunPipeline => {
-                if let Some(chain) = self.flowchart.get_pipeline_chain() {
-                    let commands: Vec<String> = chain
-                        .iter()
-                        .filter_map(|id| self.flowchart.nodes.iter().find(|n| n.id == *id))
-                        .map(|node| node.command.clone())
-                        .collect();
-                    match self.flowchart.run_pipeline_with_intermediates(&commands) {
-                        Ok(outputs) => {
-                            for (i, id) in chain.iter().enumerate() {
-                                if let Some(node) =
-                                    self.flowchart.nodes.iter_mut().find(|n| n.id == *id)
-                                {
-                                    node.output = outputs.get(i).cloned().unwrap_or_default();
-                                }
-                            }
-                            self.pipeline_output = outputs.last().cloned().unwrap_or_default();
-                        }
-                        Err(e) => self.pipeline_output = e,
+                self.run_pipeline(RunScope::All, false);
+            }
+            FlowChartCommand::ForceRunPipeline => {
+                self.run_pipeline(RunScope::All, true);
+            }
+            FlowChartCommand::ForceRerunSelectedNode => {
+                let chart = self.active_chart_mut();
+                if let Some(selected_id) = chart.selected_node {
+                    chart.invalidate_from(selected_id);
+                    self.run_pipeline(RunScope::All, false);
+                }
+            }
+            FlowChartCommand::RunUpToSelected => {
+                if let Some(path) = self.selected_path() {
+                    self.run_pipeline(RunScope::UpTo(path), false);
+                }
+            }
+            FlowChartCommand::RunFromSelected => {
+                if let Some(path) = self.selected_path() {
+                    self.run_pipeline(RunScope::From(path), true);
+                }
+            }
+            FlowChartCommand::RunSelectedWithTestInput => {
+                if let Some(path) = self.selected_path() {
+                    let input = self
+                        .flowchart
+                        .node_at_path(&path)
+                        .map(|n| n.test_input.clone().into_bytes())
+                        .unwrap_or_default();
+                    if let Err(e) = self.flowchart.start_isolated_run(&path, input) {
+                        self.pipeline_error = Some(e);
                     }
-                } else {
-                    self.pipeline_output = "No valid pipeline chain found.".into();
                 }
             }
             FlowChartCommand::DeleteSelectedNode => {
-                if let Some(selected_id) = self.flowchart.selected_node {
-                    self.flowchart.nodes.retain(|node| node.id != selected_id);
-                    self.flowchart.connections.retain(|conn| {
-                        conn.from != selected_id && conn.to != selected_id
-                    });
-                    if self.flowchart.connection_start == Some(selected_id) {
-                        self.flowchart.connection_start = None;
+                let chart = self.active_chart_mut();
+                let mut doomed = std::mem::take(&mut chart.selection);
+                doomed.extend(chart.selected_node.take());
+                chart.nodes.retain(|node| !doomed.contains(&node.id));
+                chart
+                    .connections
+                    .retain(|conn| !doomed.contains(&conn.from) && !doomed.contains(&conn.to));
+                if chart.connection_start.is_some_and(|(id, _)| doomed.contains(&id)) {
+                    chart.connection_start = None;
+                }
+                self.close_stale_tabs();
+            }
+            FlowChartCommand::CollapseSelection => {
+                if self.flowchart.is_running() {
+                    self.pipeline_error = Some("Wait for the run to finish before collapsing nodes.".into());
+                } else if let Err(e) = self.active_chart_mut().collapse_selection() {
+                    self.pipeline_error = Some(e);
+                } else {
+                    // Tabs of subflows that were just collapsed into the new one no longer resolve.
+                    self.close_stale_tabs();
+                }
+            }
+            FlowChartCommand::OpenSelectedSubflow => {
+                if let Some(path) = self.selected_path()
+                    && let Some(NodeType::Subflow(_)) =
+                        self.flowchart.node_at_path(&path).map(|n| &n.node_type)
+                {
+                    let tab = match self.open_subflows.iter().position(|open| *open == path) {
+                        Some(tab) => tab,
+                        None => {
+                            self.open_subflows.push(path);
+                            self.open_subflows.len() - 1
+                        }
+                    };
+                    self.active_tab = Some(tab);
+                }
+            }
+            FlowChartCommand::SaveSelectedSubflowToLibrary => {
+                if let Some(path) = self.selected_path()
+                    && let Some(NodeType::Subflow(subflow)) =
+                        self.flowchart.node_at_path(&path).map(|n| &n.node_type)
+                {
+                    match subflow::save_to_library(Path::new(&self.library_path), subflow) {
+                        Ok(()) => self.reload_library(),
+                        Err(e) => self.pipeline_error = Some(e),
                     }
-                    self.flowchart.selected_node = None;
+                }
+            }
+            FlowChartCommand::SaveSelectedNodeAsPreset => {
+                if let Some(path) = self.selected_path()
+                    && let Some(node) = self.flowchart.node_at_path(&path)
+                {
+                    self.preset_draft = Some(Preset::from_node(node));
+                }
+            }
+            FlowChartCommand::SaveFlow => {
+                match self.flowchart.save_to_file(Path::new(&self.flow_path)) {
+                    Ok(()) => self.remember_flow(),
+                    Err(e) => self.pipeline_error = Some(e),
+                }
+            }
+            FlowChartCommand::OpenFlow => {
+                if let Err(e) = self.open_flow() {
+                    self.pipeline_error = Some(e);
                 }
             }
             FlowChartCommand::PanLeft => {
-                self.flowchart.pan_offset.x += 20.0;
+                self.active_chart_mut().pan_offset.x += 20.0;
             }
             FlowChartCommand::PanRight => {
-                self.flowchart.pan_offset.x -= 20.0;
+                self.active_chart_mut().pan_offset.x -= 20.0;
             }
             FlowChartCommand::PanUp => {
-                self.flowchart.pan_offset.y += 20.0;
+                self.active_chart_mut().pan_offset.y += 20.0;
             }
             FlowChartCommand::PanDown => {
-                self.flowchart.pan_offset.y -= 20.0;
+                self.active_chart_mut().pan_offset.y -= 20.0;
             }
             FlowChartCommand::ZoomIn => {
-                self.flowchart.zoom *= 1.1;
+                self.active_chart_mut().zoom_by(1.1);
             }
             FlowChartCommand::ZoomOut => {
-                self.flowchart.zoom /= 1.1;
+                self.active_chart_mut().zoom_by(1.0 / 1.1);
+            }
+            FlowChartCommand::ZoomToFit => {
+                self.active_chart_mut().zoom_to_fit();
+            }
+            FlowChartCommand::ZoomToSelection => {
+                self.active_chart_mut().zoom_to_selection();
+            }
+            FlowChartCommand::ResetView => {
+                self.active_chart_mut().reset_view();
+            }
+            FlowChartCommand::AddPreset(index) => {
+                if let Some(preset) = self.presets.get(index).cloned() {
+                    preset.instantiate(self.active_chart_mut());
+                }
+            }
+            FlowChartCommand::AddToolboxEntry(index) => {
+                if let Some(entry) = registry().entries().get(index) {
+                    let (node_type, command) = entry.make();
+                    self.active_chart_mut().add_node_of_type(node_type, &command);
+                }
+            }
+            FlowChartCommand::FocusNode(id) => {
+                self.active_chart_mut().focus_node(id);
+            }
+            FlowChartCommand::OpenRecentFlow(path) => {
+                let previous = std::mem::replace(&mut self.flow_path, path);
+                if let Err(e) = self.open_flow() {
+                    // Keep saving to the flow that is still open.
+                    self.flow_path = previous;
+                    self.pipeline_error = Some(e);
+                }
+            }
+            FlowChartCommand::OpenCommandPalette => {
+                self.palette = Some(Palette::default());
+            }
+            FlowChartCommand::ShowShortcuts => {
+                self.show_shortcuts = true;
+            }
+            FlowChartCommand::ToggleMinimap => {
+                self.show_minimap = !self.show_minimap;
+            }
+            FlowChartCommand::ToggleGrid => {
+                self.canvas.show_grid = !self.canvas.show_grid;
+            }
+            FlowChartCommand::ToggleSnapToGrid => {
+                self.canvas.snap_to_grid = !self.canvas.snap_to_grid;
             }
         }
     }
-} 
\ No newline at end of file
+
+    /// Path from the main flow to the subflow shown in the active tab; empty
+    /// for the main flow.
+    pub fn active_path(&self) -> Vec<usize> {
+        self.active_tab
+            .and_then(|tab| self.open_subflows.get(tab))
+            .filter(|path| self.flowchart.chart_at_path(path).is_some())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// The chart shown in the active tab, which editing commands act on.
+    pub fn active_chart_mut(&mut self) -> &mut FlowChart {
+        let path = self.active_path();
+        self.flowchart
+            .chart_at_path_mut(&path)
+            .expect("the active path was just resolved")
+    }
+
+    /// Path from the main flow to the node selected in the active tab.
+    fn selected_path(&mut self) -> Option<Vec<usize>> {
+        let selected_id = self.active_chart_mut().selected_node?;
+        let mut path = self.active_path();
+        path.push(selected_id);
+        Some(path)
+    }
+
+    /// Closes the tabs of subflows that no longer exist.
+    pub fn close_stale_tabs(&mut self) {
+        let active = self.active_tab.and_then(|tab| self.open_subflows.get(tab)).cloned();
+        self.open_subflows
+            .retain(|path| self.flowchart.chart_at_path(path).is_some());
+        self.active_tab = active.and_then(|path| self.open_subflows.iter().position(|open| *open == path));
+    }
+
+    /// Re-reads the subflow library file.
+    pub fn reload_library(&mut self) {
+        match subflow::load_library(Path::new(&self.library_path)) {
+            Ok(library) => self.library = library,
+            Err(e) => self.pipeline_error = Some(e),
+        }
+    }
+
+    /// Re-reads the preset file.
+    pub fn reload_presets(&mut self) {
+        match presets::load_presets(Path::new(&self.presets_path)) {
+            Ok(presets) => {
+                self.presets = presets;
+                self.presets_loaded = true;
+            }
+            Err(e) => {
+                self.presets_loaded = false;
+                self.pipeline_error = Some(e);
+            }
+        }
+    }
+
+    /// Re-reads the keymap file. A broken file leaves the shortcuts as they were.
+    pub fn reload_keymap(&mut self) {
+        match keymap::load_keymap(Path::new(&self.keymap_path)) {
+            Ok(keymap) => self.keymap = keymap,
+            Err(e) => self.pipeline_error = Some(e),
+        }
+    }
+
+    /// Adds a preset to the toolbox, replacing any preset with the same
+    /// name, and writes the preset file.
+    pub fn save_preset(&mut self, preset: Preset) {
+        match self.presets.iter_mut().find(|entry| entry.name == preset.name) {
+            Some(entry) => *entry = preset,
+            None => self.presets.push(preset),
+        }
+        self.write_presets();
+    }
+
+    pub fn remove_preset(&mut self, index: usize) {
+        self.presets.remove(index);
+        self.write_presets();
+    }
+
+    fn write_presets(&mut self) {
+        if !self.presets_loaded {
+            self.pipeline_error = Some(format!(
+                "Not writing {}: it was not loaded. Fix the file and reload it first.",
+                self.presets_path
+            ));
+            return;
+        }
+        if let Err(e) = presets::save_presets(Path::new(&self.presets_path), &self.presets) {
+            self.pipeline_error = Some(e);
+        }
+    }
+
+    /// Replaces the main flow with the one saved at `flow_path`.
+    fn open_flow(&mut self) -> Result<(), String> {
+        if self.flowchart.is_running() {
+            return Err("Wait for the run to finish before opening a flow.".into());
+        }
+        let mut chart = FlowChart::load_from_file(Path::new(&self.flow_path))?;
+        chart.max_parallelism = self.flowchart.max_parallelism;
+        self.flowchart = chart;
+        self.open_subflows.clear();
+        self.active_tab = None;
+        self.remember_flow();
+        Ok(())
+    }
+
+    /// Puts the flow file at the top of the recent flow files. Paths are
+    /// stored absolute, so the same file is listed once however it was
+    /// typed and still opens from another working directory.
+    fn remember_flow(&mut self) {
+        let flow = std::fs::canonicalize(&self.flow_path)
+            .map_or_else(|_| self.flow_path.clone(), |path| path.display().to_string());
+        let path = palette::default_recent_path();
+        if let Err(e) = palette::remember_recent(&path, &mut self.recent_flows, &flow) {
+            self.pipeline_error = Some(e);
+        }
+    }
+
+    /// Starts running (part of) the flow in the background.
+    fn run_pipeline(&mut self, scope: RunScope, force: bool) {
+        if let Err(e) = self.flowchart.start_run(&scope, force) {
+            self.pipeline_error = Some(e);
+        }
+    }
+
+    /// Picks up progress from the run in flight and stores its final output
+    /// or error once it completes.
+    pub fn poll_pipeline(&mut self) {
+        match self.flowchart.poll_run() {
+            Some(Ok(output)) => {
+                self.pipeline_output = output;
+                self.pipeline_error = None;
+            }
+            Some(Err(e)) => self.pipeline_error = Some(e),
+            None => {}
+        }
+    }
+}
@@ -1,12 +1,187 @@
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum FlowChartCommand {
     AddNode,
     RunPipeline,
+    /// Re-run every node, ignoring cached outputs.
+    ForceRunPipeline,
+    /// Re-run the selected node and its descendants, ignoring cached outputs.
+    ForceRerunSelectedNode,
+    /// Run the selected node and everything upstream of it.
+    RunUpToSelected,
+    /// Run the selected node and everything downstream of it, reusing upstream outputs.
+    RunFromSelected,
+    /// Run only the selected node, fed with its test input.
+    RunSelectedWithTestInput,
     DeleteSelectedNode,
+    /// Replace the selected nodes with a subflow node holding them.
+    CollapseSelection,
+    /// Open the selected subflow node in its own canvas tab.
+    OpenSelectedSubflow,
+    /// Add the selected subflow to the library file.
+    SaveSelectedSubflowToLibrary,
+    /// Start saving the selected node to the toolbox as a preset.
+    SaveSelectedNodeAsPreset,
+    /// Write the main flow to the flow file.
+    SaveFlow,
+    /// Replace the main flow with the one in the flow file.
+    OpenFlow,
     PanLeft,
     PanRight,
     PanUp,
     PanDown,
     ZoomIn,
     ZoomOut,
-} 
\ No newline at end of file
+    /// Zoom and pan so every node is in view.
+    ZoomToFit,
+    /// Zoom and pan so the selected nodes are in view.
+    ZoomToSelection,
+    /// Back to 100% zoom with the world origin in the corner.
+    ResetView,
+    ToggleMinimap,
+    ToggleGrid,
+    /// Turn snapping dragged nodes to the grid on or off.
+    ToggleSnapToGrid,
+    /// Add a node made from the toolbox preset at this index.
+    AddPreset(usize),
+    /// Add a node from the registry's toolbox entry at this index.
+    AddToolboxEntry(usize),
+    /// Select the node with this ID in the active tab and center it.
+    FocusNode(usize),
+    /// Open this flow file and make it the flow file.
+    OpenRecentFlow(String),
+    OpenCommandPalette,
+    /// Show the window listing the keyboard shortcuts.
+    ShowShortcuts,
+}
+
+impl FlowChartCommand {
+    /// The commands that take no argument, as offered in the command palette.
+    pub fn actions() -> Vec<Self> {
+        vec![
+            Self::AddNode,
+            Self::RunPipeline,
+            Self::ForceRunPipeline,
+            Self::ForceRerunSelectedNode,
+            Self::RunUpToSelected,
+            Self::RunFromSelected,
+            Self::RunSelectedWithTestInput,
+            Self::DeleteSelectedNode,
+            Self::CollapseSelection,
+            Self::OpenSelectedSubflow,
+            Self::SaveSelectedSubflowToLibrary,
+            Self::SaveSelectedNodeAsPreset,
+            Self::SaveFlow,
+            Self::OpenFlow,
+            Self::PanLeft,
+            Self::PanRight,
+            Self::PanUp,
+            Self::PanDown,
+            Self::ZoomIn,
+            Self::ZoomOut,
+            Self::ZoomToFit,
+            Self::ZoomToSelection,
+            Self::ResetView,
+            Self::ToggleMinimap,
+            Self::ToggleGrid,
+            Self::ToggleSnapToGrid,
+            Self::OpenCommandPalette,
+            Self::ShowShortcuts,
+        ]
+    }
+
+    /// Finds a command from `actions` by its ID.
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::actions().into_iter().find(|command| command.id() == id)
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Self::AddNode => "Add node".into(),
+            Self::RunPipeline => "Run pipeline".into(),
+            Self::ForceRunPipeline => "Force run pipeline (ignore cache)".into(),
+            Self::ForceRerunSelectedNode => "Force re-run selected node".into(),
+            Self::RunUpToSelected => "Run up to selected node".into(),
+            Self::RunFromSelected => "Run from selected node".into(),
+            Self::RunSelectedWithTestInput => "Run selected node with test input".into(),
+            Self::DeleteSelectedNode => "Delete selected node".into(),
+            Self::CollapseSelection => "Collapse selection into subflow".into(),
+            Self::OpenSelectedSubflow => "Open selected subflow".into(),
+            Self::SaveSelectedSubflowToLibrary => "Save selected subflow to library".into(),
+            Self::SaveSelectedNodeAsPreset => "Save selected node as preset".into(),
+            Self::SaveFlow => "Save flow".into(),
+            Self::OpenFlow => "Open flow".into(),
+            Self::PanLeft => "Pan left".into(),
+            Self::PanRight => "Pan right".into(),
+            Self::PanUp => "Pan up".into(),
+            Self::PanDown => "Pan down".into(),
+            Self::ZoomIn => "Zoom in".into(),
+            Self::ZoomOut => "Zoom out".into(),
+            Self::ZoomToFit => "Zoom to fit all".into(),
+            Self::ZoomToSelection => "Zoom to selection".into(),
+            Self::ResetView => "Reset view".into(),
+            Self::ToggleMinimap => "Show or hide minimap".into(),
+            Self::ToggleGrid => "Show or hide grid".into(),
+            Self::ToggleSnapToGrid => "Snap to grid on/off".into(),
+            Self::AddPreset(index) => format!("Add preset #{}", index),
+            Self::AddToolboxEntry(index) => format!("Add toolbox entry #{}", index),
+            Self::FocusNode(id) => format!("Go to node {}", id),
+            Self::OpenRecentFlow(path) => format!("Open {}", path),
+            Self::OpenCommandPalette => "Command palette".into(),
+            Self::ShowShortcuts => "Keyboard shortcuts".into(),
+        }
+    }
+
+    /// Name of the command in the keymap file.
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::AddNode => "add_node",
+            Self::RunPipeline => "run_pipeline",
+            Self::ForceRunPipeline => "force_run_pipeline",
+            Self::ForceRerunSelectedNode => "force_rerun_selected_node",
+            Self::RunUpToSelected => "run_up_to_selected",
+            Self::RunFromSelected => "run_from_selected",
+            Self::RunSelectedWithTestInput => "run_selected_with_test_input",
+            Self::DeleteSelectedNode => "delete_selected_node",
+            Self::CollapseSelection => "collapse_selection",
+            Self::OpenSelectedSubflow => "open_selected_subflow",
+            Self::SaveSelectedSubflowToLibrary => "save_selected_subflow_to_library",
+            Self::SaveSelectedNodeAsPreset => "save_selected_node_as_preset",
+            Self::SaveFlow => "save_flow",
+            Self::OpenFlow => "open_flow",
+            Self::PanLeft => "pan_left",
+            Self::PanRight => "pan_right",
+            Self::PanUp => "pan_up",
+            Self::PanDown => "pan_down",
+            Self::ZoomIn => "zoom_in",
+            Self::ZoomOut => "zoom_out",
+            Self::ZoomToFit => "zoom_to_fit",
+            Self::ZoomToSelection => "zoom_to_selection",
+            Self::ResetView => "reset_view",
+            Self::ToggleMinimap => "toggle_minimap",
+            Self::ToggleGrid => "toggle_grid",
+            Self::ToggleSnapToGrid => "toggle_snap_to_grid",
+            Self::AddPreset(_) => "add_preset",
+            Self::AddToolboxEntry(_) => "add_toolbox_entry",
+            Self::FocusNode(_) => "focus_node",
+            Self::OpenRecentFlow(_) => "open_recent_flow",
+            Self::OpenCommandPalette => "open_command_palette",
+            Self::ShowShortcuts => "show_shortcuts",
+        }
+    }
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn every_action_is_found_by_its_own_unique_id() {
+        let actions = FlowChartCommand::actions();
+        let ids: HashSet<&str> = actions.iter().map(FlowChartCommand::id).collect();
+        assert_eq!(ids.len(), actions.len());
+        for command in &actions {
+            assert_eq!(FlowChartCommand::from_id(command.id()).map(|found| found.id()), Some(command.id()));
+        }
+        assert!(FlowChartCommand::from_id("focus_node").is_none());
+    }
+}
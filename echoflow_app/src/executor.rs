@@ -0,0 +1,1058 @@
+use crate::io_nodes::{Sink, Source};
+use crate::json::JsonSettings;
+use crate::map::{self, MapSettings};
+use crate::registry::PluginNode;
+use crate::script::ScriptSettings;
+use crate::subflow::Subflow;
+use crate::table::TableSettings;
+use crate::transform::Transform;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Everything the executor needs to know about one node, detached from the UI
+/// so it can be run on a worker thread.
+#[derive(Debug, Clone)]
+pub struct NodeJob {
+    pub id: usize,
+    /// How the node is referred to in error messages.
+    pub label: String,
+    pub node_type: NodeType,
+    pub command: String,
+    /// Upstream node IDs in connection order; fan-in inputs are always
+    /// combined in this order, whichever upstream finishes first.
+    pub upstream: Vec<usize>,
+    pub merge_mode: MergeMode,
+    /// Nodes that must succeed before this one runs (on-success control edges).
+    pub after_success: Vec<usize>,
+    /// Nodes that must fail before this one runs (on-failure control edges).
+    pub after_failure: Vec<usize>,
+    /// Exit codes that count as success.
+    pub success_codes: Vec<i32>,
+    pub retry: RetryPolicy,
+    pub process: ProcessSettings,
+    /// Nodes outside the run scope are not executed; they only provide
+    /// their previous output to the nodes below them.
+    pub in_scope: bool,
+    pub previous_key: Option<u64>,
+    pub previous_output: Arc<Vec<u8>>,
+    /// Replaces the upstream input (used for isolated test runs). Results
+    /// produced from a fixed input are never cached.
+    pub fixed_input: Option<Vec<u8>>,
+}
+
+/// A snapshot of the flow to execute, with jobs in dependency order.
+#[derive(Debug)]
+pub struct RunPlan {
+    pub jobs: Vec<NodeJob>,
+    pub force: bool,
+    /// Maximum number of commands running at the same time.
+    pub max_parallelism: usize,
+}
+
+/// Progress reported by a running plan.
+#[derive(Debug)]
+pub enum RunEvent {
+    Started(usize),
+    /// An attempt failed and the node is about to be retried.
+    Retrying(usize, Attempt),
+    Finished(usize, NodeResult),
+    /// The run is over; carries the combined output of the terminal nodes.
+    Done(Result<Vec<u8>, String>),
+}
+
+#[derive(Debug)]
+pub enum NodeResult {
+    /// The final, successful attempt.
+    Ran {
+        attempt: Attempt,
+        cache_key: Option<u64>,
+    },
+    /// The previous output was reused because the key did not change.
+    Cached,
+    /// The command could not be started or exited with a code not listed
+    /// as success, and the retry policy gave up.
+    Failed { attempt: Attempt, error: String },
+    /// Not run because an upstream node did not end the way its edge required.
+    Skipped,
+}
+
+/// One execution of a node's command: what it wrote and how it exited.
+#[derive(Debug, Clone, Default)]
+pub struct Attempt {
+    /// `None` if the command could not be started or was killed by a signal.
+    pub exit_code: Option<i32>,
+    pub stdout: Vec<u8>,
+    /// When each line of `stdout` was written, for commands whose output an
+    /// interleaving node reads; empty otherwise.
+    pub line_times: Vec<Instant>,
+    pub stderr: Vec<u8>,
+    pub duration: Duration,
+    pub succeeded: bool,
+}
+
+/// Environment and working directory for the commands a node runs.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProcessSettings {
+    /// Variables set on top of echoflow's own environment, in the order
+    /// they are edited. Saved as a JSON object.
+    #[serde(with = "env_map")]
+    pub env: Vec<(String, String)>,
+    /// Directory the commands run in; empty for echoflow's own.
+    pub cwd: String,
+}
+
+mod env_map {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::collections::BTreeMap;
+
+    pub fn serialize<S: Serializer>(env: &[(String, String)], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(env.iter().map(|(key, value)| (key, value)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<(String, String)>, D::Error> {
+        Ok(BTreeMap::<String, String>::deserialize(deserializer)?.into_iter().collect())
+    }
+}
+
+/// When and how often a failed node is run again.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one.
+    pub max_attempts: u32,
+    pub backoff: Backoff,
+    /// Wait before the first retry.
+    pub delay: Duration,
+    /// Exit codes worth retrying; empty means any failure.
+    pub retry_on: Vec<i32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Backoff {
+    /// Wait the same delay before every retry.
+    Fixed,
+    /// Double the delay after every retry.
+    Exponential,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 1,
+            backoff: Backoff::Fixed,
+            delay: Duration::from_secs(1),
+            retry_on: Vec::new(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    fn should_retry(&self, exit_code: Option<i32>) -> bool {
+        self.retry_on.is_empty() || exit_code.is_some_and(|code| self.retry_on.contains(&code))
+    }
+
+    /// Wait before retry number `retry` (1 for the first retry). Exponential
+    /// backoff stops doubling after 16 retries.
+    fn delay_before(&self, retry: u32) -> Duration {
+        match self.backoff {
+            Backoff::Fixed => self.delay,
+            Backoff::Exponential => self.delay.saturating_mul(1 << retry.saturating_sub(1).min(16)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Succeeded,
+    Failed,
+    Skipped,
+}
+
+/// What a node does with its command.
+#[derive(Debug, Clone, Hash, Default, Serialize, Deserialize)]
+pub enum NodeType {
+    /// Runs the command once over the whole input.
+    #[default]
+    Command,
+    /// Splits the input into records and runs the command once per record.
+    Map(MapSettings),
+    /// A nested flow, expanded into its inner nodes when the flow runs.
+    Subflow(Box<Subflow>),
+    /// Reads data from outside the flow instead of running a command.
+    Source(Source),
+    /// Writes its input outside the flow and passes it on.
+    Sink(Sink),
+    /// A built-in text transform, run in-process without spawning a command.
+    Transform(Transform),
+    /// Parses JSON or JSON Lines and selects, filters or reshapes the values.
+    Json(JsonSettings),
+    /// Reads CSV, TSV or aligned columns and selects, filters, sorts or
+    /// counts the rows.
+    Table(TableSettings),
+    /// Runs a Rhai script over the input, per line or once over all of it.
+    Script(ScriptSettings),
+    /// A node kind from the registry, compiled in through a cargo feature.
+    Plugin(PluginNode),
+}
+
+impl NodeType {
+    pub fn label(&self) -> &'static str {
+        match self {
+            NodeType::Command => "Command",
+            NodeType::Map(_) => "Map over records",
+            NodeType::Subflow(_) => "Subflow",
+            NodeType::Source(_) => "Source",
+            NodeType::Sink(_) => "Sink",
+            NodeType::Transform(_) => "Built-in transform",
+            NodeType::Json(_) => "JSON",
+            NodeType::Table(_) => "Table",
+            NodeType::Script(_) => "Script",
+            NodeType::Plugin(node) => node.kind().map_or("Missing node kind", |kind| kind.name()),
+        }
+    }
+
+    /// Checks, before a run starts, that the node's settings are usable and
+    /// the files it reads or writes are where they should be.
+    pub fn check(&self) -> Result<(), String> {
+        match self {
+            NodeType::Map(settings) => settings.check(),
+            NodeType::Source(source) => source.check(),
+            NodeType::Sink(sink) => sink.check(),
+            NodeType::Transform(transform) => transform.check(),
+            NodeType::Json(settings) => settings.check(),
+            NodeType::Table(settings) => settings.check(),
+            NodeType::Script(settings) => settings.check(),
+            NodeType::Plugin(node) => node.kind()?.check(&node.config),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether an unchanged key means the output can be reused. Sources
+    /// read data that may change behind our back, and sinks must write
+    /// every time.
+    fn is_cacheable(&self) -> bool {
+        match self {
+            NodeType::Source(_) | NodeType::Sink(_) => false,
+            NodeType::Plugin(node) => node.is_cacheable(),
+            _ => true,
+        }
+    }
+}
+
+/// How a node combines the outputs of several upstream nodes into its input.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum MergeMode {
+    /// Inputs back to back, in edge order (`cat a b`).
+    #[default]
+    Concat,
+    /// Lines of all inputs in the order their commands wrote them, like
+    /// `(a & b) | cat` without torn lines. Lines of the same moment, and of
+    /// nodes that did not run a command, keep edge order.
+    Interleave,
+    /// One line from each input in turn, in edge order (`paste -d '\n' a b`).
+    /// Unlike `Interleave`, the result is the same on every run, whichever
+    /// node writes first.
+    RoundRobin,
+    /// Line N of every input joined into one line (`paste -d SEP a b`).
+    Zip { separator: String },
+    /// Inputs that are each sorted, merged into one sorted stream (`sort -m a b`).
+    SortMerge,
+}
+
+impl MergeMode {
+    /// One of each mode, with default settings, for pickers.
+    pub fn choices() -> [MergeMode; 5] {
+        [
+            MergeMode::Concat,
+            MergeMode::Interleave,
+            MergeMode::RoundRobin,
+            MergeMode::Zip {
+                separator: "\t".into(),
+            },
+            MergeMode::SortMerge,
+        ]
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            MergeMode::Concat => "Concatenate",
+            MergeMode::Interleave => "Interleave lines as they arrive",
+            MergeMode::RoundRobin => "Round-robin lines",
+            MergeMode::Zip { .. } => "Zip lines",
+            MergeMode::SortMerge => "Sorted merge",
+        }
+    }
+
+    /// Combines the inputs according to this mode. `times` holds when each
+    /// line of each input arrived, for `Interleave`; lines without a time
+    /// count as having arrived first. A single input is passed through
+    /// untouched whatever the mode.
+    pub fn merge(&self, inputs: &[&[u8]], times: &[&[Instant]]) -> Vec<u8> {
+        if let [single] = inputs {
+            return single.to_vec();
+        }
+        match self {
+            MergeMode::Concat => inputs.concat(),
+            MergeMode::Interleave => {
+                let mut lines: Vec<(Option<Instant>, &[u8])> = Vec::new();
+                for (index, input) in inputs.iter().enumerate() {
+                    let input_times = times.get(index).copied().unwrap_or_default();
+                    for (line_index, line) in split_lines(input).into_iter().enumerate() {
+                        lines.push((input_times.get(line_index).copied(), line));
+                    }
+                }
+                // The sort is stable, so ties keep edge order.
+                lines.sort_by_key(|(time, _)| *time);
+                let mut out = Vec::new();
+                for (_, line) in lines {
+                    out.extend_from_slice(line);
+                    out.push(b'\n');
+                }
+                out
+            }
+            MergeMode::RoundRobin => {
+                let lines: Vec<_> = inputs.iter().map(|input| split_lines(input)).collect();
+                let mut out = Vec::new();
+                let rows = lines.iter().map(Vec::len).max().unwrap_or(0);
+                for row in 0..rows {
+                    for input in &lines {
+                        if let Some(line) = input.get(row) {
+                            out.extend_from_slice(line);
+                            out.push(b'\n');
+                        }
+                    }
+                }
+                out
+            }
+            MergeMode::Zip { separator } => {
+                let lines: Vec<_> = inputs.iter().map(|input| split_lines(input)).collect();
+                let mut out = Vec::new();
+                let rows = lines.iter().map(Vec::len).max().unwrap_or(0);
+                for row in 0..rows {
+                    for (i, input) in lines.iter().enumerate() {
+                        if i > 0 {
+                            out.extend_from_slice(separator.as_bytes());
+                        }
+                        if let Some(line) = input.get(row) {
+                            out.extend_from_slice(line);
+                        }
+                    }
+                    out.push(b'\n');
+                }
+                out
+            }
+            MergeMode::SortMerge => {
+                let lines: Vec<_> = inputs.iter().map(|input| split_lines(input)).collect();
+                let mut cursors = vec![0; lines.len()];
+                let mut out = Vec::new();
+                // Repeatedly take the smallest head line; ties go to the earlier edge.
+                while let Some(next) = (0..lines.len())
+                    .filter(|&i| cursors[i] < lines[i].len())
+                    .min_by_key(|&i| lines[i][cursors[i]])
+                {
+                    out.extend_from_slice(lines[next][cursors[next]]);
+                    out.push(b'\n');
+                    cursors[next] += 1;
+                }
+                out
+            }
+        }
+    }
+}
+
+/// Splits data into lines without their terminators; a trailing newline does
+/// not produce an extra empty line.
+pub fn split_lines(data: &[u8]) -> Vec<&[u8]> {
+    let data = data.strip_suffix(b"\n").unwrap_or(data);
+    if data.is_empty() {
+        return Vec::new();
+    }
+    data.split(|&b| b == b'\n').collect()
+}
+
+impl NodeJob {
+    /// Key identifying this node's output: its settings plus everything fed into it.
+    fn cache_key(&self, input: Option<&[u8]>) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash_settings(&mut hasher);
+        input.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Feeds the settings that decide the job's output to `state`.
+    pub fn hash_settings<H: Hasher>(&self, state: &mut H) {
+        self.node_type.hash(state);
+        self.command.hash(state);
+        self.merge_mode.hash(state);
+        self.success_codes.hash(state);
+        self.process.hash(state);
+    }
+
+    /// How many of the `free` command slots the job takes while it runs:
+    /// map nodes take one per record they process at the same time.
+    fn slots(&self, free: usize) -> usize {
+        match &self.node_type {
+            NodeType::Map(settings) => settings.parallelism.clamp(1, free.max(1)),
+            _ => 1,
+        }
+    }
+}
+
+/// Starts executing the plan on a background thread and returns the stream
+/// of progress events. The last event is always `RunEvent::Done`.
+pub fn spawn_run(plan: RunPlan) -> Receiver<RunEvent> {
+    let (events, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let result = schedule(&plan, &events);
+        let _ = events.send(RunEvent::Done(result));
+    });
+    receiver
+}
+
+/// Runs every in-scope job as soon as all of its upstream jobs have
+/// settled, keeping at most `max_parallelism` commands in flight, map
+/// records included. A job whose
+/// data or control edges are not satisfied is skipped, along with everything
+/// that depends on it. The run fails if any node fails without an on-failure
+/// edge to handle it.
+pub(crate) fn schedule(plan: &RunPlan, events: &Sender<RunEvent>) -> Result<Vec<u8>, String> {
+    let jobs: HashMap<usize, &NodeJob> = plan.jobs.iter().map(|job| (job.id, job)).collect();
+    let mut outputs: HashMap<usize, Arc<Vec<u8>>> = HashMap::new();
+    // Nodes outside the scope count as having succeeded with their previous output.
+    let mut outcomes: HashMap<usize, Outcome> = HashMap::new();
+    for job in plan.jobs.iter().filter(|job| !job.in_scope) {
+        outputs.insert(job.id, job.previous_output.clone());
+        outcomes.insert(job.id, Outcome::Succeeded);
+    }
+
+    let mut waiting: HashMap<usize, usize> = HashMap::new();
+    let mut dependents: HashMap<usize, Vec<usize>> = HashMap::new();
+    for job in plan.jobs.iter().filter(|job| job.in_scope) {
+        let mut count = 0;
+        if job.fixed_input.is_none() {
+            let edges = job.upstream.iter().chain(&job.after_success).chain(&job.after_failure);
+            for from in edges {
+                if jobs.get(from).is_some_and(|up| up.in_scope) {
+                    count += 1;
+                    dependents.entry(*from).or_default().push(job.id);
+                }
+            }
+        }
+        waiting.insert(job.id, count);
+    }
+    let mut ready: VecDeque<usize> = plan
+        .jobs
+        .iter()
+        .filter(|job| waiting.get(&job.id) == Some(&0))
+        .map(|job| job.id)
+        .collect();
+
+    // Outputs read by interleaving nodes have their lines timed.
+    let timed: HashSet<usize> = plan
+        .jobs
+        .iter()
+        .filter(|job| job.merge_mode == MergeMode::Interleave)
+        .flat_map(|job| job.upstream.iter().copied())
+        .collect();
+    let mut line_times: HashMap<usize, Vec<Instant>> = HashMap::new();
+
+    let (done_tx, done_rx) = mpsc::channel();
+    let mut running = 0;
+    let mut failures: Vec<String> = Vec::new();
+    let max_parallelism = plan.max_parallelism.max(1);
+
+    loop {
+        while running < max_parallelism {
+            let Some(id) = ready.pop_front() else {
+                break;
+            };
+            let job = jobs[&id];
+            if job.fixed_input.is_none() && !gates_open(job, &outcomes) {
+                outcomes.insert(id, Outcome::Skipped);
+                let _ = events.send(RunEvent::Finished(id, NodeResult::Skipped));
+                release_dependents(id, &dependents, &mut waiting, &mut ready);
+                continue;
+            }
+            let input = match &job.fixed_input {
+                Some(input) => Some(input.clone()),
+                None => gather_input(job, &outputs, &line_times),
+            };
+            let key = match job.fixed_input {
+                Some(_) => None,
+                None if !job.node_type.is_cacheable() => None,
+                None => Some(job.cache_key(input.as_deref())),
+            };
+            if !plan.force && key.is_some() && key == job.previous_key {
+                outputs.insert(id, job.previous_output.clone());
+                outcomes.insert(id, Outcome::Succeeded);
+                let _ = events.send(RunEvent::Finished(id, NodeResult::Cached));
+                release_dependents(id, &dependents, &mut waiting, &mut ready);
+                continue;
+            }
+
+            let _ = events.send(RunEvent::Started(id));
+            let slots = job.slots(max_parallelism - running);
+            running += slots;
+            let time_lines = timed.contains(&id);
+            let job = job.clone();
+            let events = events.clone();
+            let done_tx = done_tx.clone();
+            thread::spawn(move || {
+                let (attempt, count) = run_with_retries(&job, input.as_deref(), slots, time_lines, |failed| {
+                    let _ = events.send(RunEvent::Retrying(id, failed));
+                });
+                let _ = done_tx.send((id, key, attempt, count, slots));
+            });
+        }
+
+        if running == 0 {
+            break;
+        }
+        let Ok((id, key, attempt, count, slots)) = done_rx.recv() else {
+            break;
+        };
+        running -= slots;
+        let result = if attempt.succeeded {
+            if timed.contains(&id) {
+                // Output that was not read line by line arrives all at once.
+                let times = if attempt.line_times.is_empty() {
+                    vec![Instant::now(); split_lines(&attempt.stdout).len()]
+                } else {
+                    attempt.line_times.clone()
+                };
+                line_times.insert(id, times);
+            }
+            outputs.insert(id, Arc::new(attempt.stdout.clone()));
+            outcomes.insert(id, Outcome::Succeeded);
+            NodeResult::Ran {
+                attempt,
+                cache_key: key,
+            }
+        } else {
+            let command = &jobs[&id].command;
+            let stderr = String::from_utf8_lossy(&attempt.stderr).trim_end().to_string();
+            let mut error = if !stderr.is_empty() {
+                stderr
+            } else if let Some(code) = attempt.exit_code {
+                format!("'{}' exited with code {}", command, code)
+            } else {
+                format!("'{}' was terminated by a signal", command)
+            };
+            if count > 1 {
+                error = format!("{} (after {} attempts)", error, count);
+            }
+            outcomes.insert(id, Outcome::Failed);
+            NodeResult::Failed { attempt, error }
+        };
+        if let NodeResult::Failed { error, .. } = &result {
+            let handled = plan
+                .jobs
+                .iter()
+                .any(|other| other.in_scope && other.after_failure.contains(&id));
+            if !handled {
+                failures.push(format!("{}: {}", jobs[&id].label, error));
+            }
+        }
+        let _ = events.send(RunEvent::Finished(id, result));
+        release_dependents(id, &dependents, &mut waiting, &mut ready);
+    }
+
+    if !failures.is_empty() {
+        return Err(failures.join("\n"));
+    }
+    let mut final_output = Vec::new();
+    for job in plan.jobs.iter().filter(|job| job.in_scope) {
+        if !dependents.contains_key(&job.id)
+            && outcomes.get(&job.id) == Some(&Outcome::Succeeded)
+            && let Some(output) = outputs.get(&job.id)
+        {
+            final_output.extend_from_slice(output);
+        }
+    }
+    Ok(final_output)
+}
+
+/// Whether every edge into the job is satisfied: data and on-success
+/// sources succeeded, on-failure sources failed.
+fn gates_open(job: &NodeJob, outcomes: &HashMap<usize, Outcome>) -> bool {
+    let ended = |id: &usize, wanted: Outcome| outcomes.get(id) == Some(&wanted);
+    job.upstream.iter().all(|id| ended(id, Outcome::Succeeded))
+        && job.after_success.iter().all(|id| ended(id, Outcome::Succeeded))
+        && job.after_failure.iter().all(|id| ended(id, Outcome::Failed))
+}
+
+/// Bytes fed to a job's stdin: its upstream outputs combined in connection
+/// order by the job's merge mode, or `None` for jobs without inputs.
+fn gather_input(
+    job: &NodeJob,
+    outputs: &HashMap<usize, Arc<Vec<u8>>>,
+    line_times: &HashMap<usize, Vec<Instant>>,
+) -> Option<Vec<u8>> {
+    if job.upstream.is_empty() {
+        return None;
+    }
+    let inputs: Vec<&[u8]> = job
+        .upstream
+        .iter()
+        .map(|from| outputs.get(from).map_or(&[][..], |output| output.as_slice()))
+        .collect();
+    let times: Vec<&[Instant]> = job
+        .upstream
+        .iter()
+        .map(|from| line_times.get(from).map_or(&[][..], Vec::as_slice))
+        .collect();
+    Some(job.merge_mode.merge(&inputs, &times))
+}
+
+fn release_dependents(
+    id: usize,
+    dependents: &HashMap<usize, Vec<usize>>,
+    waiting: &mut HashMap<usize, usize>,
+    ready: &mut VecDeque<usize>,
+) {
+    for dependent in dependents.get(&id).into_iter().flatten() {
+        if let Some(count) = waiting.get_mut(dependent) {
+            *count -= 1;
+            if *count == 0 {
+                ready.push_back(*dependent);
+            }
+        }
+    }
+}
+
+/// Runs the job, with `slots` commands at most at the same time, until it
+/// succeeds or its retry policy gives up. Every attempt that is going to be
+/// retried is handed to `on_retry`; the last attempt is returned with the
+/// number of attempts made. With `time_lines`, a command's output lines are
+/// timed as they arrive.
+fn run_with_retries(
+    job: &NodeJob,
+    input: Option<&[u8]>,
+    slots: usize,
+    time_lines: bool,
+    mut on_retry: impl FnMut(Attempt),
+) -> (Attempt, u32) {
+    let retry = &job.retry;
+    let mut count = 1;
+    loop {
+        let started = Instant::now();
+        let mut attempt = catch_panic(|| execute(job, input, slots, time_lines)).unwrap_or_else(|e| Attempt {
+            stderr: e.into_bytes(),
+            ..Attempt::default()
+        });
+        attempt.duration = started.elapsed();
+        attempt.succeeded = attempt
+            .exit_code
+            .is_some_and(|code| job.success_codes.contains(&code));
+        if attempt.succeeded || count >= retry.max_attempts || !retry.should_retry(attempt.exit_code)
+        {
+            return (attempt, count);
+        }
+        on_retry(attempt);
+        thread::sleep(retry.delay_before(count));
+        count += 1;
+    }
+}
+
+/// Runs `f`, turning a panic, e.g. in a plugin's code, into an error with
+/// the panic message. Otherwise the worker thread would die without
+/// reporting back and the run would wait for it forever.
+fn catch_panic<T>(f: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "no message".into());
+        Err(format!("The node panicked: {}", message))
+    })
+}
+
+/// Runs one attempt of the job according to its node type.
+fn execute(job: &NodeJob, input: Option<&[u8]>, slots: usize, time_lines: bool) -> Result<Attempt, String> {
+    match &job.node_type {
+        NodeType::Command => run_shell(&job.command, input, &job.process, &[], time_lines),
+        NodeType::Map(settings) => map::run_map(
+            settings,
+            &job.command,
+            input.unwrap_or_default(),
+            &job.success_codes,
+            &job.process,
+            slots,
+        ),
+        NodeType::Subflow(_) => Err("Subflow nodes are expanded before the flow runs.".into()),
+        NodeType::Source(source) => source.read(),
+        NodeType::Sink(sink) => sink.write(input.unwrap_or_default()),
+        NodeType::Transform(transform) => transform.apply(input.unwrap_or_default()),
+        NodeType::Json(settings) => settings.apply(input.unwrap_or_default()),
+        NodeType::Table(settings) => settings.apply(input.unwrap_or_default()),
+        NodeType::Script(settings) => settings.apply(input.unwrap_or_default()),
+        NodeType::Plugin(node) => node.execute(input),
+    }
+}
+
+/// Runs a single shell command with extra environment variables set,
+/// feeding `input` to its stdin when given, and returns its stdout bytes
+/// untouched along with its exit status.
+pub fn run_command_with_env(
+    command: &str,
+    input: Option<&[u8]>,
+    process: &ProcessSettings,
+    env: &[(&str, String)],
+) -> Result<Attempt, String> {
+    run_shell(command, input, process, env, false)
+}
+
+/// Runs a shell command; with `time_lines`, notes when each line of its
+/// output arrives.
+fn run_shell(
+    command: &str,
+    input: Option<&[u8]>,
+    process: &ProcessSettings,
+    env: &[(&str, String)],
+    time_lines: bool,
+) -> Result<Attempt, String> {
+    let mut shell = Command::new("sh");
+    if !process.cwd.is_empty() {
+        if !Path::new(&process.cwd).is_dir() {
+            return Err(format!("Working directory '{}' does not exist.", process.cwd));
+        }
+        shell.current_dir(&process.cwd);
+    }
+    let mut child = shell
+        .arg("-c")
+        .arg(command)
+        .envs(process.env.iter().filter(|(key, _)| !key.is_empty()).map(|(key, value)| (key, value)))
+        .envs(env.iter().map(|(key, value)| (key, value)))
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run command '{}': {}", command, e))?;
+
+    // Feed stdin from a separate thread so a child that fills its stdout pipe
+    // before draining stdin (e.g. `gzip` on large input) cannot deadlock us.
+    let writer = match (input, child.stdin.take()) {
+        (Some(data), Some(mut stdin)) => {
+            let data = data.to_vec();
+            Some(thread::spawn(move || stdin.write_all(&data)))
+        }
+        _ => None,
+    };
+    let timed_reader = if time_lines {
+        child.stdout.take().map(|stdout| thread::spawn(move || read_timed_lines(stdout)))
+    } else {
+        None
+    };
+
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("Error waiting on command '{}': {}", command, e))?;
+    let (stdout, line_times) = match timed_reader {
+        Some(reader) => reader
+            .join()
+            .unwrap_or_else(|_| Err(std::io::Error::other("the reader panicked")))
+            .map_err(|e| format!("Failed to read the output of '{}': {}", command, e))?,
+        None => (output.stdout, Vec::new()),
+    };
+    if let Some(writer) = writer {
+        // A broken pipe just means the command stopped reading early (e.g. `head`).
+        match writer.join() {
+            Ok(Err(e)) if e.kind() != std::io::ErrorKind::BrokenPipe => {
+                return Err(format!("Failed to write to stdin: {}", e));
+            }
+            _ => {}
+        }
+    }
+    Ok(Attempt {
+        exit_code: output.status.code(),
+        stdout,
+        line_times,
+        stderr: output.stderr,
+        ..Attempt::default()
+    })
+}
+
+/// Reads a command's stdout to the end, noting when each line came in.
+fn read_timed_lines(stdout: impl Read) -> std::io::Result<(Vec<u8>, Vec<Instant>)> {
+    let mut reader = BufReader::new(stdout);
+    let mut data = Vec::new();
+    let mut times = Vec::new();
+    while reader.read_until(b'\n', &mut data)? > 0 {
+        times.push(Instant::now());
+    }
+    Ok((data, times))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use echoflow_node_api::{Config, ConfigField, NodeKind, Output};
+    use std::collections::BTreeMap;
+
+    /// A node kind whose code panics, registered in test builds only.
+    pub struct Panics;
+
+    impl NodeKind for Panics {
+        fn id(&self) -> &'static str {
+            "test.panics"
+        }
+
+        fn name(&self) -> &'static str {
+            "Panics"
+        }
+
+        fn config_schema(&self) -> Vec<ConfigField> {
+            Vec::new()
+        }
+
+        fn execute(&self, _config: &Config, _input: &[u8]) -> Result<Output, String> {
+            panic!("plugin bug");
+        }
+    }
+
+    fn job(id: usize, node_type: NodeType, command: &str) -> NodeJob {
+        NodeJob {
+            id,
+            label: format!("Node {}", id),
+            node_type,
+            command: command.into(),
+            upstream: Vec::new(),
+            merge_mode: MergeMode::default(),
+            after_success: Vec::new(),
+            after_failure: Vec::new(),
+            success_codes: vec![0],
+            retry: RetryPolicy::default(),
+            process: ProcessSettings::default(),
+            in_scope: true,
+            previous_key: None,
+            previous_output: Arc::default(),
+            fixed_input: None,
+        }
+    }
+
+    fn run(jobs: Vec<NodeJob>) -> Result<Vec<u8>, String> {
+        run_plan(jobs, true).0
+    }
+
+    /// Runs the jobs, returning the result and every event before `Done`.
+    fn run_plan(jobs: Vec<NodeJob>, force: bool) -> (Result<Vec<u8>, String>, Vec<RunEvent>) {
+        let events = spawn_run(RunPlan { jobs, force, max_parallelism: 2 });
+        let mut seen = Vec::new();
+        loop {
+            match events.recv().expect("the run reports that it is done") {
+                RunEvent::Done(result) => return (result, seen),
+                event => seen.push(event),
+            }
+        }
+    }
+
+    /// How each node finished, by id: "ran", "cached", "failed" or "skipped",
+    /// and the cache keys of the nodes that ran.
+    fn results(events: Vec<RunEvent>) -> (BTreeMap<usize, &'static str>, HashMap<usize, u64>) {
+        let mut results = BTreeMap::new();
+        let mut keys = HashMap::new();
+        for event in events {
+            if let RunEvent::Finished(id, result) = event {
+                let name = match result {
+                    NodeResult::Ran { cache_key, .. } => {
+                        keys.extend(cache_key.map(|key| (id, key)));
+                        "ran"
+                    }
+                    NodeResult::Cached => "cached",
+                    NodeResult::Failed { .. } => "failed",
+                    NodeResult::Skipped => "skipped",
+                };
+                results.insert(id, name);
+            }
+        }
+        (results, keys)
+    }
+
+    fn chain(commands: &[&str]) -> Vec<NodeJob> {
+        commands
+            .iter()
+            .enumerate()
+            .map(|(id, command)| {
+                let mut job = job(id, NodeType::Command, command);
+                job.upstream = id.checked_sub(1).into_iter().collect();
+                job
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reruns_only_nodes_whose_settings_or_input_changed() {
+        let (output, events) = run_plan(chain(&["echo a", "tr a b", "cat"]), false);
+        assert_eq!(output.unwrap(), b"b\n");
+        let (ran, keys) = results(events);
+        assert_eq!(ran.values().collect::<Vec<_>>(), [&"ran"; 3]);
+
+        let previous = |mut jobs: Vec<NodeJob>| {
+            for job in &mut jobs {
+                job.previous_key = keys.get(&job.id).copied();
+                job.previous_output = Arc::new(if job.id == 0 { b"a\n".to_vec() } else { b"b\n".to_vec() });
+            }
+            jobs
+        };
+        let (output, events) = run_plan(previous(chain(&["echo a", "tr a b", "cat"])), false);
+        assert_eq!(output.unwrap(), b"b\n");
+        assert_eq!(results(events).0.values().collect::<Vec<_>>(), [&"cached"; 3]);
+
+        // The middle node changes but writes the same output, so the last
+        // node's input, and with it its key, stays the same.
+        let (_, events) = run_plan(previous(chain(&["echo a", "tr a-a b", "cat"])), false);
+        assert_eq!(results(events).0.values().collect::<Vec<_>>(), [&"cached", &"ran", &"cached"]);
+
+        let (_, events) = run_plan(previous(chain(&["echo a", "tr a b", "cat"])), true);
+        assert_eq!(results(events).0.values().collect::<Vec<_>>(), [&"ran"; 3]);
+    }
+
+    fn retry(backoff: Backoff, delay: Duration, retry_on: Vec<i32>) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            backoff,
+            delay,
+            retry_on,
+        }
+    }
+
+    #[test]
+    fn exponential_backoff_doubles_up_to_a_cap() {
+        let second = Duration::from_secs(1);
+        let fixed = retry(Backoff::Fixed, second, Vec::new());
+        assert_eq!(fixed.delay_before(1), second);
+        assert_eq!(fixed.delay_before(40), second);
+        let exponential = retry(Backoff::Exponential, second, Vec::new());
+        assert_eq!(exponential.delay_before(0), second);
+        assert_eq!(exponential.delay_before(1), second);
+        assert_eq!(exponential.delay_before(4), second * 8);
+        assert_eq!(exponential.delay_before(17), second * 65536);
+        assert_eq!(exponential.delay_before(u32::MAX), second * 65536);
+        let huge = retry(Backoff::Exponential, Duration::MAX, Vec::new());
+        assert_eq!(huge.delay_before(3), Duration::MAX);
+    }
+
+    #[test]
+    fn retries_only_listed_exit_codes() {
+        assert!(retry(Backoff::Fixed, Duration::ZERO, Vec::new()).should_retry(None));
+        let listed = retry(Backoff::Fixed, Duration::ZERO, vec![75]);
+        assert!(listed.should_retry(Some(75)));
+        assert!(!listed.should_retry(Some(1)));
+        assert!(!listed.should_retry(None));
+    }
+
+    #[test]
+    fn failed_jobs_run_until_attempts_run_out() {
+        let mut failing = job(0, NodeType::Command, "echo try >&2; exit 3");
+        failing.retry = retry(Backoff::Exponential, Duration::ZERO, vec![3]);
+        let mut retried = Vec::new();
+        let (last, count) = run_with_retries(&failing, None, 1, false, |attempt| retried.push(attempt));
+        assert_eq!(count, 3);
+        assert_eq!(retried.len(), 2);
+        assert_eq!(last.exit_code, Some(3));
+        assert!(!last.succeeded);
+
+        failing.retry.retry_on = vec![4];
+        let (_, count) = run_with_retries(&failing, None, 1, false, |_| {});
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn bytes_that_are_not_utf8_pass_through_commands_intact() {
+        let binary = job(0, NodeType::Command, r"printf '\000\377\n\200'");
+        let mut copy = job(1, NodeType::Command, "cat");
+        copy.upstream = vec![0];
+        assert_eq!(run(vec![binary, copy]).unwrap(), b"\0\xff\n\x80");
+    }
+
+    #[test]
+    fn concat_keeps_edge_order() {
+        let merged = MergeMode::Concat.merge(&[b"b\n", b"a\n", b"c"], &[]);
+        assert_eq!(merged, b"b\na\nc");
+    }
+
+    #[test]
+    fn a_single_input_passes_through_untouched() {
+        let separator = "\t".to_string();
+        assert_eq!(MergeMode::Zip { separator }.merge(&[b"x\ny"], &[]), b"x\ny");
+    }
+
+    #[test]
+    fn interleave_orders_lines_by_arrival() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let first = [at(0), at(30)];
+        let second = [at(10), at(30)];
+        let merged = MergeMode::Interleave.merge(&[b"a1\na2\n", b"b1\nb2\n"], &[&first, &second]);
+        assert_eq!(merged, b"a1\nb1\na2\nb2\n");
+    }
+
+    #[test]
+    fn interleave_puts_untimed_lines_first_in_edge_order() {
+        let late = [Instant::now()];
+        let merged = MergeMode::Interleave.merge(&[b"timed\n", b"old1\nold2\n"], &[&late]);
+        assert_eq!(merged, b"old1\nold2\ntimed\n");
+    }
+
+    #[test]
+    fn round_robin_takes_turns_until_every_input_runs_out() {
+        let merged = MergeMode::RoundRobin.merge(&[b"a1\na2\na3\n", b"b1\n", b""], &[]);
+        assert_eq!(merged, b"a1\nb1\na2\na3\n");
+    }
+
+    #[test]
+    fn zip_pads_short_inputs_and_joins_with_the_separator() {
+        let zip = MergeMode::Zip { separator: ", ".into() };
+        let merged = zip.merge(&[b"a1\na2\n", b"b1"], &[]);
+        assert_eq!(merged, b"a1, b1\na2, \n");
+    }
+
+    #[test]
+    fn sort_merge_merges_sorted_inputs_with_ties_to_the_earlier_edge() {
+        let merged = MergeMode::SortMerge.merge(&[b"a\nc\ne\n", b"b\nc\nd\n"], &[]);
+        assert_eq!(merged, b"a\nb\nc\nc\nd\ne\n");
+    }
+
+    #[test]
+    fn an_interleaving_node_reads_lines_in_the_order_commands_wrote_them() {
+        let early = job(0, NodeType::Command, "echo a1; sleep 0.4; echo a2");
+        let late = job(1, NodeType::Command, "sleep 0.2; echo b1");
+        let mut merge = job(2, NodeType::Command, "cat");
+        merge.upstream = vec![0, 1];
+        merge.merge_mode = MergeMode::Interleave;
+        assert_eq!(run(vec![early, late, merge]).unwrap(), b"a1\nb1\na2\n");
+    }
+
+    #[test]
+    fn independent_branches_run_at_the_same_time() {
+        let started = Instant::now();
+        let mut join = job(2, NodeType::Command, "cat");
+        join.upstream = vec![0, 1];
+        let first = job(0, NodeType::Command, "sleep 0.4; echo a");
+        let second = job(1, NodeType::Command, "sleep 0.4; echo b");
+        assert_eq!(run(vec![first, second, join]).unwrap(), b"a\nb\n");
+        assert!(started.elapsed() < Duration::from_millis(750), "took {:?}", started.elapsed());
+    }
+
+    #[test]
+    fn a_panicking_job_fails_the_run_with_its_message() {
+        let kind = crate::registry::registry().kind("test.panics").unwrap();
+        let panics = job(0, NodeType::Plugin(PluginNode::new(kind)), "");
+        let mut after = job(1, NodeType::Command, "cat");
+        after.upstream = vec![0];
+        let error = run(vec![panics, after]).unwrap_err();
+        assert!(error.contains("Node 0"), "{}", error);
+        assert!(error.contains("plugin bug"), "{}", error);
+    }
+}
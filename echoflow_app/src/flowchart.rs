@@ -1,40 +1,373 @@
+use crate::executor::{
+    self, Attempt, MergeMode, NodeJob, NodeResult, NodeType, ProcessSettings, RetryPolicy, RunEvent,
+    RunPlan,
+};
+use crate::preview;
+use crate::subflow::{Port, Subflow};
 use eframe::egui;
-use std::collections::HashMap;
-use std::io::Write;
-use std::process::{Command, Stdio};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::mpsc::Receiver;
+use std::time::Duration;
 
-/// A node in the flow-chart.
-#[derive(Debug)]
+/// Size of a node at 100% zoom, in world units.
+pub const NODE_SIZE: egui::Vec2 = egui::vec2(120.0, 50.0);
+/// Bounds of `FlowChart::zoom`.
+const MIN_ZOOM: f32 = 0.1;
+const MAX_ZOOM: f32 = 5.0;
+/// World-space room left around the nodes by zoom to fit.
+const FIT_MARGIN: f32 = 40.0;
+/// Spacing of the background grid and of snapped positions, in world units.
+const GRID_SIZE: f32 = 20.0;
+/// How close, in screen pixels, a dragged node must come to another one's
+/// row or column to line up with it.
+const ALIGN_DISTANCE: f32 = 8.0;
+
+/// How the canvas is drawn and edited; shared by all tabs.
+#[derive(Debug, Clone, Copy)]
+pub struct CanvasOptions {
+    pub show_grid: bool,
+    /// Round dragged node positions to the grid.
+    pub snap_to_grid: bool,
+}
+
+impl Default for CanvasOptions {
+    fn default() -> Self {
+        Self { show_grid: true, snap_to_grid: false }
+    }
+}
+
+/// A node in the flow-chart. Run results are not saved with the flow.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Node {
     pub id: usize,
     pub pos: egui::Pos2, // In world coordinates
+    pub node_type: NodeType,
     pub command: String,
-    pub output: String,  // Intermediate result after running its command
+    #[serde(skip)]
+    pub output: Vec<u8>, // Intermediate result after running its command, as raw bytes
+    /// Line count of `output`, worked out once when it is set.
+    #[serde(skip)]
+    pub output_lines: usize,
+    /// Whether `output` is UTF-8 text, worked out once when it is set.
+    #[serde(skip)]
+    pub output_is_text: bool,
+    /// Hash of the command and inputs that produced `output`; `None` when stale.
+    #[serde(skip)]
+    pub cache_key: Option<u64>,
+    #[serde(skip)]
+    pub status: NodeStatus,
+    /// Error message from the node's last failed run.
+    #[serde(skip)]
+    pub error: Option<String>,
+    /// Sample stdin used when running this node on its own.
+    pub test_input: String,
+    /// How the outputs of several upstream nodes are combined.
+    pub merge_mode: MergeMode,
+    /// Exit codes that count as success (e.g. `0, 1` for `grep` that may match nothing).
+    pub success_codes: Vec<i32>,
+    pub retry: RetryPolicy,
+    #[serde(default)]
+    pub process: ProcessSettings,
+    /// Run record: every attempt of the node's last run, oldest first.
+    #[serde(skip)]
+    pub attempts: Vec<Attempt>,
+}
+
+/// Badges on nodes are left out below this zoom, where they would not be readable.
+const BADGE_MIN_ZOOM: f32 = 0.6;
+/// How much of a node's output its tooltip previews.
+const TOOLTIP_LINES: usize = 12;
+const TOOLTIP_CHARS: usize = 1200;
+
+/// Outcome of a node's most recent run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NodeStatus {
+    #[default]
+    Idle,
+    /// Part of the current run, waiting for its inputs or a free worker.
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    /// The node was skipped because its cached output was still valid.
+    Cached,
+    /// Not run because an upstream node did not end the way its edge required.
+    Skipped,
+}
+
+impl NodeStatus {
+    pub fn label(self) -> &'static str {
+        match self {
+            NodeStatus::Idle => "idle",
+            NodeStatus::Queued => "queued",
+            NodeStatus::Running => "running",
+            NodeStatus::Succeeded => "succeeded",
+            NodeStatus::Failed => "failed",
+            NodeStatus::Cached => "cached",
+            NodeStatus::Skipped => "skipped",
+        }
+    }
+
+    /// Fill of a node in this state on the canvas.
+    pub fn color(self) -> egui::Color32 {
+        match self {
+            NodeStatus::Idle => egui::Color32::from_rgb(100, 150, 200),
+            NodeStatus::Queued => egui::Color32::from_rgb(110, 120, 140),
+            NodeStatus::Running => egui::Color32::from_rgb(200, 140, 30),
+            NodeStatus::Succeeded => egui::Color32::from_rgb(60, 140, 80),
+            NodeStatus::Failed => egui::Color32::from_rgb(180, 60, 60),
+            NodeStatus::Cached => egui::Color32::from_rgb(70, 125, 120),
+            NodeStatus::Skipped => egui::Color32::from_rgb(85, 85, 90),
+        }
+    }
+}
+
+/// Which part of the flow a run covers. Nodes are given by their path: the
+/// IDs of the subflow nodes leading to them from the main flow, then their own.
+#[derive(Debug, Clone)]
+pub enum RunScope {
+    All,
+    /// The given node and everything upstream of it.
+    UpTo(Vec<usize>),
+    /// The given node and everything downstream of it, fed by the stored
+    /// outputs of the nodes above it.
+    From(Vec<usize>),
+}
+
+impl Node {
+    /// A fresh node with default settings at the default position.
+    pub fn new(id: usize, node_type: NodeType, command: String) -> Self {
+        Self {
+            id,
+            pos: egui::pos2(50.0, 50.0),
+            node_type,
+            command,
+            output: Vec::new(),
+            output_lines: 0,
+            output_is_text: true,
+            cache_key: None,
+            status: NodeStatus::Idle,
+            error: None,
+            test_input: String::new(),
+            merge_mode: MergeMode::default(),
+            success_codes: vec![0],
+            retry: RetryPolicy::default(),
+            process: ProcessSettings::default(),
+            attempts: Vec::new(),
+        }
+    }
+
+    /// Text shown on the node in the canvas.
+    pub fn label(&self) -> String {
+        match &self.node_type {
+            NodeType::Command => self.command.clone(),
+            NodeType::Map(settings) => match &settings.body {
+                Some(body) => format!("map: {}", body.name),
+                None => format!("map: {}", self.command),
+            },
+            NodeType::Subflow(subflow) => format!("subflow: {}", subflow.name),
+            NodeType::Source(source) => source.summary(),
+            NodeType::Sink(sink) => sink.summary(),
+            NodeType::Transform(transform) => transform.summary(),
+            NodeType::Json(settings) => settings.summary(),
+            NodeType::Table(settings) => settings.summary(),
+            NodeType::Script(settings) => settings.summary(),
+            NodeType::Plugin(node) => node.summary(),
+        }
+    }
+
+    /// Number of input and output ports: those of a subflow, one each
+    /// for every other node.
+    pub fn port_counts(&self) -> (usize, usize) {
+        match &self.node_type {
+            NodeType::Subflow(subflow) => (subflow.inputs.len().max(1), subflow.outputs.len().max(1)),
+            _ => (1, 1),
+        }
+    }
+
+    /// Exit code of the last attempt of the node's last run.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.attempts.last().and_then(|attempt| attempt.exit_code)
+    }
+
+    /// Replaces the node's output, along with its line count and text flag,
+    /// so drawing the node does not rescan it every frame.
+    pub fn set_output(&mut self, output: Vec<u8>) {
+        self.output_lines = output.iter().filter(|&&byte| byte == b'\n').count();
+        if output.last().is_some_and(|&byte| byte != b'\n') {
+            self.output_lines += 1;
+        }
+        self.output_is_text = std::str::from_utf8(&output).is_ok();
+        self.output = output;
+    }
+
+    /// Line and byte count of the output, e.g. `3 lines, 42 bytes`.
+    fn output_size(&self) -> String {
+        let unit = if self.output_lines == 1 { "line" } else { "lines" };
+        format!("{} {}, {}", self.output_lines, unit, preview::format_size(self.output.len()))
+    }
+
+    /// Time spent in all attempts of the node's last run.
+    pub fn runtime(&self) -> Duration {
+        self.attempts.iter().map(|attempt| attempt.duration).sum()
+    }
+
+    /// What the node's last run did and the start of its output, for the
+    /// canvas tooltip.
+    fn show_summary(&self, ui: &mut egui::Ui) {
+        ui.strong(self.label());
+        ui.label(format!("Status: {}", self.status.label()));
+        if !self.attempts.is_empty() {
+            let exit = self.exit_code().map_or("no exit code".to_string(), |code| format!("exit code {}", code));
+            ui.label(format!("{}, {}", exit, format_runtime(self.runtime())));
+        }
+        if let Some(error) = &self.error {
+            ui.colored_label(egui::Color32::from_rgb(255, 120, 120), error);
+        }
+        if self.output.is_empty() {
+            return;
+        }
+        ui.separator();
+        ui.label(self.output_size());
+        if !self.output_is_text {
+            ui.label(format!("Binary ({})", preview::detect_mime(&self.output)));
+            return;
+        }
+        // Only the head of the output is decoded, however large it is.
+        let head = &self.output[..self.output.len().min(TOOLTIP_CHARS)];
+        let head = match std::str::from_utf8(head) {
+            Ok(text) => text,
+            // Cut in the middle of a character.
+            Err(e) => std::str::from_utf8(&head[..e.valid_up_to()]).unwrap_or_default(),
+        };
+        let mut shown: String = head.lines().take(TOOLTIP_LINES).collect::<Vec<_>>().join("\n");
+        if self.output_lines > TOOLTIP_LINES {
+            shown.push_str("\n…");
+        } else if head.len() < self.output.len() {
+            shown.push('…');
+        }
+        ui.monospace(shown);
+    }
+}
+
+/// How a node is referred to in messages, e.g. "Node 4 › 2" for node 2
+/// inside subflow node 4.
+fn path_label(path: &[usize]) -> String {
+    let ids: Vec<String> = path.iter().map(ToString::to_string).collect();
+    format!("Node {}", ids.join(" › "))
+}
+
+/// The given jobs plus every job reachable from them through data and
+/// control edges, downstream or upstream.
+fn reachable_jobs(jobs: &[NodeJob], start: Vec<usize>, downstream: bool) -> HashSet<usize> {
+    let mut seen: HashSet<usize> = start.iter().copied().collect();
+    let mut stack = start;
+    while let Some(current) = stack.pop() {
+        for job in jobs {
+            let sources = job.upstream.iter().chain(&job.after_success).chain(&job.after_failure);
+            for &source in sources {
+                let (here, there) = if downstream { (source, job.id) } else { (job.id, source) };
+                if here == current && seen.insert(there) {
+                    stack.push(there);
+                }
+            }
+        }
+    }
+    seen
 }
 
 /// A connection between two nodes.
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Connection {
     pub from: usize,
     pub to: usize,
+    pub kind: ConnectionKind,
+    /// Output port of the source, when it is a subflow.
+    #[serde(default)]
+    pub from_port: usize,
+    /// Input port of the target, when it is a subflow.
+    #[serde(default)]
+    pub to_port: usize,
+}
+
+/// What a connection carries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum ConnectionKind {
+    /// The source's output is piped into the target, which runs only if the source succeeded.
+    #[default]
+    Data,
+    /// Control only: the target runs only if the source succeeded.
+    OnSuccess,
+    /// Control only: the target runs only if the source failed.
+    OnFailure,
+}
+
+impl ConnectionKind {
+    pub const ALL: [ConnectionKind; 3] = [
+        ConnectionKind::Data,
+        ConnectionKind::OnSuccess,
+        ConnectionKind::OnFailure,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ConnectionKind::Data => "Data",
+            ConnectionKind::OnSuccess => "On success",
+            ConnectionKind::OnFailure => "On failure",
+        }
+    }
+
+    fn color(self) -> egui::Color32 {
+        match self {
+            ConnectionKind::Data => egui::Color32::LIGHT_GREEN,
+            ConnectionKind::OnSuccess => egui::Color32::from_rgb(90, 170, 255),
+            ConnectionKind::OnFailure => egui::Color32::from_rgb(255, 110, 90),
+        }
+    }
 }
 
-/// Encapsulates the flow-chart UI.
+/// Encapsulates the flow-chart UI. Only the nodes and connections are saved
+/// to flow files; the view and run state start fresh.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct FlowChart {
     pub nodes: Vec<Node>,
     pub connections: Vec<Connection>,
     pub next_id: usize,
+    #[serde(skip)]
     pub selected_node: Option<usize>,
-    pub connection_start: Option<usize>,
+    /// Nodes picked with shift-click, e.g. to collapse them into a subflow.
+    #[serde(skip)]
+    pub selection: BTreeSet<usize>,
+    /// Node and output port a new connection is being drawn from.
+    #[serde(skip)]
+    pub connection_start: Option<(usize, usize)>,
 
     /// How far the camera has been panned, in screen coordinates.
+    #[serde(skip)]
     pub pan_offset: egui::Vec2,
     /// Zoom factor (1.0 = 100%, 2.0 = 200%, etc.).
+    #[serde(skip)]
     pub zoom: f32,
 
-    /// We only store the size (width & height) of the central panel,
-    /// so we can compute the camera rectangle in world coordinates.
-    pub main_view_rect_size: Option<egui::Vec2>,
+    /// Screen area of the central panel the chart was last drawn in, so
+    /// we can compute the camera rectangle in world coordinates.
+    #[serde(skip)]
+    pub main_view_rect: Option<egui::Rect>,
+
+    /// Maximum number of nodes executed concurrently.
+    #[serde(skip)]
+    pub max_parallelism: usize,
+    /// Progress events of the run in flight, if any.
+    #[serde(skip)]
+    run_events: Option<Receiver<RunEvent>>,
+    /// Path of the node behind each job of the run in flight.
+    #[serde(skip)]
+    run_paths: Vec<Vec<usize>>,
 }
 
 impl Default for FlowChart {
@@ -44,133 +377,729 @@ impl Default for FlowChart {
             connections: Vec::new(),
             next_id: 1,
             selected_node: None,
+            selection: BTreeSet::new(),
             connection_start: None,
             pan_offset: egui::Vec2::ZERO,
             zoom: 1.0,
-            main_view_rect_size: None,
+            main_view_rect: None,
+            max_parallelism: std::thread::available_parallelism().map_or(4, |n| n.get()),
+            run_events: None,
+            run_paths: Vec::new(),
         }
     }
 }
 
+impl Clone for FlowChart {
+    /// Copies the flow and its view; a run in flight stays with the original.
+    fn clone(&self) -> Self {
+        Self {
+            nodes: self.nodes.clone(),
+            connections: self.connections.clone(),
+            next_id: self.next_id,
+            selected_node: self.selected_node,
+            selection: self.selection.clone(),
+            connection_start: self.connection_start,
+            pan_offset: self.pan_offset,
+            zoom: self.zoom,
+            main_view_rect: self.main_view_rect,
+            max_parallelism: self.max_parallelism,
+            run_events: None,
+            run_paths: Vec::new(),
+        }
+    }
+}
+
+/// Job graph of a flow whose subflow nodes are expanded in place.
+#[derive(Default)]
+struct Expansion {
+    /// Jobs indexed by their ID.
+    jobs: Vec<NodeJob>,
+    /// Path of the node behind each job.
+    paths: Vec<Vec<usize>>,
+}
+
+/// The jobs running a subflow on its own, e.g. as the body of a map node.
+#[derive(Clone)]
+pub struct SubflowJobs {
+    pub jobs: Vec<NodeJob>,
+    /// Jobs behind the input ports.
+    pub inputs: Vec<usize>,
+    /// Job behind the first output port, if there is one.
+    pub output: Option<usize>,
+}
+
+/// The jobs standing for one node of a chart when its connections are wired.
+struct Endpoints {
+    /// Job receiving the data of each input port.
+    inputs: Vec<usize>,
+    /// Job providing the data of each output port.
+    outputs: Vec<usize>,
+    /// Jobs gated by control edges into the node.
+    entries: Vec<usize>,
+}
+
 impl FlowChart {
     /// Add a new node at a default position.
     pub fn add_node(&mut self) {
-        let node = Node {
-            id: self.next_id,
-            pos: egui::pos2(50.0, 50.0),
-            command: format!("echo Node {}", self.next_id),
-            output: String::new(),
-        };
-        self.next_id += 1;
-        self.nodes.push(node);
-    }
-    
-    /// Add a new node with a specific command.
-    pub fn add_node_with_command(&mut self, command: &str) {
-        let node = Node {
-            id: self.next_id,
-            pos: egui::pos2(50.0, 50.0), // You might adjust this to suit your needs.
-            command: command.to_string(),
-            output: String::new(),
-        };
+        self.add_node_of_type(NodeType::Command, &format!("echo Node {}", self.next_id));
+    }
+
+    /// Add a new node of the given type with a specific command.
+    pub fn add_node_of_type(&mut self, node_type: NodeType, command: &str) -> &mut Node {
+        self.nodes
+            .push(Node::new(self.next_id, node_type, command.to_string()));
         self.next_id += 1;
-        self.nodes.push(node);
-    }
-    
-    
-    /// Compute a linear chain of node IDs based on connections.
-    /// Assumes a valid chain starts with a node having no incoming connection.
-    pub fn get_pipeline_chain(&self) -> Option<Vec<usize>> {
-        let mut incoming = HashMap::new();
-        let mut outgoing = HashMap::new();
-        for node in &self.nodes {
-            incoming.insert(node.id, 0);
-        }
+        self.nodes.last_mut().expect("a node was just added")
+    }
+
+    /// Order the nodes so every node comes after all of its upstream nodes.
+    /// Fails if the connections form a cycle.
+    pub fn topological_order(&self) -> Result<Vec<usize>, String> {
+        let mut incoming: HashMap<usize, usize> = self.nodes.iter().map(|n| (n.id, 0)).collect();
         for conn in &self.connections {
             *incoming.entry(conn.to).or_insert(0) += 1;
-            outgoing.insert(conn.from, conn.to);
         }
-        let start_id = self
+        let mut ready: Vec<usize> = self
             .nodes
             .iter()
-            .find(|n| incoming.get(&n.id) == Some(&0))?
-            .id;
-        let mut chain = vec![start_id];
-        let mut current = start_id;
-        while let Some(&next) = outgoing.get(&current) {
-            chain.push(next);
-            current = next;
+            .filter(|n| incoming[&n.id] == 0)
+            .map(|n| n.id)
+            .collect();
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut next = 0;
+        while next < ready.len() {
+            let id = ready[next];
+            next += 1;
+            order.push(id);
+            for conn in self.connections.iter().filter(|c| c.from == id) {
+                let count = incoming.entry(conn.to).or_insert(0);
+                *count -= 1;
+                if *count == 0 {
+                    ready.push(conn.to);
+                }
+            }
+        }
+        if order.len() != self.nodes.len() {
+            return Err("The flow contains a cycle.".into());
         }
-        Some(chain)
+        Ok(order)
     }
 
-    /// Runs the commands in sequence (piping each output into the next),
-    /// and returns intermediate outputs for each command.
-    pub fn run_pipeline_with_intermediates(
+    /// IDs of the nodes piping data into `id`, in connection order.
+    pub fn upstream_of(&self, id: usize) -> Vec<usize> {
+        self.sources_of(id, ConnectionKind::Data)
+    }
+
+    /// IDs of the nodes connected to `id` by edges of the given kind.
+    pub fn sources_of(&self, id: usize, kind: ConnectionKind) -> Vec<usize> {
+        self.connections
+            .iter()
+            .filter(|c| c.to == id && c.kind == kind)
+            .map(|c| c.from)
+            .collect()
+    }
+
+    /// Swaps the `index`-th incoming data connection of a node with the one
+    /// before it, changing the order in which fan-in inputs are merged.
+    pub fn move_input_up(&mut self, id: usize, index: usize) {
+        let incoming: Vec<usize> = self
+            .connections
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.to == id && c.kind == ConnectionKind::Data)
+            .map(|(i, _)| i)
+            .collect();
+        if index > 0 && index < incoming.len() {
+            self.connections.swap(incoming[index - 1], incoming[index]);
+        }
+    }
+
+    /// The given node plus every node reachable from it.
+    pub fn descendants_of(&self, id: usize) -> HashSet<usize> {
+        let mut seen = HashSet::from([id]);
+        let mut stack = vec![id];
+        while let Some(current) = stack.pop() {
+            for conn in self.connections.iter().filter(|c| c.from == current) {
+                if seen.insert(conn.to) {
+                    stack.push(conn.to);
+                }
+            }
+        }
+        seen
+    }
+
+    /// Forget the cached output of a node and everything downstream of it,
+    /// so the next run re-executes them.
+    pub fn invalidate_from(&mut self, id: usize) {
+        let stale = self.descendants_of(id);
+        for node in self.nodes.iter_mut().filter(|n| stale.contains(&n.id)) {
+            node.cache_key = None;
+        }
+    }
+
+    /// The chart at the end of a path of subflow node IDs; the empty path
+    /// is this chart.
+    pub fn chart_at_path(&self, path: &[usize]) -> Option<&FlowChart> {
+        let Some((first, rest)) = path.split_first() else {
+            return Some(self);
+        };
+        match &self.nodes.iter().find(|n| n.id == *first)?.node_type {
+            NodeType::Subflow(subflow) => subflow.chart.chart_at_path(rest),
+            _ => None,
+        }
+    }
+
+    pub fn chart_at_path_mut(&mut self, path: &[usize]) -> Option<&mut FlowChart> {
+        let Some((first, rest)) = path.split_first() else {
+            return Some(self);
+        };
+        match &mut self.nodes.iter_mut().find(|n| n.id == *first)?.node_type {
+            NodeType::Subflow(subflow) => subflow.chart.chart_at_path_mut(rest),
+            _ => None,
+        }
+    }
+
+    /// The node at the end of a path, e.g. `[4, 2]` for node 2 inside subflow node 4.
+    pub fn node_at_path(&self, path: &[usize]) -> Option<&Node> {
+        let (last, parents) = path.split_last()?;
+        self.chart_at_path(parents)?.nodes.iter().find(|n| n.id == *last)
+    }
+
+    pub fn node_at_path_mut(&mut self, path: &[usize]) -> Option<&mut Node> {
+        let (last, parents) = path.split_last()?;
+        self.chart_at_path_mut(parents)?
+            .nodes
+            .iter_mut()
+            .find(|n| n.id == *last)
+    }
+
+    /// Writes the flow's nodes, settings and connections to a JSON file.
+    pub fn save_to_file(&self, path: &Path) -> Result<(), String> {
+        let data = serde_json::to_vec_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(path, data).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    /// Reads a flow saved by [`FlowChart::save_to_file`].
+    pub fn load_from_file(path: &Path) -> Result<FlowChart, String> {
+        let data =
+            std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let mut chart: FlowChart = serde_json::from_slice(&data)
+            .map_err(|e| format!("Invalid flow file {}: {}", path.display(), e))?;
+        chart.refresh_subflows();
+        Ok(chart)
+    }
+
+    /// Starts running the nodes covered by `scope` in the background. Subflow
+    /// nodes are expanded into their inner nodes, so the executor only sees
+    /// plain nodes. Nodes run as soon as all of their upstream nodes are
+    /// done, so independent branches execute concurrently. Nodes outside the
+    /// scope are not executed; their stored output is fed to the nodes below
+    /// them as-is. Nodes whose command and inputs are unchanged since their
+    /// last run reuse their cached output unless `force` is set.
+    pub fn start_run(&mut self, scope: &RunScope, force: bool) -> Result<(), String> {
+        if self.is_running() {
+            return Err("A run is already in progress.".into());
+        }
+        let mut expansion = Expansion::default();
+        self.expand(&[], &mut expansion)?;
+        let Expansion { mut jobs, paths } = expansion;
+        let under = |path: &[usize]| {
+            (0..jobs.len())
+                .filter(|&job| paths[job].starts_with(path))
+                .collect()
+        };
+        let in_scope = match scope {
+            RunScope::All => (0..jobs.len()).collect(),
+            RunScope::UpTo(path) => reachable_jobs(&jobs, under(path), false),
+            RunScope::From(path) => reachable_jobs(&jobs, under(path), true),
+        };
+        self.check_files(jobs.iter().filter(|job| in_scope.contains(&job.id)), &paths)?;
+        for job in &mut jobs {
+            job.in_scope = in_scope.contains(&job.id);
+            // Only copy outputs the executor may actually reuse.
+            let reusable = !job.in_scope || (!force && job.previous_key.is_some());
+            if reusable && let Some(node) = self.node_at_path(&paths[job.id]) {
+                job.previous_output = Arc::new(node.output.clone());
+            }
+        }
+        self.launch(
+            RunPlan {
+                jobs,
+                force,
+                max_parallelism: self.max_parallelism,
+            },
+            paths,
+        );
+        Ok(())
+    }
+
+    /// Fails, marking the offending nodes, if any of the jobs' input files
+    /// is missing or an output file cannot be created.
+    fn check_files<'a>(
+        &mut self,
+        jobs: impl Iterator<Item = &'a NodeJob>,
+        paths: &[Vec<usize>],
+    ) -> Result<(), String> {
+        let mut problems = Vec::new();
+        for job in jobs {
+            if let Err(e) = job.node_type.check() {
+                problems.push(format!("{}: {}", job.label, e));
+                if let Some(node) = self.node_at_path_mut(&paths[job.id]) {
+                    node.status = NodeStatus::Failed;
+                    node.error = Some(e);
+                }
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            self.refresh_subflows();
+            Err(problems.join("\n"))
+        }
+    }
+
+    /// Appends one job per plain node of this chart, whose own path is
+    /// `prefix`, recursing into subflows, and wires the chart's connections
+    /// between them. Returns the jobs standing for each node. Fails if an
+    /// edge uses a port its subflow does not have, rather than dropping it.
+    fn expand(
         &self,
-        commands: &[String],
-    ) -> Result<Vec<String>, String> {
-        if commands.is_empty() {
-            return Ok(vec![]);
-        }
-        let mut intermediate_outputs = Vec::new();
-
-        // Run the first command:
-        let output = Command::new("sh")
-            .arg("-c")
-            .arg(&commands[0])
-            .output()
-            .map_err(|e| format!("Failed to run command '{}': {}", commands[0], e))?;
-        if !output.status.success() {
-            return Err(String::from_utf8_lossy(&output.stderr).to_string());
-        }
-        let first_out = String::from_utf8_lossy(&output.stdout).to_string();
-        intermediate_outputs.push(first_out.clone());
-        let mut current_input = first_out;
-
-        // Pipe subsequent commands:
-        for command in commands.iter().skip(1) {
-            let mut child = Command::new("sh")
-                .arg("-c")
-                .arg(command)
-                .stdin(Stdio::piped())
-                .stdout(Stdio::piped())
-                .spawn()
-                .map_err(|e| format!("Failed to run command '{}': {}", command, e))?;
-
-            {
-                let child_stdin = child.stdin.as_mut().ok_or("Failed to open stdin")?;
-                child_stdin
-                    .write_all(current_input.as_bytes())
-                    .map_err(|e| format!("Failed to write to stdin: {}", e))?;
+        prefix: &[usize],
+        expansion: &mut Expansion,
+    ) -> Result<HashMap<usize, Endpoints>, String> {
+        let mut endpoints = HashMap::new();
+        for id in self.topological_order()? {
+            let Some(node) = self.nodes.iter().find(|n| n.id == id) else {
+                continue;
+            };
+            let mut path = prefix.to_vec();
+            path.push(id);
+            let ends = match &node.node_type {
+                NodeType::Subflow(subflow) => {
+                    let inner = subflow.chart.expand(&path, expansion)?;
+                    let port_job = |port: &Port, input: bool| {
+                        inner
+                            .get(&port.node)
+                            .and_then(|ends| {
+                                let jobs = if input { &ends.inputs } else { &ends.outputs };
+                                jobs.get(port.node_port).copied()
+                            })
+                            .ok_or_else(|| {
+                                format!(
+                                    "Port '{}' of subflow '{}' is not attached to a node.",
+                                    port.name, subflow.name
+                                )
+                            })
+                    };
+                    let inputs = subflow.inputs.iter().map(|p| port_job(p, true));
+                    let outputs = subflow.outputs.iter().map(|p| port_job(p, false));
+                    let chart = &subflow.chart;
+                    Endpoints {
+                        inputs: inputs.collect::<Result<_, _>>()?,
+                        outputs: outputs.collect::<Result<_, _>>()?,
+                        // Inner nodes without incoming edges are where the subflow starts.
+                        entries: chart
+                            .nodes
+                            .iter()
+                            .filter(|n| !chart.connections.iter().any(|c| c.to == n.id))
+                            .flat_map(|n| inner[&n.id].entries.iter().copied())
+                            .collect(),
+                    }
+                }
+                _ => {
+                    let job = expansion.jobs.len();
+                    expansion.jobs.push(NodeJob {
+                        id: job,
+                        label: path_label(&path),
+                        node_type: node.node_type.clone(),
+                        command: node.command.clone(),
+                        upstream: Vec::new(),
+                        merge_mode: node.merge_mode.clone(),
+                        after_success: Vec::new(),
+                        after_failure: Vec::new(),
+                        success_codes: node.success_codes.clone(),
+                        retry: node.retry.clone(),
+                        process: node.process.clone(),
+                        in_scope: true,
+                        previous_key: node.cache_key,
+                        previous_output: Arc::default(),
+                        fixed_input: None,
+                    });
+                    expansion.paths.push(path);
+                    Endpoints {
+                        inputs: vec![job],
+                        outputs: vec![job],
+                        entries: vec![job],
+                    }
+                }
+            };
+            endpoints.insert(id, ends);
+        }
+        for conn in &self.connections {
+            let (Some(from), Some(to)) = (endpoints.get(&conn.from), endpoints.get(&conn.to)) else {
+                continue;
+            };
+            let missing_port = |id: usize, side: &str, port: usize| {
+                let name = self.nodes.iter().find(|n| n.id == id).map(Node::label).unwrap_or_default();
+                let mut path = prefix.to_vec();
+                path.push(id);
+                format!(
+                    "{} ({}) has no {} port {}; add the port or remove the edge.",
+                    path_label(&path),
+                    name,
+                    side,
+                    port + 1
+                )
+            };
+            let Some(&source) = from.outputs.get(conn.from_port) else {
+                return Err(missing_port(conn.from, "output", conn.from_port));
+            };
+            match conn.kind {
+                ConnectionKind::Data => {
+                    let Some(&target) = to.inputs.get(conn.to_port) else {
+                        return Err(missing_port(conn.to, "input", conn.to_port));
+                    };
+                    expansion.jobs[target].upstream.push(source);
+                }
+                ConnectionKind::OnSuccess => {
+                    for &target in &to.entries {
+                        expansion.jobs[target].after_success.push(source);
+                    }
+                }
+                ConnectionKind::OnFailure => {
+                    for &target in &to.entries {
+                        expansion.jobs[target].after_failure.push(source);
+                    }
+                }
+            }
+        }
+        Ok(endpoints)
+    }
+
+    /// Feeds what decides the chart's outputs to `state`: the nodes'
+    /// settings, inner charts included, and the connections. Positions,
+    /// selection and run results are left out.
+    pub fn hash_settings<H: Hasher>(&self, state: &mut H) {
+        for node in &self.nodes {
+            node.id.hash(state);
+            node.node_type.hash(state);
+            node.command.hash(state);
+            node.merge_mode.hash(state);
+            node.success_codes.hash(state);
+            node.process.hash(state);
+            // A subflow's own hash leaves its inner chart out.
+            if let NodeType::Subflow(subflow) = &node.node_type {
+                subflow.chart.hash_settings(state);
+            }
+        }
+        for conn in &self.connections {
+            (conn.from, conn.to, conn.kind, conn.from_port, conn.to_port).hash(state);
+        }
+    }
+
+    /// Expands a subflow outside any chart, as if it were the only node of one.
+    pub fn expand_subflow(subflow: &Subflow) -> Result<SubflowJobs, String> {
+        let mut chart = FlowChart::default();
+        chart.nodes.push(Node::new(0, NodeType::Subflow(Box::new(subflow.clone())), String::new()));
+        let mut expansion = Expansion::default();
+        let ends = chart.expand(&[], &mut expansion)?.remove(&0).expect("the subflow was expanded");
+        Ok(SubflowJobs {
+            jobs: expansion.jobs,
+            inputs: ends.inputs,
+            output: ends.outputs.first().copied(),
+        })
+    }
+
+    /// Starts running a single node in isolation on the given input,
+    /// ignoring its upstream connections.
+    pub fn start_isolated_run(&mut self, path: &[usize], input: Vec<u8>) -> Result<(), String> {
+        if self.is_running() {
+            return Err("A run is already in progress.".into());
+        }
+        let node = self
+            .node_at_path(path)
+            .ok_or_else(|| format!("{} does not exist.", path_label(path)))?;
+        if let NodeType::Subflow(_) = node.node_type {
+            return Err("A subflow cannot run on its own; open it and run its nodes instead.".into());
+        }
+        let job = NodeJob {
+            id: 0,
+            label: path_label(path),
+            node_type: node.node_type.clone(),
+            command: node.command.clone(),
+            upstream: Vec::new(),
+            merge_mode: MergeMode::default(),
+            after_success: Vec::new(),
+            after_failure: Vec::new(),
+            success_codes: node.success_codes.clone(),
+            retry: node.retry.clone(),
+            process: node.process.clone(),
+            in_scope: true,
+            previous_key: None,
+            previous_output: Arc::default(),
+            fixed_input: Some(input),
+        };
+        let paths = vec![path.to_vec()];
+        self.check_files(std::iter::once(&job), &paths)?;
+        self.launch(
+            RunPlan {
+                jobs: vec![job],
+                force: true,
+                max_parallelism: 1,
+            },
+            paths,
+        );
+        Ok(())
+    }
+
+    fn launch(&mut self, plan: RunPlan, paths: Vec<Vec<usize>>) {
+        for job in plan.jobs.iter().filter(|job| job.in_scope) {
+            if let Some(node) = self.node_at_path_mut(&paths[job.id]) {
+                node.status = NodeStatus::Queued;
+            }
+        }
+        self.refresh_subflows();
+        self.run_paths = paths;
+        self.run_events = Some(executor::spawn_run(plan));
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.run_events.is_some()
+    }
+
+    /// The node behind a job of the run in flight.
+    fn job_node_mut(&mut self, job: usize) -> Option<&mut Node> {
+        let path = self.run_paths.get(job)?.clone();
+        self.node_at_path_mut(&path)
+    }
+
+    /// Applies progress from the run in flight to the nodes. Returns the
+    /// run's final output once it has completed.
+    pub fn poll_run(&mut self) -> Option<Result<Vec<u8>, String>> {
+        let events: Vec<RunEvent> = self.run_events.as_ref()?.try_iter().collect();
+        if events.is_empty() {
+            return None;
+        }
+        let mut done = None;
+        for event in events {
+            match event {
+                RunEvent::Started(id) => {
+                    if let Some(node) = self.job_node_mut(id) {
+                        node.status = NodeStatus::Running;
+                        node.attempts.clear();
+                    }
+                }
+                RunEvent::Retrying(id, attempt) => {
+                    if let Some(node) = self.job_node_mut(id) {
+                        node.attempts.push(attempt);
+                    }
+                }
+                RunEvent::Finished(id, result) => {
+                    let Some(node) = self.job_node_mut(id) else {
+                        continue;
+                    };
+                    match result {
+                        NodeResult::Ran { attempt, cache_key } => {
+                            node.set_output(attempt.stdout.clone());
+                            node.attempts.push(attempt);
+                            node.cache_key = cache_key;
+                            node.status = NodeStatus::Succeeded;
+                            node.error = None;
+                        }
+                        NodeResult::Cached => {
+                            node.status = NodeStatus::Cached;
+                            node.error = None;
+                        }
+                        NodeResult::Failed { attempt, error } => {
+                            node.set_output(attempt.stdout.clone());
+                            node.attempts.push(attempt);
+                            node.cache_key = None;
+                            node.status = NodeStatus::Failed;
+                            node.error = Some(error);
+                        }
+                        NodeResult::Skipped => node.status = NodeStatus::Skipped,
+                    }
+                }
+                RunEvent::Done(result) => {
+                    self.run_events = None;
+                    self.run_paths.clear();
+                    // Nodes that never got to run after a failure are back to idle.
+                    self.for_each_node_mut(&mut |node| {
+                        if node.status == NodeStatus::Queued {
+                            node.status = NodeStatus::Idle;
+                        }
+                    });
+                    done = Some(result);
+                    break;
+                }
+            }
+        }
+        self.refresh_subflows();
+        done
+    }
+
+    /// Blocks until the run in flight completes and returns its final output.
+    pub fn wait_for_run(&mut self) -> Result<Vec<u8>, String> {
+        while self.is_running() {
+            if let Some(result) = self.poll_run() {
+                return result;
             }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        Err("No run is in progress.".into())
+    }
+
+    /// Whether any node, including the nodes inside subflows, matches.
+    pub fn any_node(&self, f: &impl Fn(&Node) -> bool) -> bool {
+        self.nodes.iter().any(|node| match &node.node_type {
+            NodeType::Subflow(subflow) => f(node) || subflow.chart.any_node(f),
+            _ => f(node),
+        })
+    }
 
-            let output = child
-                .wait_with_output()
-                .map_err(|e| format!("Error waiting on command '{}': {}", command, e))?;
-            if !output.status.success() {
-                return Err(String::from_utf8_lossy(&output.stderr).to_string());
+    /// Calls `f` on every node, including the nodes inside subflows.
+    fn for_each_node_mut(&mut self, f: &mut impl FnMut(&mut Node)) {
+        for node in &mut self.nodes {
+            if let NodeType::Subflow(subflow) = &mut node.node_type {
+                subflow.chart.for_each_node_mut(f);
             }
-            let out_str = String::from_utf8_lossy(&output.stdout).to_string();
-            intermediate_outputs.push(out_str.clone());
-            current_input = out_str;
+            f(node);
         }
+    }
+
+    /// Selects a node and pans the camera so it sits in the middle of the view.
+    pub fn focus_node(&mut self, id: usize) {
+        let Some(node) = self.nodes.iter().find(|node| node.id == id) else {
+            return;
+        };
+        let center = node.pos + NODE_SIZE / 2.0;
+        self.center_on(center);
+        self.selection = BTreeSet::from([id]);
+        self.selected_node = Some(id);
+    }
+
+    /// Pans so the world point `center` is in the middle of the view.
+    pub fn center_on(&mut self, center: egui::Pos2) {
+        self.pan_offset = self.view_rect().center().to_vec2() - center.to_vec2() * self.zoom;
+    }
+
+    /// The screen area the chart was last drawn in.
+    pub fn view_rect(&self) -> egui::Rect {
+        self.main_view_rect.unwrap_or(egui::Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(800.0, 600.0)))
+    }
+
+    /// The part of the world the camera shows.
+    pub fn camera_rect(&self) -> egui::Rect {
+        let view = self.view_rect();
+        egui::Rect::from_min_max(
+            ((view.min - self.pan_offset).to_vec2() / self.zoom).to_pos2(),
+            ((view.max - self.pan_offset).to_vec2() / self.zoom).to_pos2(),
+        )
+    }
+
+    /// Scales the view by `factor`, keeping the world point under `anchor`
+    /// (in screen coordinates) in place. Zoom stays within its bounds.
+    pub fn zoom_around(&mut self, factor: f32, anchor: egui::Pos2) {
+        let zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        let world = (anchor - self.pan_offset).to_vec2() / self.zoom;
+        self.pan_offset = anchor.to_vec2() - world * zoom;
+        self.zoom = zoom;
+    }
+
+    /// Zooms around the middle of the view, as the zoom buttons do.
+    pub fn zoom_by(&mut self, factor: f32) {
+        self.zoom_around(factor, self.view_rect().center());
+    }
+
+    /// Zooms and pans so every node is in view.
+    pub fn zoom_to_fit(&mut self) {
+        let ids: Vec<usize> = self.nodes.iter().map(|node| node.id).collect();
+        self.fit_nodes(&ids);
+    }
 
-        Ok(intermediate_outputs)
+    /// Zooms and pans so the selected nodes are in view.
+    pub fn zoom_to_selection(&mut self) {
+        let mut ids: Vec<usize> = self.selection.iter().copied().collect();
+        ids.extend(self.selected_node.filter(|id| !self.selection.contains(id)));
+        self.fit_nodes(&ids);
+    }
+
+    /// Back to 100% with the world origin in the top-left corner of the view.
+    pub fn reset_view(&mut self) {
+        self.zoom = 1.0;
+        self.pan_offset = self.view_rect().min.to_vec2();
+    }
+
+    fn fit_nodes(&mut self, ids: &[usize]) {
+        let Some(bounds) = self
+            .nodes
+            .iter()
+            .filter(|node| ids.contains(&node.id))
+            .map(|node| egui::Rect::from_min_size(node.pos, NODE_SIZE))
+            .reduce(|a, b| a.union(b))
+        else {
+            return;
+        };
+        let bounds = bounds.expand(FIT_MARGIN);
+        let view = self.view_rect();
+        let zoom = (view.width() / bounds.width()).min(view.height() / bounds.height());
+        self.zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+        self.center_on(bounds.center());
+    }
+
+    /// Derives the status and output of every subflow node from the nodes inside it.
+    pub fn refresh_subflows(&mut self) {
+        for node in &mut self.nodes {
+            if let NodeType::Subflow(subflow) = &mut node.node_type {
+                subflow.chart.refresh_subflows();
+                node.status = subflow.status();
+                match subflow.output_node() {
+                    Some(inner) => {
+                        node.output = inner.output.clone();
+                        node.output_lines = inner.output_lines;
+                        node.output_is_text = inner.output_is_text;
+                    }
+                    None => node.set_output(Vec::new()),
+                }
+            }
+        }
     }
 
     /// Draw the flow-chart in the main (central) panel.
-    /// This captures the panel size, handles pan/zoom, draws nodes, etc.
-    pub fn draw(&mut self, ui: &mut egui::Ui) {
-        // 1) Store just the size (width & height) of the central panel:
-        let panel_size = ui.available_size_before_wrap();
-        self.main_view_rect_size = Some(panel_size);
+    /// This captures the panel rect, handles pan/zoom, draws nodes, etc.
+    pub fn draw(&mut self, ui: &mut egui::Ui, options: CanvasOptions) {
+        // 1) Store the screen area of the central panel:
+        let panel_rect = ui.available_rect_before_wrap();
+        self.main_view_rect = Some(panel_rect);
 
-        // 2) Process mouse wheel for zoom:
-        let scroll_delta = ui.input(|i| i.raw_scroll_delta);
-        if scroll_delta.y != 0.0 {
-            let zoom_factor = 1.0 + scroll_delta.y * 0.001;
-            self.zoom *= zoom_factor;
+        // 2) Mouse wheel and pinch zoom around the pointer, while it is over the chart:
+        if let Some(pointer) = ui.input(|i| i.pointer.hover_pos())
+            && ui.rect_contains_pointer(panel_rect)
+        {
+            let (scroll_delta, pinch) = ui.input(|i| (i.raw_scroll_delta, i.zoom_delta()));
+            let zoom_factor = (1.0 + scroll_delta.y * 0.001) * pinch;
+            if zoom_factor != 1.0 {
+                self.zoom_around(zoom_factor, pointer);
+            }
+        }
+
+        // 3) Background: the grid, and panning by dragging with the middle
+        // button, or with the left one while Space is held:
+        let canvas = ui.interact(panel_rect, ui.id().with("canvas"), egui::Sense::drag());
+        let space_held = ui.input(|i| i.key_down(egui::Key::Space)) && !ui.ctx().wants_keyboard_input();
+        let pans = |response: &egui::Response| {
+            response.dragged_by(egui::PointerButton::Middle)
+                || (space_held && response.dragged_by(egui::PointerButton::Primary))
+        };
+        let mut pan_delta = egui::Vec2::ZERO;
+        if pans(&canvas) {
+            pan_delta += canvas.drag_delta();
+        }
+        if space_held && canvas.hovered() {
+            ui.ctx().set_cursor_icon(egui::CursorIcon::Grab);
+        }
+        if options.show_grid {
+            self.draw_grid(ui.painter(), panel_rect);
         }
 
         // We'll do a simple world->screen transform:
@@ -179,196 +1108,778 @@ impl FlowChart {
         };
 
         // Node drawing:
-        let node_size = egui::vec2(120.0, 50.0) * self.zoom;
+        let node_size = NODE_SIZE * self.zoom;
         let mut node_rects = std::collections::HashMap::new();
 
         // Allocate rects for nodes:
+        let positions: Vec<(usize, egui::Pos2)> = self.nodes.iter().map(|node| (node.id, node.pos)).collect();
+        let mut guides = Vec::new();
+        let mut hovered_node = None;
         for node in &mut self.nodes {
             let screen_pos = transform(node.pos);
             let rect = egui::Rect::from_min_size(screen_pos, node_size);
             let response = ui.allocate_rect(rect, egui::Sense::click_and_drag());
-            if response.dragged() {
-                // Convert drag delta from screen coords to world coords:
-                node.pos += response.drag_delta() / self.zoom;
+            if response.hovered() {
+                hovered_node = Some(node.id);
+            }
+            if pans(&response) {
+                pan_delta += response.drag_delta();
+            } else if response.dragged_by(egui::PointerButton::Primary) {
+                // Follow the pointer exactly in `raw`, then snap what is shown.
+                let raw_id = response.id.with("raw_pos");
+                let raw = ui.data(|d| d.get_temp(raw_id)).unwrap_or(node.pos) + response.drag_delta() / self.zoom;
+                ui.data_mut(|d| d.insert_temp(raw_id, raw));
+                let mut pos = if options.snap_to_grid {
+                    egui::pos2(snap(raw.x), snap(raw.y))
+                } else {
+                    raw
+                };
+                let others = positions.iter().filter(|(id, _)| *id != node.id).map(|(_, pos)| *pos);
+                let (column, row) = alignment_partners(raw, others, ALIGN_DISTANCE / self.zoom);
+                if let Some(other) = column {
+                    pos.x = other.x;
+                }
+                if let Some(other) = row {
+                    pos.y = other.y;
+                }
+                node.pos = pos;
+                guides.extend(column.into_iter().chain(row).map(|other| [other, pos]));
+            }
+            if response.drag_stopped() {
+                ui.data_mut(|d| d.remove::<egui::Pos2>(response.id.with("raw_pos")));
             }
             if response.clicked() {
-                self.selected_node = Some(node.id);
+                if ui.input(|i| i.modifiers.shift) {
+                    // Shift-click toggles the node in the multi-selection.
+                    if !self.selection.remove(&node.id) {
+                        self.selection.insert(node.id);
+                    }
+                    self.selected_node = self.selection.last().copied();
+                } else {
+                    self.selection = BTreeSet::from([node.id]);
+                    self.selected_node = Some(node.id);
+                }
             }
             node_rects.insert(node.id, rect);
         }
 
-        // Connection handles:
+        // Connection handles: an output handle on the right starts a
+        // connection from its port; clicking an output handle or an input
+        // handle on the left of another node finishes it.
         let handle_size = egui::vec2(10.0, 10.0) * self.zoom;
+        let port_counts: HashMap<usize, (usize, usize)> =
+            self.nodes.iter().map(|node| (node.id, node.port_counts())).collect();
         for (id, rect) in &node_rects {
-            let handle_pos = egui::pos2(
-                rect.max.x - handle_size.x / 2.0,
-                rect.center().y - handle_size.y / 2.0,
-            );
-            let handle_rect = egui::Rect::from_min_size(handle_pos, handle_size);
-
-            let handle_response =
-                ui.interact(handle_rect, egui::Id::new(*id), egui::Sense::click());
-            if handle_response.clicked() {
+            let (inputs, outputs) = port_counts[id];
+            let mut start = None;
+            let mut finish = None;
+            let mut output_handles = Vec::with_capacity(outputs);
+            for port in 0..outputs {
+                let anchor = port_anchor(*rect, Side::Right, port, outputs);
+                let handle_rect = egui::Rect::from_center_size(anchor, handle_size);
+                let id = if port == 0 { egui::Id::new(*id) } else { egui::Id::new((*id, "output", port)) };
+                if ui.interact(handle_rect, id, egui::Sense::click()).clicked() {
+                    start = Some(port);
+                    finish = Some(0);
+                }
+                output_handles.push(handle_rect);
+            }
+
+            for port in 0..inputs {
+                let anchor = port_anchor(*rect, Side::Left, port, inputs);
+                let input_rect = egui::Rect::from_center_size(anchor, handle_size);
+                let response = ui.interact(input_rect, egui::Id::new((*id, "input", port)), egui::Sense::click());
+                if response.clicked() && self.connection_start.is_some() {
+                    finish = Some(port);
+                }
+                let fill = if response.hovered() { egui::Color32::WHITE } else { egui::Color32::LIGHT_GRAY };
+                ui.painter().circle_filled(anchor, handle_size.x / 2.0, fill);
+            }
+
+            if let Some(to_port) = finish {
                 if self.connection_start.is_none() {
-                    self.connection_start = Some(*id);
-                } else if let Some(start_id) = self.connection_start {
+                    self.connection_start = start.map(|port| (*id, port));
+                } else if let Some((start_id, from_port)) = self.connection_start {
                     if start_id != *id {
                         self.connections.push(Connection {
                             from: start_id,
                             to: *id,
+                            kind: ConnectionKind::Data,
+                            from_port,
+                            to_port,
                         });
                     }
                     self.connection_start = None;
                 }
             }
 
-            ui.painter()
-                .rect_filled(handle_rect, 2.0, egui::Color32::YELLOW);
+            for handle_rect in output_handles {
+                ui.painter().rect_filled(handle_rect, 2.0, egui::Color32::YELLOW);
+            }
         }
 
         // Temporary connection line if the user is dragging from a node handle:
-        if let Some(start_id) = self.connection_start {
-            if let Some(&start_rect) = node_rects.get(&start_id) {
-                let start_handle = egui::pos2(start_rect.max.x, start_rect.center().y);
-                let pointer_pos = ui
-                    .input(|i| i.pointer.hover_pos())
-                    .unwrap_or(start_handle);
-                ui.painter().line_segment(
-                    [start_handle, pointer_pos],
-                    egui::Stroke::new(2.0, egui::Color32::RED),
-                );
-            }
+        if let Some((start_id, port)) = self.connection_start
+            && let Some(&start_rect) = node_rects.get(&start_id)
+        {
+            let (_, outputs) = port_counts[&start_id];
+            let start_handle = port_anchor(start_rect, Side::Right, port.min(outputs - 1), outputs);
+            let pointer_pos = ui
+                .input(|i| i.pointer.hover_pos())
+                .unwrap_or(start_handle);
+            ui.painter().add(edge_curve(
+                (start_handle, Side::Right),
+                (pointer_pos, Side::Left),
+                self.zoom,
+                egui::Stroke::new(2.0, egui::Color32::RED),
+            ));
         }
 
-        // Draw established connections with arrowheads:
-        for conn in &self.connections {
-            if let (Some(&from_rect), Some(&to_rect)) =
-                (node_rects.get(&conn.from), node_rects.get(&conn.to))
-            {
-                let from_pos = from_rect.center();
-                let to_pos = to_rect.center();
-                ui.painter().line_segment(
-                    [from_pos, to_pos],
-                    egui::Stroke::new(2.0, egui::Color32::LIGHT_GREEN),
-                );
+        // Draw established connections as curves from the source's output
+        // port to the target's input port, with arrowheads on the target's
+        // edge. Edges leave and enter through the sides facing each other,
+        // so flows laid out top to bottom read as well as left to right:
+        let pointer = ui.input(|i| i.pointer.hover_pos()).filter(|pos| panel_rect.contains(*pos));
+        let time = ui.input(|i| i.time) as f32;
+        let mut hovered_edge = None;
+        for (index, conn) in self.connections.iter().enumerate() {
+            let (Some(&from_rect), Some(&to_rect)) = (node_rects.get(&conn.from), node_rects.get(&conn.to)) else {
+                continue;
+            };
+            let (_, outputs) = port_counts[&conn.from];
+            let (inputs, _) = port_counts[&conn.to];
+            let (from_side, to_side) = facing_sides(from_rect, to_rect);
+            let from_pos = port_anchor(from_rect, from_side, conn.from_port.min(outputs - 1), outputs);
+            let to_pos = port_anchor(to_rect, to_side, conn.to_port.min(inputs - 1), inputs);
+            let arrow_size = 10.0 * self.zoom;
 
-                // Draw arrowhead
-                let arrow_size = 10.0 * self.zoom;
-                let direction = (to_pos - from_pos).normalized();
-                let perpendicular = egui::Vec2::new(-direction.y, direction.x);
-                let arrow_tip = to_pos - direction * (node_size.x / 2.0); // Adjust the arrow tip position
-                let arrow_left = arrow_tip - direction * arrow_size + perpendicular * arrow_size * 0.5;
-                let arrow_right = arrow_tip - direction * arrow_size - perpendicular * arrow_size * 0.5;
+            // The curve stops at the base of the arrowhead, which touches the node.
+            let mut curve = edge_curve((from_pos, from_side), (to_pos, to_side), self.zoom, egui::Stroke::NONE);
+            let points = curve.flatten(Some(0.5));
+            let hovered = hovered_edge.is_none()
+                && pointer.is_some_and(|pointer| distance_to_polyline(pointer, &points) < 6.0);
+            if hovered {
+                hovered_edge = Some(index);
+            }
+            let width = if hovered { 4.0 } else { 2.0 };
+            let color = if hovered { conn.kind.color().gamma_multiply(1.4) } else { conn.kind.color() };
+            let stroke = egui::Stroke::new(width, color);
 
-                ui.painter().line_segment(
-                    [arrow_tip, arrow_left],
-                    egui::Stroke::new(2.0, egui::Color32::LIGHT_GREEN),
-                );
-                ui.painter().line_segment(
-                    [arrow_tip, arrow_right],
-                    egui::Stroke::new(2.0, egui::Color32::LIGHT_GREEN),
-                );
+            let direction = (to_pos - curve.points[2]).normalized();
+            let perpendicular = egui::Vec2::new(-direction.y, direction.x);
+            let arrow_base = to_pos - direction * arrow_size;
+            curve.points[3] = arrow_base;
+            if conn.kind == ConnectionKind::Data {
+                curve.stroke = stroke;
+                ui.painter().add(curve);
+            } else {
+                // Control edges are dashed so they read differently from data flow.
+                ui.painter().extend(egui::Shape::dashed_line(
+                    &curve.flatten(Some(0.5)),
+                    stroke,
+                    8.0 * self.zoom,
+                    5.0 * self.zoom,
+                ));
+            }
+            ui.painter().add(egui::Shape::convex_polygon(
+                vec![
+                    to_pos,
+                    arrow_base + perpendicular * arrow_size * 0.5,
+                    arrow_base - perpendicular * arrow_size * 0.5,
+                ],
+                color,
+                egui::Stroke::NONE,
+            ));
+
+            // Data moving along the edge: dots flowing toward the node reading it.
+            let target_running = self.nodes.iter().any(|n| n.id == conn.to && n.status == NodeStatus::Running);
+            if conn.kind == ConnectionKind::Data && target_running {
+                for dot in 0..3 {
+                    let t = (time * 0.8 + dot as f32 / 3.0).fract();
+                    ui.painter().circle_filled(curve.sample(t), 3.0 * self.zoom, egui::Color32::WHITE);
+                }
+                ui.ctx().request_repaint();
             }
         }
+        if let Some(index) = hovered_edge {
+            let conn = &self.connections[index];
+            egui::show_tooltip_at_pointer(ui.ctx(), ui.id().with("edge_tooltip"), |ui| {
+                ui.label(format!("{}: node {} → node {}", conn.kind.label(), conn.from, conn.to));
+            });
+        }
 
         // Finally, draw each node's background + text:
         for node in &self.nodes {
             if let Some(&rect) = node_rects.get(&node.id) {
                 let is_selected = Some(node.id) == self.selected_node;
-                let fill_color = egui::Color32::from_rgb(100, 150, 200);
+                let fill_color = node.status.color();
                 let stroke = if is_selected {
                     egui::Stroke::new(3.0, egui::Color32::GOLD)
+                } else if self.selection.contains(&node.id) {
+                    egui::Stroke::new(2.0, egui::Color32::GOLD)
+                } else if node.status == NodeStatus::Running {
+                    egui::Stroke::new(3.0, egui::Color32::YELLOW)
                 } else {
                     egui::Stroke::new(2.0, egui::Color32::BLACK)
                 };
 
                 ui.painter().rect_filled(rect, 5.0, fill_color);
                 ui.painter().rect_stroke(rect, 5.0, stroke);
+                let tag = match node.status {
+                    NodeStatus::Idle => None,
+                    NodeStatus::Running => Some((node.status.label(), egui::Color32::YELLOW)),
+                    status => Some((status.label(), egui::Color32::LIGHT_GRAY)),
+                };
+                // Attempt count, shown live while retrying and after a retried run.
+                let attempt = match node.status {
+                    NodeStatus::Running => node.attempts.len() + 1,
+                    _ => node.attempts.len(),
+                };
+                if attempt > 1 {
+                    ui.painter().text(
+                        rect.right_top() + egui::vec2(-4.0, 2.0) * self.zoom,
+                        egui::Align2::RIGHT_TOP,
+                        format!("attempt {}", attempt),
+                        egui::FontId::proportional(11.0 * self.zoom),
+                        egui::Color32::LIGHT_GRAY,
+                    );
+                }
+                if let Some((text, color)) = tag {
+                    ui.painter().text(
+                        rect.left_top() + egui::vec2(4.0, 2.0) * self.zoom,
+                        egui::Align2::LEFT_TOP,
+                        text,
+                        egui::FontId::proportional(11.0 * self.zoom),
+                        color,
+                    );
+                }
                 ui.painter().text(
                     rect.center(),
                     egui::Align2::CENTER_CENTER,
-                    &node.command,
+                    node.label(),
                     egui::FontId::proportional(16.0 * self.zoom),
                     egui::Color32::WHITE,
                 );
+
+                // Badges along the bottom edge, once the node has run:
+                // exit code on the left, runtime and output size on the right,
+                // cut short with an ellipsis where they would meet.
+                if self.zoom >= BADGE_MIN_ZOOM && !node.attempts.is_empty() {
+                    let font = egui::FontId::proportional(10.0 * self.zoom);
+                    let margin = 4.0 * self.zoom;
+                    let bottom = rect.bottom() - 2.0 * self.zoom;
+                    let mut free_left = rect.left() + margin;
+                    if let Some(code) = node.exit_code() {
+                        let color = if node.success_codes.contains(&code) {
+                            egui::Color32::LIGHT_GRAY
+                        } else {
+                            egui::Color32::from_rgb(255, 200, 200)
+                        };
+                        let galley = ui.painter().layout_no_wrap(format!("exit {}", code), font.clone(), color);
+                        let pos = egui::pos2(free_left, bottom - galley.size().y);
+                        free_left += galley.size().x + margin;
+                        ui.painter().galley(pos, galley, color);
+                    }
+                    let mut job = egui::text::LayoutJob::simple_singleline(
+                        format!("{} · {}", format_runtime(node.runtime()), node.output_size()),
+                        font,
+                        egui::Color32::LIGHT_GRAY,
+                    );
+                    job.wrap = egui::text::TextWrapping {
+                        max_width: (rect.right() - margin - free_left).max(0.0),
+                        max_rows: 1,
+                        break_anywhere: true,
+                        overflow_character: Some('…'),
+                    };
+                    let galley = ui.painter().layout_job(job);
+                    let pos = egui::pos2(rect.right() - margin - galley.size().x, bottom - galley.size().y);
+                    ui.painter().galley(pos, galley, egui::Color32::LIGHT_GRAY);
+                }
             }
         }
+
+        // Output preview of the node under the pointer, unless it is being dragged.
+        if let Some(node) = hovered_node.and_then(|id| self.nodes.iter().find(|node| node.id == id))
+            && !ui.input(|i| i.pointer.any_down())
+        {
+            egui::show_tooltip_at_pointer(ui.ctx(), ui.id().with("node_tooltip"), |ui| {
+                node.show_summary(ui);
+            });
+        }
+
+        // Alignment guides of the node being dragged, through the centers
+        // of the nodes it lines up with:
+        let guide_stroke = egui::Stroke::new(1.0, egui::Color32::from_rgb(0, 200, 255));
+        for [from, to] in guides {
+            let offset = NODE_SIZE / 2.0;
+            ui.painter().extend(egui::Shape::dashed_line(
+                &[transform(from + offset), transform(to + offset)],
+                guide_stroke,
+                6.0,
+                4.0,
+            ));
+        }
+
+        self.pan_offset += pan_delta;
     }
 
-    /// Draw a minimap in the given UI, showing nodes, connections, and a red camera rectangle.
-    pub fn draw_minimap(&self, ui: &mut egui::Ui) {
-        let mut min = egui::pos2(f32::INFINITY, f32::INFINITY);
-        let mut max = egui::pos2(f32::NEG_INFINITY, f32::NEG_INFINITY);
-        for node in &self.nodes {
-            min.x = min.x.min(node.pos.x);
-            min.y = min.y.min(node.pos.y);
-            max.x = max.x.max(node.pos.x);
-            max.y = max.y.max(node.pos.y);
-        }
-        let padding = egui::vec2(50.0, 50.0);
-        min -= padding;
-        max += padding;
-        let world_rect = egui::Rect::from_min_max(min, max);
-
-        let minimap_size = egui::vec2(200.0, 150.0);
-        let minimap_rect =
-            egui::Rect::from_min_size(ui.min_rect().min + egui::vec2(10.0, 10.0), minimap_size);
+    /// Grid lines every `GRID_SIZE` world units, thinned out when zoomed
+    /// out so they stay apart; every fifth line is brighter.
+    fn draw_grid(&self, painter: &egui::Painter, rect: egui::Rect) {
+        let mut spacing = GRID_SIZE;
+        while spacing * self.zoom < 8.0 {
+            spacing *= 5.0;
+        }
+        let minor = egui::Stroke::new(1.0, egui::Color32::from_gray(40));
+        let major = egui::Stroke::new(1.0, egui::Color32::from_gray(58));
+        let camera = self.camera_rect();
+        let lines = |min: f32, max: f32| (min / spacing).floor() as i64..=(max / spacing).ceil() as i64;
+        for i in lines(camera.min.x, camera.max.x) {
+            let x = i as f32 * spacing * self.zoom + self.pan_offset.x;
+            let stroke = if i % 5 == 0 { major } else { minor };
+            painter.line_segment([egui::pos2(x, rect.top()), egui::pos2(x, rect.bottom())], stroke);
+        }
+        for i in lines(camera.min.y, camera.max.y) {
+            let y = i as f32 * spacing * self.zoom + self.pan_offset.y;
+            let stroke = if i % 5 == 0 { major } else { minor };
+            painter.line_segment([egui::pos2(rect.left(), y), egui::pos2(rect.right(), y)], stroke);
+        }
+    }
+
+    /// Draw a minimap of the given size, showing nodes, connections, and a
+    /// red camera rectangle. Clicking the map centers the camera there;
+    /// dragging the camera rectangle pans. Returns the map's area.
+    pub fn draw_minimap(&mut self, ui: &mut egui::Ui, minimap_size: egui::Vec2) -> egui::Rect {
+        let (minimap_rect, response) = ui.allocate_exact_size(minimap_size, egui::Sense::click_and_drag());
         ui.painter()
             .rect_filled(minimap_rect, 3.0, egui::Color32::DARK_GRAY);
 
+        // The map shows every node and the camera, so the camera rectangle
+        // can always be grabbed, even on an empty chart.
+        let camera_rect = self.camera_rect();
+        let world_rect = self
+            .nodes
+            .iter()
+            .map(|node| egui::Rect::from_min_size(node.pos, NODE_SIZE))
+            .fold(camera_rect, |a, b| a.union(b))
+            .expand(50.0);
+
         let world_size = world_rect.size();
         let scale_x = minimap_size.x / world_size.x;
         let scale_y = minimap_size.y / world_size.y;
         let minimap_scale = scale_x.min(scale_y);
         let extra_space = minimap_size - world_size * minimap_scale;
         let offset = extra_space * 0.5;
-
-        for node in &self.nodes {
-            let minimap_pos =
-                minimap_rect.min + offset + (node.pos - world_rect.min) * minimap_scale;
-            let node_size = egui::vec2(20.0, 10.0);
-            let node_rect = egui::Rect::from_center_size(minimap_pos, node_size);
-            ui.painter().rect_filled(node_rect, 2.0, egui::Color32::LIGHT_BLUE);
-        }
+        let to_minimap = |world: egui::Pos2| minimap_rect.min + offset + (world - world_rect.min) * minimap_scale;
+        let to_world = |minimap: egui::Pos2| world_rect.min + (minimap - minimap_rect.min - offset) / minimap_scale;
 
         for conn in &self.connections {
             let from_node = self.nodes.iter().find(|n| n.id == conn.from);
             let to_node = self.nodes.iter().find(|n| n.id == conn.to);
             if let (Some(from), Some(to)) = (from_node, to_node) {
-                let from_minimap =
-                    minimap_rect.min + offset + (from.pos - world_rect.min) * minimap_scale;
-                let to_minimap =
-                    minimap_rect.min + offset + (to.pos - world_rect.min) * minimap_scale;
                 ui.painter().line_segment(
-                    [from_minimap, to_minimap],
+                    [to_minimap(from.pos + NODE_SIZE / 2.0), to_minimap(to.pos + NODE_SIZE / 2.0)],
                     egui::Stroke::new(1.0, egui::Color32::WHITE),
                 );
             }
         }
 
-        if let Some(panel_size) = self.main_view_rect_size {
-            let camera_min_vec = -self.pan_offset / self.zoom;
-            let camera_max_vec = camera_min_vec + (panel_size / self.zoom);
-            let mut camera_rect = egui::Rect::from_min_max(
-                egui::pos2(camera_min_vec.x, camera_min_vec.y),
-                egui::pos2(camera_max_vec.x, camera_max_vec.y),
-            );
-            camera_rect = camera_rect.intersect(world_rect);
-
-            let minimap_viewport_min = minimap_rect.min
-                + offset
-                + (camera_rect.min - world_rect.min) * minimap_scale;
-            let minimap_viewport_max = minimap_rect.min
-                + offset
-                + (camera_rect.max - world_rect.min) * minimap_scale;
-            let minimap_viewport =
-                egui::Rect::from_min_max(minimap_viewport_min, minimap_viewport_max);
-
-            ui.painter().rect_stroke(
-                minimap_viewport,
-                2.0,
-                egui::Stroke::new(1.0, egui::Color32::RED),
-            );
-        }
-    }
-}
\ No newline at end of file
+        for node in &self.nodes {
+            let node_size = (NODE_SIZE * minimap_scale).max(egui::vec2(4.0, 3.0));
+            let node_rect = egui::Rect::from_min_size(to_minimap(node.pos), node_size);
+            let fill = match node.status {
+                NodeStatus::Idle => egui::Color32::LIGHT_BLUE,
+                status => status.color(),
+            };
+            ui.painter().rect_filled(node_rect, 2.0, fill);
+            if self.selected_node == Some(node.id) || self.selection.contains(&node.id) {
+                ui.painter()
+                    .rect_stroke(node_rect.expand(1.5), 2.0, egui::Stroke::new(1.5, egui::Color32::YELLOW));
+            }
+        }
+
+        let minimap_viewport = egui::Rect::from_min_max(to_minimap(camera_rect.min), to_minimap(camera_rect.max));
+        ui.painter().rect_stroke(
+            minimap_viewport,
+            2.0,
+            egui::Stroke::new(1.0, egui::Color32::RED),
+        );
+
+        // Whether the current drag grabbed the camera rectangle, rather than
+        // dragging the camera's center around.
+        let grab_id = response.id.with("grab");
+        if response.drag_started()
+            && let Some(pointer) = response.interact_pointer_pos()
+        {
+            ui.data_mut(|d| d.insert_temp(grab_id, minimap_viewport.contains(pointer)));
+        }
+        let grabbed = ui.data(|d| d.get_temp::<bool>(grab_id)).unwrap_or(false);
+        if response.dragged() && grabbed {
+            self.pan_offset -= response.drag_delta() / minimap_scale * self.zoom;
+        } else if (response.dragged() || response.clicked())
+            && let Some(pointer) = response.interact_pointer_pos()
+        {
+            self.center_on(to_world(pointer));
+        }
+        minimap_rect
+    }
+}
+
+/// Short form of a run time, e.g. `85 ms` or `2.4 s`.
+fn format_runtime(runtime: Duration) -> String {
+    if runtime.as_secs_f32() < 1.0 {
+        format!("{} ms", runtime.as_millis())
+    } else {
+        format!("{:.1} s", runtime.as_secs_f32())
+    }
+}
+
+/// A side of a node, where edges leave or enter it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+impl Side {
+    /// Unit vector pointing out of the node through this side.
+    fn normal(self) -> egui::Vec2 {
+        match self {
+            Side::Left => egui::vec2(-1.0, 0.0),
+            Side::Right => egui::vec2(1.0, 0.0),
+            Side::Top => egui::vec2(0.0, -1.0),
+            Side::Bottom => egui::vec2(0.0, 1.0),
+        }
+    }
+}
+
+/// The sides an edge from `from` to `to` leaves and enters by: top and
+/// bottom when the nodes are further apart vertically than horizontally,
+/// left and right otherwise.
+fn facing_sides(from: egui::Rect, to: egui::Rect) -> (Side, Side) {
+    let offset = to.center() - from.center();
+    if offset.y.abs() > offset.x.abs() {
+        if offset.y > 0.0 { (Side::Bottom, Side::Top) } else { (Side::Top, Side::Bottom) }
+    } else if offset.x >= 0.0 {
+        (Side::Right, Side::Left)
+    } else {
+        (Side::Left, Side::Right)
+    }
+}
+
+/// Where port `index` of `count` sits on a side of a node, spread evenly
+/// along it.
+fn port_anchor(rect: egui::Rect, side: Side, index: usize, count: usize) -> egui::Pos2 {
+    let fraction = (index + 1) as f32 / (count + 1) as f32;
+    match side {
+        Side::Left => egui::pos2(rect.left(), rect.top() + rect.height() * fraction),
+        Side::Right => egui::pos2(rect.right(), rect.top() + rect.height() * fraction),
+        Side::Top => egui::pos2(rect.left() + rect.width() * fraction, rect.top()),
+        Side::Bottom => egui::pos2(rect.left() + rect.width() * fraction, rect.bottom()),
+    }
+}
+
+/// An edge leaving a node at `from` through the given side and entering
+/// another at `to` through the given side.
+fn edge_curve(
+    (from, from_side): (egui::Pos2, Side),
+    (to, to_side): (egui::Pos2, Side),
+    zoom: f32,
+    stroke: egui::Stroke,
+) -> egui::epaint::CubicBezierShape {
+    let bend = |side: Side| {
+        let distance = (to - from).dot(side.normal()).abs();
+        side.normal() * (distance / 2.0).max(40.0 * zoom)
+    };
+    egui::epaint::CubicBezierShape::from_points_stroke(
+        [from, from + bend(from_side), to + bend(to_side), to],
+        false,
+        egui::Color32::TRANSPARENT,
+        stroke,
+    )
+}
+
+/// Distance from `point` to the nearest segment of `points`.
+fn distance_to_polyline(point: egui::Pos2, points: &[egui::Pos2]) -> f32 {
+    points
+        .windows(2)
+        .map(|segment| {
+            let (a, b) = (segment[0], segment[1]);
+            let along = b - a;
+            let t = ((point - a).dot(along) / along.length_sq().max(f32::EPSILON)).clamp(0.0, 1.0);
+            point.distance(a + along * t)
+        })
+        .fold(f32::INFINITY, f32::min)
+}
+
+/// Rounds a world coordinate to the grid.
+fn snap(value: f32) -> f32 {
+    (value / GRID_SIZE).round() * GRID_SIZE
+}
+
+/// The nearest of `others` in the same column as a node at `pos`, and the
+/// nearest in the same row, within `distance`. Nodes all have the same
+/// size, so lining up their positions lines up their edges and centers.
+fn alignment_partners(
+    pos: egui::Pos2,
+    others: impl Iterator<Item = egui::Pos2> + Clone,
+    distance: f32,
+) -> (Option<egui::Pos2>, Option<egui::Pos2>) {
+    let nearest = |axis: fn(egui::Pos2) -> f32| {
+        others
+            .clone()
+            .filter(|other| (axis(*other) - axis(pos)).abs() <= distance)
+            .min_by(|a, b| (axis(*a) - axis(pos)).abs().total_cmp(&(axis(*b) - axis(pos)).abs()))
+    };
+    (nearest(|p| p.x), nearest(|p| p.y))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A flow of command nodes, with IDs from 1, and data edges between them.
+    pub(crate) fn flow(commands: &[&str], edges: &[(usize, usize)]) -> FlowChart {
+        let mut chart = FlowChart::default();
+        for command in commands {
+            chart.add_node_of_type(NodeType::Command, command);
+        }
+        for &(from, to) in edges {
+            chart.connections.push(Connection {
+                from,
+                to,
+                kind: ConnectionKind::Data,
+                from_port: 0,
+                to_port: 0,
+            });
+        }
+        chart
+    }
+
+    pub(crate) fn run(chart: &mut FlowChart, scope: RunScope) -> Result<Vec<u8>, String> {
+        chart.start_run(&scope, false)?;
+        chart.wait_for_run()
+    }
+
+    fn statuses(chart: &FlowChart) -> Vec<NodeStatus> {
+        chart.nodes.iter().map(|node| node.status).collect()
+    }
+
+    fn output(chart: &FlowChart, id: usize) -> &[u8] {
+        &chart.node_at_path(&[id]).unwrap().output
+    }
+
+    #[test]
+    fn running_up_to_a_node_leaves_its_downstream_alone() {
+        let mut chart = flow(&["echo a", "tr a b", "tr b c"], &[(1, 2), (2, 3)]);
+        assert_eq!(run(&mut chart, RunScope::UpTo(vec![2])).unwrap(), b"b\n");
+        use NodeStatus::*;
+        assert_eq!(statuses(&chart), [Succeeded, Succeeded, Idle]);
+    }
+
+    #[test]
+    fn running_from_a_node_feeds_it_the_stored_outputs_above() {
+        let mut chart = flow(&["echo a", "tr a b", "tr b c"], &[(1, 2), (2, 3)]);
+        run(&mut chart, RunScope::All).unwrap();
+        chart.nodes[0].command = "echo changed".into();
+        chart.nodes[1].command = "tr a B".into();
+        assert_eq!(run(&mut chart, RunScope::From(vec![2])).unwrap(), b"B\n");
+        assert_eq!(output(&chart, 1), b"a\n");
+        assert_eq!(output(&chart, 2), b"B\n");
+    }
+
+    #[test]
+    fn an_isolated_run_ignores_upstream_nodes() {
+        let mut chart = flow(&["echo a", "tr x y"], &[(1, 2)]);
+        chart.start_isolated_run(&[2], b"x\n".to_vec()).unwrap();
+        assert_eq!(chart.wait_for_run().unwrap(), b"y\n");
+        use NodeStatus::*;
+        assert_eq!(statuses(&chart), [Idle, Succeeded]);
+        assert!(chart.start_isolated_run(&[7], Vec::new()).is_err());
+    }
+
+    fn connect(chart: &mut FlowChart, from: usize, to: usize, kind: ConnectionKind) {
+        chart.connections.push(Connection {
+            from,
+            to,
+            kind,
+            from_port: 0,
+            to_port: 0,
+        });
+    }
+
+    #[test]
+    fn control_edges_pick_the_branch_by_exit_status() {
+        let mut chart = flow(&["echo out; exit 3", "echo handled", "echo fine"], &[]);
+        connect(&mut chart, 1, 2, ConnectionKind::OnFailure);
+        connect(&mut chart, 1, 3, ConnectionKind::OnSuccess);
+        // The failure is handled by its on-failure edge, so the run succeeds.
+        assert_eq!(run(&mut chart, RunScope::All).unwrap(), b"handled\n");
+        use NodeStatus::*;
+        assert_eq!(statuses(&chart), [Failed, Succeeded, Skipped]);
+
+        chart.nodes[0].success_codes = vec![0, 3];
+        assert_eq!(run(&mut chart, RunScope::All).unwrap(), b"fine\n");
+        assert_eq!(statuses(&chart), [Succeeded, Skipped, Succeeded]);
+    }
+
+    #[test]
+    fn unhandled_failures_fail_the_run_and_skip_what_reads_them() {
+        let mut chart = flow(&["exit 2", "cat"], &[(1, 2)]);
+        let error = run(&mut chart, RunScope::All).unwrap_err();
+        assert!(error.contains("exited with code 2"), "{}", error);
+        use NodeStatus::*;
+        assert_eq!(statuses(&chart), [Failed, Skipped]);
+    }
+
+    fn close(a: egui::Pos2, b: egui::Pos2) -> bool {
+        a.distance(b) < 0.01
+    }
+
+    #[test]
+    fn zooming_keeps_the_point_under_the_pointer_in_place() {
+        let mut chart = FlowChart {
+            pan_offset: egui::vec2(30.0, -20.0),
+            ..FlowChart::default()
+        };
+        let anchor = egui::pos2(200.0, 150.0);
+        let world = |chart: &FlowChart| ((anchor - chart.pan_offset).to_vec2() / chart.zoom).to_pos2();
+        let before = world(&chart);
+        chart.zoom_around(1.5, anchor);
+        assert_eq!(chart.zoom, 1.5);
+        assert!(close(world(&chart), before));
+        chart.zoom_around(100.0, anchor);
+        assert_eq!(chart.zoom, MAX_ZOOM);
+        assert!(close(world(&chart), before));
+        chart.zoom_around(0.0, anchor);
+        assert_eq!(chart.zoom, MIN_ZOOM);
+    }
+
+    #[test]
+    fn zoom_to_fit_shows_every_node() {
+        let mut chart = flow(&["a", "b", "c"], &[]);
+        for (node, pos) in chart.nodes.iter_mut().zip([(-500.0, 0.0), (900.0, 40.0), (100.0, 1200.0)]) {
+            node.pos = egui::pos2(pos.0, pos.1);
+        }
+        chart.zoom_to_fit();
+        let camera = chart.camera_rect();
+        for node in &chart.nodes {
+            assert!(camera.contains_rect(egui::Rect::from_min_size(node.pos, NODE_SIZE)), "{:?}", node.pos);
+        }
+        assert!(chart.zoom < 1.0);
+    }
+
+    #[test]
+    fn zoom_to_selection_centers_the_selected_node() {
+        let mut chart = flow(&["a", "b"], &[]);
+        chart.nodes[1].pos = egui::pos2(2000.0, 2000.0);
+        chart.selected_node = Some(2);
+        chart.zoom_to_selection();
+        let node = egui::Rect::from_min_size(chart.nodes[1].pos, NODE_SIZE);
+        assert!(close(chart.camera_rect().center(), node.center()));
+        assert!(!chart.camera_rect().contains(chart.nodes[0].pos));
+
+        chart.reset_view();
+        assert_eq!(chart.zoom, 1.0);
+        assert_eq!(chart.camera_rect().min, egui::Pos2::ZERO);
+    }
+
+    #[test]
+    fn centering_on_a_minimap_point_moves_the_camera_there() {
+        let mut chart = FlowChart {
+            main_view_rect: Some(egui::Rect::from_min_size(egui::pos2(200.0, 50.0), egui::vec2(600.0, 400.0))),
+            ..FlowChart::default()
+        };
+        for zoom in [0.25, 1.0, 3.0] {
+            chart.zoom = zoom;
+            chart.center_on(egui::pos2(-350.0, 720.0));
+            assert!(close(chart.camera_rect().center(), egui::pos2(-350.0, 720.0)), "at zoom {}", zoom);
+            assert!((chart.camera_rect().width() - 600.0 / zoom).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn snapping_rounds_to_the_nearest_grid_line() {
+        assert_eq!(snap(29.0), 20.0);
+        assert_eq!(snap(31.0), 40.0);
+        assert_eq!(snap(-9.0), 0.0);
+        assert_eq!(snap(-11.0), -20.0);
+    }
+
+    #[test]
+    fn alignment_picks_the_nearest_node_per_axis() {
+        let others = [egui::pos2(100.0, 500.0), egui::pos2(104.0, 0.0), egui::pos2(900.0, 203.0)];
+        let (column, row) = alignment_partners(egui::pos2(103.0, 200.0), others.into_iter(), 8.0);
+        assert_eq!(column, Some(egui::pos2(104.0, 0.0)));
+        assert_eq!(row, Some(egui::pos2(900.0, 203.0)));
+        let (column, row) = alignment_partners(egui::pos2(300.0, 300.0), others.into_iter(), 8.0);
+        assert_eq!((column, row), (None, None));
+    }
+
+    #[test]
+    fn output_badges_count_lines_and_bytes() {
+        let mut node = Node::new(1, NodeType::Command, String::new());
+        node.set_output(b"a\nb".to_vec());
+        assert_eq!(node.output_size(), "2 lines, 3 bytes");
+        node.set_output(b"a\n".to_vec());
+        assert_eq!(node.output_size(), "1 line, 2 bytes");
+        assert!(node.output_is_text);
+        node.set_output(vec![0xff; 2048]);
+        assert_eq!(node.output_size(), "1 line, 2.0 KiB");
+        assert!(!node.output_is_text);
+        node.set_output(Vec::new());
+        assert_eq!(node.output_size(), "0 lines, 0 bytes");
+    }
+
+    #[test]
+    fn runtimes_add_up_every_attempt() {
+        let mut node = Node::new(1, NodeType::Command, String::new());
+        for millis in [300, 900] {
+            node.attempts.push(Attempt {
+                duration: Duration::from_millis(millis),
+                ..Attempt::default()
+            });
+        }
+        assert_eq!(format_runtime(node.runtime()), "1.2 s");
+        assert_eq!(format_runtime(Duration::from_micros(85_400)), "85 ms");
+    }
+
+    fn node_at(x: f32, y: f32) -> egui::Rect {
+        egui::Rect::from_min_size(egui::pos2(x, y), NODE_SIZE)
+    }
+
+    #[test]
+    fn edges_face_the_other_node() {
+        let origin = node_at(0.0, 0.0);
+        assert_eq!(facing_sides(origin, node_at(200.0, 30.0)), (Side::Right, Side::Left));
+        assert_eq!(facing_sides(origin, node_at(-200.0, 30.0)), (Side::Left, Side::Right));
+        assert_eq!(facing_sides(origin, node_at(20.0, 100.0)), (Side::Bottom, Side::Top));
+        assert_eq!(facing_sides(origin, node_at(-20.0, -100.0)), (Side::Top, Side::Bottom));
+    }
+
+    #[test]
+    fn ports_spread_along_their_side() {
+        let rect = node_at(0.0, 0.0);
+        assert_eq!(port_anchor(rect, Side::Right, 0, 1), egui::pos2(120.0, 25.0));
+        assert_eq!(port_anchor(rect, Side::Left, 1, 4), egui::pos2(0.0, 20.0));
+        assert_eq!(port_anchor(rect, Side::Top, 0, 2), egui::pos2(40.0, 0.0));
+        assert_eq!(port_anchor(rect, Side::Bottom, 1, 2), egui::pos2(80.0, 50.0));
+    }
+
+    #[test]
+    fn curves_bend_out_of_the_sides_they_use() {
+        let from = egui::pos2(60.0, 50.0);
+        let to = egui::pos2(60.0, 250.0);
+        let curve = edge_curve((from, Side::Bottom), (to, Side::Top), 1.0, egui::Stroke::NONE);
+        assert_eq!(curve.points[1], egui::pos2(60.0, 150.0));
+        assert_eq!(curve.points[2], egui::pos2(60.0, 150.0));
+
+        // Close nodes still get a visible bend.
+        let to = egui::pos2(130.0, 25.0);
+        let curve = edge_curve((egui::pos2(120.0, 25.0), Side::Right), (to, Side::Left), 1.0, egui::Stroke::NONE);
+        assert_eq!(curve.points[1], egui::pos2(160.0, 25.0));
+        assert_eq!(curve.points[2], egui::pos2(90.0, 25.0));
+    }
+}
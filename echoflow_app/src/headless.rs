@@ -0,0 +1,38 @@
+use crate::executor::NodeType;
+use crate::flowchart::{FlowChart, RunScope};
+use crate::io_nodes::Sink;
+use std::io::Write;
+use std::path::Path;
+use std::process::ExitCode;
+
+/// Runs a saved flow to completion without opening a window, for use in
+/// scripts: `echoflow_app --run flow.json < input > output`. Stdout sinks
+/// write to our stdout as they run; a flow without any gets its final
+/// output printed instead. Errors go to stderr with a failing exit status.
+pub fn run(path: &Path) -> ExitCode {
+    let mut chart = match FlowChart::load_from_file(path) {
+        Ok(chart) => chart,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let has_stdout_sink =
+        chart.any_node(&|node| matches!(node.node_type, NodeType::Sink(Sink::Stdout)));
+    match chart
+        .start_run(&RunScope::All, false)
+        .and_then(|()| chart.wait_for_run())
+    {
+        Ok(output) => {
+            if !has_stdout_sink && let Err(e) = std::io::stdout().write_all(&output) {
+                eprintln!("Failed to write to stdout: {}", e);
+                return ExitCode::FAILURE;
+            }
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,375 @@
+use crate::executor::Attempt;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{IsTerminal, Read, Write};
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Where a source node gets its data from. Sources have no inputs.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Source {
+    /// The contents of one file.
+    File { path: String },
+    /// The contents of every file matching a glob pattern, in path order.
+    Glob { pattern: String },
+    /// Everything piped into the echoflow process.
+    Stdin,
+    /// A block of text typed into (or pasted into) the node, fed downstream
+    /// byte for byte: no trailing newline is added and no quoting applies.
+    Text { text: String },
+}
+
+/// Where a sink node puts its input. Sinks pass their input on unchanged,
+/// so they can sit in the middle of a flow (like `tee`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Sink {
+    /// Create or truncate the file (`> path`).
+    Write { path: String },
+    /// Add to the end of the file (`>> path`).
+    Append { path: String },
+    /// Write a temporary file next to the target and rename it over the
+    /// target, so readers never see a half-written file.
+    Replace { path: String },
+    /// The stdout of the echoflow process, for headless runs.
+    Stdout,
+}
+
+impl Source {
+    /// One of each kind, with empty settings, for pickers.
+    pub fn choices() -> [Source; 4] {
+        [
+            Source::File {
+                path: String::new(),
+            },
+            Source::Glob {
+                pattern: String::new(),
+            },
+            Source::Stdin,
+            Source::Text {
+                text: String::new(),
+            },
+        ]
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Source::File { .. } => "File",
+            Source::Glob { .. } => "Glob of files",
+            Source::Stdin => "Standard input",
+            Source::Text { .. } => "Text block",
+        }
+    }
+
+    /// Short description shown on the node.
+    pub fn summary(&self) -> String {
+        match self {
+            Source::File { path } => format!("read: {}", path),
+            Source::Glob { pattern } => format!("glob: {}", pattern),
+            Source::Stdin => "stdin".into(),
+            Source::Text { text } => {
+                let first: String = text.lines().next().unwrap_or_default().chars().take(20).collect();
+                let first = first.trim_end();
+                match text.lines().count() {
+                    0 => "text: (empty)".into(),
+                    1 => format!("text: {}", first),
+                    lines => format!("text: {} (+{} lines)", first, lines - 1),
+                }
+            }
+        }
+    }
+
+    /// Fails if the source has nothing to read from.
+    pub fn check(&self) -> Result<(), String> {
+        self.check_settings()?;
+        self.check_files()
+    }
+
+    /// The part of `check` that only looks at the settings, so its result
+    /// holds until they change.
+    pub fn check_settings(&self) -> Result<(), String> {
+        match self {
+            Source::File { path } if path.is_empty() => Err("No input file is set.".into()),
+            Source::Glob { pattern } => glob::Pattern::new(pattern)
+                .map(|_| ())
+                .map_err(|e| format!("Invalid glob '{}': {}", pattern, e)),
+            _ => Ok(()),
+        }
+    }
+
+    /// The part of `check` that looks at the files and stdin, which can
+    /// change at any time.
+    pub fn check_files(&self) -> Result<(), String> {
+        match self {
+            Source::File { path } => {
+                if Path::new(path).is_file() {
+                    Ok(())
+                } else {
+                    Err(format!("Input file '{}' does not exist.", path))
+                }
+            }
+            Source::Glob { pattern } => {
+                if matching_files(pattern)?.is_empty() {
+                    Err(format!("No file matches '{}'.", pattern))
+                } else {
+                    Ok(())
+                }
+            }
+            Source::Stdin => {
+                if std::io::stdin().is_terminal() {
+                    Err("Nothing is piped into echoflow's standard input.".into())
+                } else {
+                    Ok(())
+                }
+            }
+            Source::Text { .. } => Ok(()),
+        }
+    }
+
+    pub fn read(&self) -> Result<Attempt, String> {
+        let data = match self {
+            Source::File { path } => read_file(Path::new(path))?,
+            Source::Glob { pattern } => {
+                let mut data = Vec::new();
+                for path in matching_files(pattern)? {
+                    data.extend(read_file(&path)?);
+                }
+                data
+            }
+            Source::Stdin => process_stdin()?.to_vec(),
+            Source::Text { text } => text.clone().into_bytes(),
+        };
+        Ok(Attempt {
+            exit_code: Some(0),
+            stdout: data,
+            ..Attempt::default()
+        })
+    }
+}
+
+impl Sink {
+    /// One of each kind, with empty settings, for pickers.
+    pub fn choices() -> [Sink; 4] {
+        [
+            Sink::Write {
+                path: String::new(),
+            },
+            Sink::Append {
+                path: String::new(),
+            },
+            Sink::Replace {
+                path: String::new(),
+            },
+            Sink::Stdout,
+        ]
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Sink::Write { .. } => "Write file",
+            Sink::Append { .. } => "Append to file",
+            Sink::Replace { .. } => "Replace file atomically",
+            Sink::Stdout => "Standard output",
+        }
+    }
+
+    /// Short description shown on the node.
+    pub fn summary(&self) -> String {
+        match self {
+            Sink::Write { path } => format!("write: {}", path),
+            Sink::Append { path } => format!("append: {}", path),
+            Sink::Replace { path } => format!("replace: {}", path),
+            Sink::Stdout => "stdout".into(),
+        }
+    }
+
+    /// Fails if the target file cannot be created because its directory is missing.
+    pub fn check(&self) -> Result<(), String> {
+        self.check_settings()?;
+        self.check_files()
+    }
+
+    /// The part of `check` that only looks at the settings.
+    pub fn check_settings(&self) -> Result<(), String> {
+        match self {
+            Sink::Write { path } | Sink::Append { path } | Sink::Replace { path } if path.is_empty() => {
+                Err("No output file is set.".into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The part of `check` that looks at the filesystem.
+    pub fn check_files(&self) -> Result<(), String> {
+        let (Sink::Write { path } | Sink::Append { path } | Sink::Replace { path }) = self else {
+            return Ok(());
+        };
+        match Path::new(path).parent() {
+            Some(dir) if !dir.as_os_str().is_empty() && !dir.is_dir() => {
+                Err(format!("Output directory '{}' does not exist.", dir.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn write(&self, input: &[u8]) -> Result<Attempt, String> {
+        match self {
+            Sink::Write { path } => fs::write(path, input)
+                .map_err(|e| format!("Failed to write '{}': {}", path, e))?,
+            Sink::Append { path } => OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| file.write_all(input))
+                .map_err(|e| format!("Failed to append to '{}': {}", path, e))?,
+            Sink::Replace { path } => replace_file(Path::new(path), input)
+                .map_err(|e| format!("Failed to replace '{}': {}", path, e))?,
+            Sink::Stdout => {
+                let mut stdout = std::io::stdout().lock();
+                stdout
+                    .write_all(input)
+                    .and_then(|()| stdout.flush())
+                    .map_err(|e| format!("Failed to write to stdout: {}", e))?;
+            }
+        }
+        Ok(Attempt {
+            exit_code: Some(0),
+            stdout: input.to_vec(),
+            ..Attempt::default()
+        })
+    }
+}
+
+/// Text currently on the system clipboard.
+pub fn clipboard_text() -> Result<String, String> {
+    arboard::Clipboard::new()
+        .and_then(|mut clipboard| clipboard.get_text())
+        .map_err(|e| format!("Failed to read the clipboard: {}", e))
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|e| format!("Failed to read '{}': {}", path.display(), e))
+}
+
+fn matching_files(pattern: &str) -> Result<Vec<std::path::PathBuf>, String> {
+    let paths = glob::glob(pattern).map_err(|e| format!("Invalid glob '{}': {}", pattern, e))?;
+    let mut files: Vec<_> = paths.filter_map(Result::ok).filter(|p| p.is_file()).collect();
+    files.sort();
+    Ok(files)
+}
+
+/// The process's stdin, read to the end on first use so every stdin source
+/// of every run sees the same data.
+fn process_stdin() -> Result<&'static [u8], String> {
+    static STDIN: OnceLock<Result<Vec<u8>, String>> = OnceLock::new();
+    STDIN
+        .get_or_init(|| {
+            let mut data = Vec::new();
+            std::io::stdin()
+                .read_to_end(&mut data)
+                .map(|_| data)
+                .map_err(|e| format!("Failed to read stdin: {}", e))
+        })
+        .as_ref()
+        .map(Vec::as_slice)
+        .map_err(Clone::clone)
+}
+
+fn replace_file(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = path.with_file_name(format!(".{}.echoflow-{}", name, std::process::id()));
+    let written = fs::write(&temp, data).and_then(|()| fs::rename(&temp, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    written
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// An empty directory of the test's own under the system temp dir.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("echoflow-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn text(path: &Path) -> String {
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn a_file_source_passes_its_check_once_the_file_exists() {
+        let dir = scratch_dir("source-check");
+        let path = dir.join("in.txt");
+        let source = Source::File { path: text(&path) };
+        assert_eq!(source.check_settings(), Ok(()));
+        assert!(source.check().unwrap_err().contains("does not exist"));
+        fs::write(&path, "data").unwrap();
+        assert_eq!(source.check(), Ok(()));
+        assert_eq!(source.read().unwrap().stdout, b"data");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_glob_source_reads_matches_in_path_order() {
+        let dir = scratch_dir("glob");
+        let source = Source::Glob {
+            pattern: text(&dir.join("*.log")),
+        };
+        assert!(source.check().unwrap_err().starts_with("No file matches"));
+        fs::write(dir.join("b.log"), "b\n").unwrap();
+        fs::write(dir.join("a.log"), "a\n").unwrap();
+        fs::write(dir.join("c.txt"), "c\n").unwrap();
+        assert_eq!(source.check(), Ok(()));
+        assert_eq!(source.read().unwrap().stdout, b"a\nb\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn settings_problems_are_found_without_the_filesystem() {
+        let glob = Source::Glob { pattern: "[".into() };
+        assert!(glob.check_settings().unwrap_err().starts_with("Invalid glob"));
+        let file = Source::File { path: String::new() };
+        assert_eq!(file.check_settings(), Err("No input file is set.".into()));
+        let sink = Sink::Append { path: String::new() };
+        assert_eq!(sink.check_settings(), Err("No output file is set.".into()));
+    }
+
+    #[test]
+    fn text_sources_are_fed_byte_for_byte() {
+        let source = Source::Text { text: "a\n  b".into() };
+        assert_eq!(source.read().unwrap().stdout, b"a\n  b");
+        assert_eq!(source.summary(), "text: a (+1 lines)");
+    }
+
+    #[test]
+    fn text_summaries_show_the_first_line() {
+        let summary = |text: &str| Source::Text { text: text.into() }.summary();
+        assert_eq!(summary(""), "text: (empty)");
+        assert_eq!(summary("hello\n"), "text: hello");
+        assert_eq!(summary("a fairly long first line of text\nb\nc"), "text: a fairly long first (+2 lines)");
+    }
+
+    #[test]
+    fn sinks_write_append_and_replace() {
+        let dir = scratch_dir("sinks");
+        let path = text(&dir.join("out.txt"));
+        let missing = Sink::Write {
+            path: text(&dir.join("nope").join("out.txt")),
+        };
+        assert!(missing.check().unwrap_err().starts_with("Output directory"));
+
+        let written = Sink::Write { path: path.clone() }.write(b"one\n").unwrap();
+        assert_eq!(written.stdout, b"one\n");
+        Sink::Append { path: path.clone() }.write(b"two\n").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"one\ntwo\n");
+        Sink::Replace { path: path.clone() }.write(b"three\n").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"three\n");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1, "no temporary file is left behind");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,576 @@
+use crate::executor::Attempt;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Settings of a JSON node: how to read its input, what to do with every
+/// value read, and how to write the results.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct JsonSettings {
+    pub input: JsonInput,
+    pub op: JsonOp,
+    pub output: JsonOutput,
+}
+
+/// How the input is cut into JSON values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum JsonInput {
+    /// A single document if the whole input parses, JSON Lines otherwise.
+    Auto,
+    Document,
+    /// One value per non-empty line.
+    Lines,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum JsonOutput {
+    /// One compact value per line, ready for the next JSON node.
+    Lines,
+    /// Every value pretty-printed.
+    Pretty,
+    /// All values gathered into one pretty-printed array.
+    Array,
+}
+
+/// What a JSON node does to each value. Filter, project and flatten work
+/// on every item when the value is an array, keeping it an array (like
+/// `map(...)` in jq).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum JsonOp {
+    /// Emit whatever a jq-like path selects, e.g. `.items[].name`.
+    Select { path: String },
+    /// Keep the items whose value at `path` compares to `value`, which is
+    /// read as JSON when it parses and as a string otherwise.
+    Filter {
+        path: String,
+        compare: Compare,
+        value: String,
+    },
+    /// Keep only the listed fields (comma-separated paths like `owner.login`).
+    Project { fields: String },
+    /// Turn nested objects and arrays into a single level of
+    /// `parent<separator>child` keys.
+    Flatten { separator: String },
+    /// Re-serialize the values unchanged.
+    Format,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Compare {
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    /// Substring of a string or element of an array.
+    Contains,
+    /// The path leads to a value other than null.
+    Exists,
+}
+
+impl Default for JsonSettings {
+    fn default() -> Self {
+        Self {
+            input: JsonInput::Auto,
+            op: JsonOp::Select { path: ".".into() },
+            output: JsonOutput::Lines,
+        }
+    }
+}
+
+impl JsonInput {
+    pub const ALL: [JsonInput; 3] = [JsonInput::Auto, JsonInput::Document, JsonInput::Lines];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            JsonInput::Auto => "Detect",
+            JsonInput::Document => "One document",
+            JsonInput::Lines => "JSON Lines",
+        }
+    }
+}
+
+impl JsonOutput {
+    pub const ALL: [JsonOutput; 3] = [JsonOutput::Lines, JsonOutput::Pretty, JsonOutput::Array];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            JsonOutput::Lines => "JSON Lines",
+            JsonOutput::Pretty => "Pretty",
+            JsonOutput::Array => "One array",
+        }
+    }
+}
+
+impl Compare {
+    pub const ALL: [Compare; 6] = [
+        Compare::Equal,
+        Compare::NotEqual,
+        Compare::Less,
+        Compare::Greater,
+        Compare::Contains,
+        Compare::Exists,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Compare::Equal => "==",
+            Compare::NotEqual => "!=",
+            Compare::Less => "<",
+            Compare::Greater => ">",
+            Compare::Contains => "contains",
+            Compare::Exists => "exists",
+        }
+    }
+
+    fn test(self, found: &Value, wanted: &Value) -> bool {
+        match self {
+            Compare::Equal => found == wanted,
+            Compare::NotEqual => found != wanted,
+            Compare::Less | Compare::Greater => {
+                let order = match (found.as_f64(), wanted.as_f64()) {
+                    (Some(a), Some(b)) => a.partial_cmp(&b),
+                    _ => match (found.as_str(), wanted.as_str()) {
+                        (Some(a), Some(b)) => Some(a.cmp(b)),
+                        _ => None,
+                    },
+                };
+                let want = if self == Compare::Less {
+                    std::cmp::Ordering::Less
+                } else {
+                    std::cmp::Ordering::Greater
+                };
+                order == Some(want)
+            }
+            Compare::Contains => match (found, wanted) {
+                (Value::String(text), Value::String(part)) => text.contains(part.as_str()),
+                (Value::Array(items), wanted) => items.contains(wanted),
+                _ => false,
+            },
+            Compare::Exists => !found.is_null(),
+        }
+    }
+}
+
+impl JsonOp {
+    /// One of each operation, with default settings, for pickers.
+    pub fn choices() -> [JsonOp; 5] {
+        [
+            JsonOp::Select { path: ".".into() },
+            JsonOp::Filter {
+                path: ".".into(),
+                compare: Compare::Equal,
+                value: String::new(),
+            },
+            JsonOp::Project {
+                fields: String::new(),
+            },
+            JsonOp::Flatten {
+                separator: ".".into(),
+            },
+            JsonOp::Format,
+        ]
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            JsonOp::Select { .. } => "Select",
+            JsonOp::Filter { .. } => "Filter",
+            JsonOp::Project { .. } => "Project fields",
+            JsonOp::Flatten { .. } => "Flatten",
+            JsonOp::Format => "Re-serialize",
+        }
+    }
+}
+
+impl JsonSettings {
+    /// Short description shown on the node.
+    pub fn summary(&self) -> String {
+        match &self.op {
+            JsonOp::Select { path } => format!("json: {}", path),
+            JsonOp::Filter {
+                path,
+                compare: Compare::Exists,
+                ..
+            } => format!("json where {} exists", path),
+            JsonOp::Filter {
+                path,
+                compare,
+                value,
+            } => format!("json where {} {} {}", path, compare.label(), value),
+            JsonOp::Project { fields } => format!("json {{{}}}", fields),
+            JsonOp::Flatten { .. } => "json flatten".into(),
+            JsonOp::Format => "json format".into(),
+        }
+    }
+
+    /// Fails if a path in the settings does not parse.
+    pub fn check(&self) -> Result<(), String> {
+        match &self.op {
+            JsonOp::Select { path } | JsonOp::Filter { path, .. } => parse_path(path).map(|_| ()),
+            JsonOp::Project { fields } => project_paths(fields).map(|_| ()),
+            JsonOp::Flatten { .. } | JsonOp::Format => Ok(()),
+        }
+    }
+
+    pub fn apply(&self, input: &[u8]) -> Result<Attempt, String> {
+        let values = parse_values(input, self.input)?;
+        let mut results = Vec::new();
+        match &self.op {
+            JsonOp::Select { path } => {
+                let path = parse_path(path)?;
+                for value in &values {
+                    results.extend(select(value, &path));
+                }
+            }
+            JsonOp::Filter {
+                path,
+                compare,
+                value,
+            } => {
+                let path = parse_path(path)?;
+                let wanted = serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.clone()));
+                let keep = |item: &Value| {
+                    let found = select(item, &path).into_iter().next().unwrap_or(Value::Null);
+                    compare.test(&found, &wanted)
+                };
+                for value in values {
+                    match value {
+                        Value::Array(items) => {
+                            results.push(Value::Array(items.into_iter().filter(|item| keep(item)).collect()))
+                        }
+                        value if keep(&value) => results.push(value),
+                        _ => {}
+                    }
+                }
+            }
+            JsonOp::Project { fields } => {
+                let fields = project_paths(fields)?;
+                results.extend(values.iter().map(|value| map_items(value, |item| project(item, &fields))));
+            }
+            JsonOp::Flatten { separator } => {
+                results.extend(values.iter().map(|value| {
+                    map_items(value, |item| {
+                        // Scalars and empty containers have nothing to
+                        // flatten and no key to put them under.
+                        if !has_children(item) {
+                            return item.clone();
+                        }
+                        let mut flat = Map::new();
+                        flatten_into(&mut flat, String::new(), item, separator);
+                        Value::Object(flat)
+                    })
+                }));
+            }
+            JsonOp::Format => results = values,
+        }
+        Ok(Attempt {
+            exit_code: Some(0),
+            stdout: write_values(&results, self.output),
+            ..Attempt::default()
+        })
+    }
+}
+
+/// Reads JSON values from bytes: a whole document or one value per line.
+pub fn parse_values(input: &[u8], mode: JsonInput) -> Result<Vec<Value>, String> {
+    let document = || {
+        serde_json::from_slice(input)
+            .map(|value| vec![value])
+            .map_err(|e| format!("Input is not valid JSON: {}", e))
+    };
+    let lines = || {
+        input
+            .split(|&b| b == b'\n')
+            .enumerate()
+            .filter(|(_, line)| !line.iter().all(u8::is_ascii_whitespace))
+            .map(|(index, line)| {
+                serde_json::from_slice(line)
+                    .map_err(|e| format!("Line {} is not valid JSON: {}", index + 1, e))
+            })
+            .collect()
+    };
+    match mode {
+        JsonInput::Document => document(),
+        JsonInput::Lines => lines(),
+        JsonInput::Auto => document().or_else(|_| lines()),
+    }
+}
+
+fn write_values(values: &[Value], output: JsonOutput) -> Vec<u8> {
+    let mut out = Vec::new();
+    match output {
+        JsonOutput::Lines => {
+            for value in values {
+                out.extend(value.to_string().into_bytes());
+                out.push(b'\n');
+            }
+        }
+        JsonOutput::Pretty => {
+            for value in values {
+                out.extend(serde_json::to_vec_pretty(value).unwrap_or_default());
+                out.push(b'\n');
+            }
+        }
+        JsonOutput::Array => {
+            out.extend(serde_json::to_vec_pretty(values).unwrap_or_default());
+            out.push(b'\n');
+        }
+    }
+    out
+}
+
+/// One step of a jq-like path.
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Key(String),
+    /// Array index; negative counts from the end.
+    Index(i64),
+    /// Every element of an array or every value of an object (`[]`).
+    Each,
+}
+
+/// Parses paths like `.`, `.a.b`, `.items[0]`, `.items[].name` or
+/// `.["odd key"]`. The leading dot is optional.
+fn parse_path(path: &str) -> Result<Vec<Step>, String> {
+    let invalid = |why: &str| format!("Invalid path '{}': {}", path, why);
+    let mut steps = Vec::new();
+    let mut rest = path.trim();
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('[') {
+            let after = after.trim_start();
+            // A quoted key may itself contain `]`, so find where the string
+            // ends before looking for the bracket.
+            let quoted = if after.starts_with('"') {
+                quoted_len(after).ok_or_else(|| invalid("unterminated quoted key"))?
+            } else {
+                0
+            };
+            let end = quoted + after[quoted..].find(']').ok_or_else(|| invalid("missing ]"))?;
+            let inner = after[..end].trim();
+            steps.push(if inner.is_empty() {
+                Step::Each
+            } else if quoted > 0 {
+                Step::Key(serde_json::from_str(inner).map_err(|_| invalid("bad quoted key"))?)
+            } else {
+                Step::Index(inner.parse().map_err(|_| invalid("index is not a number"))?)
+            });
+            rest = &after[end + 1..];
+        } else {
+            rest = rest.strip_prefix('.').unwrap_or(rest);
+            let end = rest.find(['.', '[']).unwrap_or(rest.len());
+            if end > 0 {
+                steps.push(Step::Key(rest[..end].to_string()));
+            } else if !rest.is_empty() && !rest.starts_with('[') {
+                return Err(invalid("empty key"));
+            }
+            rest = &rest[end..];
+        }
+    }
+    Ok(steps)
+}
+
+/// Length of the JSON string literal at the start of `text`, quotes
+/// included, or `None` if it is not closed.
+fn quoted_len(text: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in text.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return Some(i + 1),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Every value the path leads to; missing keys and indexes lead nowhere.
+fn select(value: &Value, path: &[Step]) -> Vec<Value> {
+    let mut current = vec![value.clone()];
+    for step in path {
+        current = current
+            .into_iter()
+            .flat_map(|value| match (step, value) {
+                (Step::Key(key), Value::Object(mut map)) => map.remove(key).into_iter().collect(),
+                (Step::Index(index), Value::Array(mut items)) => {
+                    let len = items.len() as i64;
+                    let index = if *index < 0 { len + index } else { *index };
+                    if (0..len).contains(&index) {
+                        vec![items.swap_remove(index as usize)]
+                    } else {
+                        Vec::new()
+                    }
+                }
+                (Step::Each, Value::Array(items)) => items,
+                (Step::Each, Value::Object(map)) => map.into_iter().map(|(_, v)| v).collect(),
+                _ => Vec::new(),
+            })
+            .collect();
+    }
+    current
+}
+
+fn project_paths(fields: &str) -> Result<Vec<(String, Vec<Step>)>, String> {
+    fields
+        .split(',')
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .map(|field| Ok((field.trim_start_matches('.').to_string(), parse_path(field)?)))
+        .collect()
+}
+
+fn project(item: &Value, fields: &[(String, Vec<Step>)]) -> Value {
+    let mut out = Map::new();
+    for (name, path) in fields {
+        let found = select(item, path).into_iter().next().unwrap_or(Value::Null);
+        out.insert(name.clone(), found);
+    }
+    Value::Object(out)
+}
+
+/// Applies `f` to every item of an array, or to the value itself otherwise.
+fn map_items(value: &Value, f: impl Fn(&Value) -> Value) -> Value {
+    match value {
+        Value::Array(items) => Value::Array(items.iter().map(f).collect()),
+        other => f(other),
+    }
+}
+
+fn has_children(value: &Value) -> bool {
+    match value {
+        Value::Object(map) => !map.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        _ => false,
+    }
+}
+
+fn flatten_into(out: &mut Map<String, Value>, prefix: String, value: &Value, separator: &str) {
+    let join = |key: &str| {
+        if prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}{}{}", prefix, separator, key)
+        }
+    };
+    match value {
+        Value::Object(map) if has_children(value) => {
+            for (key, child) in map {
+                flatten_into(out, join(key), child, separator);
+            }
+        }
+        Value::Array(items) if has_children(value) => {
+            for (index, child) in items.iter().enumerate() {
+                flatten_into(out, join(&index.to_string()), child, separator);
+            }
+        }
+        leaf => {
+            out.insert(prefix, leaf.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key: &str) -> Step {
+        Step::Key(key.into())
+    }
+
+    fn run(op: JsonOp, input: &str) -> String {
+        let settings = JsonSettings {
+            input: JsonInput::Auto,
+            op,
+            output: JsonOutput::Lines,
+        };
+        String::from_utf8(settings.apply(input.as_bytes()).unwrap().stdout).unwrap()
+    }
+
+    #[test]
+    fn parses_paths() {
+        assert_eq!(parse_path(".").unwrap(), vec![]);
+        assert_eq!(parse_path("").unwrap(), vec![]);
+        assert_eq!(parse_path("a.b").unwrap(), vec![key("a"), key("b")]);
+        assert_eq!(
+            parse_path(".items[0][-1][].name").unwrap(),
+            vec![key("items"), Step::Index(0), Step::Index(-1), Step::Each, key("name")]
+        );
+        assert_eq!(parse_path(r#".["odd key"].x"#).unwrap(), vec![key("odd key"), key("x")]);
+    }
+
+    #[test]
+    fn quoted_keys_may_contain_brackets_and_quotes() {
+        assert_eq!(parse_path(r#".["a]b"]"#).unwrap(), vec![key("a]b")]);
+        assert_eq!(parse_path(r#".[ "say \"]\"" ][0]"#).unwrap(), vec![key(r#"say "]""#), Step::Index(0)]);
+    }
+
+    #[test]
+    fn rejects_bad_paths() {
+        assert!(parse_path(".a..b").is_err());
+        assert!(parse_path(".[0").is_err());
+        assert!(parse_path(r#".["open]"#).is_err());
+        assert!(parse_path(".[x]").is_err());
+    }
+
+    #[test]
+    fn selects_through_arrays() {
+        let out = run(
+            JsonOp::Select {
+                path: ".items[].name".into(),
+            },
+            r#"{"items": [{"name": "a"}, {"name": "b"}, {}]}"#,
+        );
+        assert_eq!(out, "\"a\"\n\"b\"\n");
+    }
+
+    #[test]
+    fn filters_items_of_an_array() {
+        let filter = |compare, value: &str| JsonOp::Filter {
+            path: ".n".into(),
+            compare,
+            value: value.into(),
+        };
+        let input = r#"[{"n": 1}, {"n": 5}, {"m": 2}]"#;
+        assert_eq!(run(filter(Compare::Greater, "2"), input), "[{\"n\":5}]\n");
+        assert_eq!(run(filter(Compare::Exists, ""), input), "[{\"n\":1},{\"n\":5}]\n");
+        assert_eq!(run(filter(Compare::Equal, "1"), "{\"n\":1}\n{\"n\":2}\n"), "{\"n\":1}\n");
+    }
+
+    #[test]
+    fn projects_fields() {
+        let out = run(
+            JsonOp::Project {
+                fields: "id, owner.login".into(),
+            },
+            r#"{"id": 1, "owner": {"login": "me", "x": 2}, "y": 3}"#,
+        );
+        assert_eq!(out, "{\"id\":1,\"owner.login\":\"me\"}\n");
+    }
+
+    #[test]
+    fn flattens_nested_values() {
+        let flatten = || JsonOp::Flatten {
+            separator: "_".into(),
+        };
+        assert_eq!(
+            run(flatten(), r#"{"a": {"b": 1, "c": [2, {}]}}"#),
+            "{\"a_b\":1,\"a_c_0\":2,\"a_c_1\":{}}\n"
+        );
+    }
+
+    #[test]
+    fn flatten_passes_scalars_through() {
+        let flatten = || JsonOp::Flatten {
+            separator: ".".into(),
+        };
+        assert_eq!(run(flatten(), "5\n\"x\"\n{}\n"), "5\n\"x\"\n{}\n");
+        assert_eq!(run(flatten(), r#"[1, {"a": {"b": 2}}]"#), "[1,{\"a.b\":2}]\n");
+    }
+
+    #[test]
+    fn auto_input_falls_back_to_lines() {
+        assert_eq!(parse_values(b"{\"a\":1}", JsonInput::Auto).unwrap().len(), 1);
+        assert_eq!(parse_values(b"1\n\n2\n", JsonInput::Auto).unwrap().len(), 2);
+        let err = parse_values(b"1\nnope\n", JsonInput::Lines).unwrap_err();
+        assert!(err.starts_with("Line 2 "), "{}", err);
+    }
+}
@@ -0,0 +1,359 @@
+use crate::commands::FlowChartCommand;
+use eframe::egui::{Key, Modifiers};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// The shortcuts of a fresh install, as command IDs (see `FlowChartCommand::id`)
+/// and key sequences.
+const DEFAULT_BINDINGS: &[(&str, &str)] = &[
+    ("add_node", "N"),
+    ("run_pipeline", "R"),
+    ("force_run_pipeline", "Ctrl+Shift+R"),
+    ("run_up_to_selected", "Ctrl+K U"),
+    ("run_from_selected", "Ctrl+K F"),
+    ("run_selected_with_test_input", "Ctrl+K T"),
+    ("force_rerun_selected_node", "Ctrl+K R"),
+    ("delete_selected_node", "Delete"),
+    ("collapse_selection", "Ctrl+G"),
+    ("save_flow", "Ctrl+S"),
+    ("open_flow", "Ctrl+O"),
+    ("pan_left", "Left"),
+    ("pan_right", "Right"),
+    ("pan_up", "Up"),
+    ("pan_down", "Down"),
+    ("zoom_in", "Equals"),
+    ("zoom_out", "Minus"),
+    ("zoom_to_fit", "Home"),
+    ("zoom_to_selection", "F"),
+    ("reset_view", "Ctrl+0"),
+    ("toggle_minimap", "M"),
+    ("toggle_snap_to_grid", "Ctrl+Shift+G"),
+    ("open_command_palette", "Ctrl+Shift+P"),
+    ("show_shortcuts", "F1"),
+];
+
+/// One key press with the modifiers held. `command` is Ctrl, or Cmd on macOS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyCombo {
+    pub command: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub key: Key,
+}
+
+/// A key sequence that runs a command; more than one combo makes a chord.
+#[derive(Debug, Clone)]
+pub struct Binding {
+    pub keys: Vec<KeyCombo>,
+    pub command: FlowChartCommand,
+}
+
+/// What a key press did.
+pub enum Press {
+    /// It completed a binding.
+    Fired(FlowChartCommand),
+    /// It started or continued a chord.
+    Pending,
+    /// It is not a shortcut; widgets may have it.
+    Unbound,
+}
+
+/// Keyboard shortcuts: the defaults with the user's overrides applied, and
+/// the chord typed so far.
+pub struct Keymap {
+    pub bindings: Vec<Binding>,
+    /// Combos of a chord that has been started but not finished.
+    pub pending: Vec<KeyCombo>,
+}
+
+impl KeyCombo {
+    /// Parses e.g. `Ctrl+Shift+P`. `Cmd` is the same as `Ctrl`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+        let key_name = parts.pop().unwrap_or_default();
+        let key = Key::from_name(key_name).ok_or_else(|| format!("Unknown key '{}' in '{}'", key_name, text))?;
+        let mut combo = Self { command: false, alt: false, shift: false, key };
+        for modifier in parts {
+            match modifier.to_lowercase().as_str() {
+                "ctrl" | "cmd" | "command" => combo.command = true,
+                "alt" | "option" => combo.alt = true,
+                "shift" => combo.shift = true,
+                _ => return Err(format!("Unknown modifier '{}' in '{}'", modifier, text)),
+            }
+        }
+        Ok(combo)
+    }
+
+    pub fn new(key: Key, modifiers: Modifiers) -> Self {
+        Self {
+            command: modifiers.command || modifiers.ctrl,
+            alt: modifiers.alt,
+            shift: modifiers.shift,
+            key,
+        }
+    }
+
+    /// Whether the combo is ordinary typing, which text fields get first.
+    pub fn is_typing(&self) -> bool {
+        !self.command && !self.alt
+    }
+
+    pub fn label(&self) -> String {
+        let mut label = String::new();
+        if self.command {
+            label.push_str("Ctrl+");
+        }
+        if self.alt {
+            label.push_str("Alt+");
+        }
+        if self.shift {
+            label.push_str("Shift+");
+        }
+        label.push_str(self.key.name());
+        label
+    }
+}
+
+/// Parses a space-separated key sequence such as `Ctrl+K U`.
+fn parse_sequence(text: &str) -> Result<Vec<KeyCombo>, String> {
+    let keys = text.split_whitespace().map(KeyCombo::parse).collect::<Result<Vec<_>, _>>()?;
+    if keys.is_empty() {
+        return Err("Empty key sequence".into());
+    }
+    Ok(keys)
+}
+
+fn binding(id: &str, keys: &str) -> Result<Binding, String> {
+    let command = FlowChartCommand::from_id(id).ok_or_else(|| format!("Unknown command '{}'", id))?;
+    let keys = parse_sequence(keys).map_err(|e| format!("{} (for '{}')", e, id))?;
+    Ok(Binding { keys, command })
+}
+
+/// Whether two key sequences cannot both work: the same keys, or one a
+/// prefix of the other, which would fire before the longer one finishes.
+fn clashes(a: &[KeyCombo], b: &[KeyCombo]) -> bool {
+    a.starts_with(b) || b.starts_with(a)
+}
+
+/// Labels a key sequence the way it is written in the keymap file.
+pub fn sequence_label(keys: &[KeyCombo]) -> String {
+    keys.iter().map(KeyCombo::label).collect::<Vec<_>>().join(" ")
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::with_overrides(&BTreeMap::new()).expect("the default bindings parse")
+    }
+}
+
+impl Keymap {
+    /// The default bindings, with the commands named in `overrides`
+    /// rebound to the key sequences given there (none unbinds them). An
+    /// override takes its keys from any default bound to them; two
+    /// overrides on clashing keys are an error.
+    pub fn with_overrides(overrides: &BTreeMap<String, Vec<String>>) -> Result<Self, String> {
+        let mut keymap = Self { bindings: Vec::new(), pending: Vec::new() };
+        for (id, sequences) in overrides {
+            if FlowChartCommand::from_id(id).is_none() {
+                return Err(format!("Unknown command '{}'", id));
+            }
+            for keys in sequences {
+                let binding = binding(id, keys)?;
+                if let Some(other) = keymap.bindings.iter().find(|other| clashes(&other.keys, &binding.keys)) {
+                    return Err(format!(
+                        "'{}' for '{}' clashes with '{}' for '{}'",
+                        keys,
+                        id,
+                        sequence_label(&other.keys),
+                        other.command.id()
+                    ));
+                }
+                keymap.bindings.push(binding);
+            }
+        }
+        let overridden = keymap.bindings.len();
+        for (id, keys) in DEFAULT_BINDINGS {
+            if overrides.contains_key(*id) {
+                continue;
+            }
+            let binding = binding(id, keys)?;
+            if !keymap.bindings[..overridden].iter().any(|other| clashes(&other.keys, &binding.keys)) {
+                keymap.bindings.push(binding);
+            }
+        }
+        Ok(keymap)
+    }
+
+    /// Handles one key press. While `typing`, a text field has focus and
+    /// plain keys are left to it, unless they continue a chord.
+    pub fn press(&mut self, combo: KeyCombo, typing: bool, repeat: bool) -> Press {
+        if typing && combo.is_typing() && self.pending.is_empty() {
+            return Press::Unbound;
+        }
+        if repeat {
+            // Held keys repeat single-key shortcuts, e.g. panning, but never chords.
+            if !self.pending.is_empty() {
+                return Press::Pending;
+            }
+            return match self.bindings.iter().find(|binding| binding.keys == [combo]) {
+                Some(binding) => Press::Fired(binding.command.clone()),
+                None => Press::Unbound,
+            };
+        }
+        self.pending.push(combo);
+        if let Some(binding) = self.bindings.iter().find(|binding| binding.keys == self.pending) {
+            self.pending.clear();
+            return Press::Fired(binding.command.clone());
+        }
+        if self.bindings.iter().any(|binding| binding.keys.starts_with(&self.pending)) {
+            return Press::Pending;
+        }
+        let chord_broken = self.pending.len() > 1;
+        self.pending.clear();
+        if chord_broken { Press::Pending } else { Press::Unbound }
+    }
+
+    /// The key sequences bound to `command`, for display.
+    pub fn keys_for(&self, command: &FlowChartCommand) -> Vec<String> {
+        self.bindings
+            .iter()
+            .filter(|binding| binding.command.id() == command.id())
+            .map(|binding| sequence_label(&binding.keys))
+            .collect()
+    }
+}
+
+/// Where the user's keymap file lives.
+pub fn default_keymap_path() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("echoflow")
+        .join("keymap.json")
+}
+
+/// Reads the keymap file: an object from command IDs to lists of key
+/// sequences, e.g. `{"run_pipeline": ["Ctrl+Enter"], "add_node": []}`.
+/// Without a file, the defaults apply.
+pub fn load_keymap(path: &Path) -> Result<Keymap, String> {
+    let overrides = match std::fs::read(path) {
+        Ok(data) => serde_json::from_slice(&data)
+            .map_err(|e| format!("Invalid keymap file {}: {}", path.display(), e))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+        Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+    };
+    Keymap::with_overrides(&overrides).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn combo(text: &str) -> KeyCombo {
+        KeyCombo::parse(text).unwrap()
+    }
+
+    fn overrides(entries: &[(&str, &[&str])]) -> BTreeMap<String, Vec<String>> {
+        entries
+            .iter()
+            .map(|(id, keys)| (id.to_string(), keys.iter().map(|k| k.to_string()).collect()))
+            .collect()
+    }
+
+    /// The command fired by typing `keys`, or why not.
+    fn fired(keymap: &mut Keymap, keys: &str) -> Option<&'static str> {
+        let mut last = None;
+        for key in keys.split_whitespace() {
+            last = match keymap.press(combo(key), false, false) {
+                Press::Fired(command) => Some(command.id()),
+                Press::Pending => Some("pending"),
+                Press::Unbound => None,
+            };
+        }
+        last
+    }
+
+    #[test]
+    fn parses_combos() {
+        let combo = combo("cmd + Shift+P");
+        assert!(combo.command && combo.shift && !combo.alt);
+        assert_eq!(combo.key, Key::P);
+        assert_eq!(combo.label(), "Ctrl+Shift+P");
+        assert!(KeyCombo::parse("Hyper+P").is_err());
+        assert!(KeyCombo::parse("Ctrl+Nope").is_err());
+        assert!(parse_sequence("  ").is_err());
+    }
+
+    #[test]
+    fn the_defaults_do_not_clash() {
+        let keymap = Keymap::default();
+        assert_eq!(keymap.bindings.len(), DEFAULT_BINDINGS.len());
+        for (i, a) in keymap.bindings.iter().enumerate() {
+            for b in &keymap.bindings[i + 1..] {
+                assert!(!clashes(&a.keys, &b.keys), "{:?} and {:?}", a.command, b.command);
+            }
+        }
+    }
+
+    #[test]
+    fn chords_fire_on_their_last_key() {
+        let mut keymap = Keymap::default();
+        assert_eq!(fired(&mut keymap, "Ctrl+K"), Some("pending"));
+        assert_eq!(fired(&mut keymap, "U"), Some("run_up_to_selected"));
+        assert!(keymap.pending.is_empty());
+        // A key that breaks a chord is swallowed, not run on its own.
+        assert_eq!(fired(&mut keymap, "Ctrl+K N"), Some("pending"));
+        assert!(keymap.pending.is_empty());
+        assert_eq!(fired(&mut keymap, "N"), Some("add_node"));
+        assert_eq!(fired(&mut keymap, "Q"), None);
+    }
+
+    #[test]
+    fn typing_leaves_plain_keys_to_text_fields() {
+        let mut keymap = Keymap::default();
+        assert!(matches!(keymap.press(combo("N"), true, false), Press::Unbound));
+        assert!(matches!(keymap.press(combo("Ctrl+S"), true, false), Press::Fired(_)));
+        assert!(matches!(keymap.press(combo("Ctrl+K"), true, false), Press::Pending));
+        assert!(matches!(keymap.press(combo("U"), true, false), Press::Fired(_)));
+    }
+
+    #[test]
+    fn held_keys_repeat_single_keys_but_not_chords() {
+        let mut keymap = Keymap::default();
+        assert!(matches!(keymap.press(combo("Left"), false, true), Press::Fired(_)));
+        assert!(matches!(keymap.press(combo("Ctrl+K"), false, true), Press::Unbound));
+        keymap.press(combo("Ctrl+K"), false, false);
+        assert!(matches!(keymap.press(combo("U"), false, true), Press::Pending));
+        assert_eq!(keymap.pending.len(), 1);
+    }
+
+    #[test]
+    fn overrides_take_keys_from_defaults() {
+        let mut keymap = Keymap::with_overrides(&overrides(&[("run_pipeline", &["N"])])).unwrap();
+        assert_eq!(fired(&mut keymap, "N"), Some("run_pipeline"));
+        assert_eq!(fired(&mut keymap, "R"), None);
+        assert!(keymap.keys_for(&FlowChartCommand::from_id("add_node").unwrap()).is_empty());
+    }
+
+    #[test]
+    fn overrides_drop_defaults_they_prefix() {
+        let mut keymap = Keymap::with_overrides(&overrides(&[("save_flow", &["Ctrl+K"])])).unwrap();
+        assert_eq!(fired(&mut keymap, "Ctrl+K"), Some("save_flow"));
+        assert!(keymap.keys_for(&FlowChartCommand::from_id("run_up_to_selected").unwrap()).is_empty());
+    }
+
+    #[test]
+    fn an_empty_override_unbinds() {
+        let mut keymap = Keymap::with_overrides(&overrides(&[("add_node", &[])])).unwrap();
+        assert_eq!(fired(&mut keymap, "N"), None);
+    }
+
+    #[test]
+    fn clashing_overrides_are_errors() {
+        let err = Keymap::with_overrides(&overrides(&[("add_node", &["N X"]), ("run_pipeline", &["N"])]))
+            .err()
+            .unwrap();
+        assert_eq!(err, "'N' for 'run_pipeline' clashes with 'N X' for 'add_node'");
+        assert!(Keymap::with_overrides(&overrides(&[("no_such_command", &["N"])])).is_err());
+        assert!(Keymap::with_overrides(&overrides(&[("add_node", &["Ctrl+"])])).is_err());
+    }
+}
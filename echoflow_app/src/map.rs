@@ -0,0 +1,336 @@
+use crate::executor::{self, Attempt, NodeResult, ProcessSettings, RunEvent, RunPlan};
+use crate::flowchart::{FlowChart, SubflowJobs};
+use crate::subflow::Subflow;
+use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, mpsc};
+use std::thread;
+
+/// Settings of a map node, which runs its command, or a subflow, once per
+/// input record (the `while read line` / `xargs -P` idiom).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MapSettings {
+    pub split: RecordSplit,
+    /// Maximum number of records processed at the same time. Records count
+    /// against the flow's own parallelism limit too.
+    pub parallelism: usize,
+    /// Subflow run per record instead of the node's command.
+    #[serde(default)]
+    pub body: Option<Box<Subflow>>,
+}
+
+impl Default for MapSettings {
+    fn default() -> Self {
+        Self {
+            split: RecordSplit::Lines,
+            parallelism: 4,
+            body: None,
+        }
+    }
+}
+
+// A subflow's own hash leaves its inner chart out, so the body's chart is
+// hashed as well.
+impl Hash for MapSettings {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.split.hash(state);
+        self.parallelism.hash(state);
+        self.body.hash(state);
+        if let Some(body) = &self.body {
+            body.chart.hash_settings(state);
+        }
+    }
+}
+
+impl MapSettings {
+    /// Checks that the body, if any, expands and that its nodes are usable.
+    pub fn check(&self) -> Result<(), String> {
+        let Some(body) = &self.body else {
+            return Ok(());
+        };
+        let expanded = FlowChart::expand_subflow(body)?;
+        for job in &expanded.jobs {
+            job.node_type
+                .check()
+                .map_err(|e| format!("{} of subflow '{}': {}", job.label, body.name, e))?;
+        }
+        Ok(())
+    }
+}
+
+/// How a map node cuts its input into records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RecordSplit {
+    /// One record per line, like `while read line`.
+    Lines,
+    /// NUL-terminated records, like `find -print0 | xargs -0`.
+    Nul,
+    /// One record per item of a JSON array. String items are passed raw,
+    /// anything else as compact JSON (like `jq -r '.[]'`).
+    JsonArray,
+}
+
+impl RecordSplit {
+    pub const ALL: [RecordSplit; 3] = [RecordSplit::Lines, RecordSplit::Nul, RecordSplit::JsonArray];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            RecordSplit::Lines => "Lines",
+            RecordSplit::Nul => "NUL-separated",
+            RecordSplit::JsonArray => "JSON array items",
+        }
+    }
+
+    /// Cuts the input into records, without their separators.
+    pub fn split(self, input: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+        match self {
+            RecordSplit::Lines => Ok(split_on(input, b'\n')),
+            RecordSplit::Nul => Ok(split_on(input, b'\0')),
+            RecordSplit::JsonArray => {
+                let value: serde_json::Value = serde_json::from_slice(input)
+                    .map_err(|e| format!("Input is not valid JSON: {}", e))?;
+                let serde_json::Value::Array(items) = value else {
+                    return Err("Input is not a JSON array.".into());
+                };
+                Ok(items
+                    .into_iter()
+                    .map(|item| match item {
+                        serde_json::Value::String(text) => text.into_bytes(),
+                        other => other.to_string().into_bytes(),
+                    })
+                    .collect())
+            }
+        }
+    }
+}
+
+fn split_on(input: &[u8], separator: u8) -> Vec<Vec<u8>> {
+    let input = input.strip_suffix(&[separator]).unwrap_or(input);
+    if input.is_empty() {
+        return Vec::new();
+    }
+    input.split(|&b| b == separator).map(<[u8]>::to_vec).collect()
+}
+
+/// Runs `command`, or the settings' body subflow, once per record with the
+/// record on stdin (newline-terminated unless records are NUL-separated)
+/// and in the `RECORD` environment variable. A body gets the record on each
+/// of its input ports and its first output port is the record's output. At
+/// most `workers` records are processed at the same time. Outputs are
+/// concatenated in input order, whatever order the records finish in. The
+/// result fails with the exit code of the first record, in input order,
+/// whose code is not in `success_codes`.
+pub fn run_map(
+    settings: &MapSettings,
+    command: &str,
+    input: &[u8],
+    success_codes: &[i32],
+    process: &ProcessSettings,
+    workers: usize,
+) -> Result<Attempt, String> {
+    let records = settings.split.split(input)?;
+    let body = match &settings.body {
+        Some(body) => Some(FlowChart::expand_subflow(body)?),
+        None => None,
+    };
+    let results: Vec<Mutex<Option<Result<Attempt, String>>>> =
+        records.iter().map(|_| Mutex::new(None)).collect();
+    let next = AtomicUsize::new(0);
+    let workers = workers.clamp(1, records.len().max(1));
+
+    let (records, results, next, split) = (&records, &results, &next, settings.split);
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            // Subflows are not `Sync`, so every worker gets its own copy of the body.
+            let body = body.clone();
+            scope.spawn(move || {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(record) = records.get(index) else {
+                        break;
+                    };
+                    let mut stdin = record.clone();
+                    if split != RecordSplit::Nul {
+                        stdin.push(b'\n');
+                    }
+                    let value = String::from_utf8_lossy(record).into_owned();
+                    let result = match &body {
+                        Some(body) => run_body(body, stdin, &value),
+                        None => executor::run_command_with_env(command, Some(&stdin), process, &[("RECORD", value)]),
+                    };
+                    *results[index].lock().unwrap() = Some(result);
+                }
+            });
+        }
+    });
+
+    let mut combined = Attempt {
+        exit_code: Some(0),
+        ..Attempt::default()
+    };
+    let mut failed = false;
+    for (index, result) in results.iter().enumerate() {
+        let attempt = result
+            .lock()
+            .unwrap()
+            .take()
+            .unwrap_or_else(|| Err("Record was never processed.".into()))
+            .map_err(|e| format!("Record {}: {}", index + 1, e))?;
+        combined.stdout.extend_from_slice(&attempt.stdout);
+        combined.stderr.extend_from_slice(&attempt.stderr);
+        let ok = attempt.exit_code.is_some_and(|code| success_codes.contains(&code));
+        if !failed && (!ok || index + 1 == records.len()) {
+            combined.exit_code = attempt.exit_code;
+        }
+        if !ok && !failed {
+            failed = true;
+            combined
+                .stderr
+                .extend_from_slice(format!("Record {} failed\n", index + 1).as_bytes());
+        }
+    }
+    Ok(combined)
+}
+
+/// Runs a map body on one record, one command at a time since the record
+/// already holds one of the map's slots. The record's result is the run of
+/// the node behind the body's first output port, with the stderr of every
+/// node of the body. Without an output port, or if that node did not get to
+/// run, it is the body's run as a whole, which fails if any node fails.
+fn run_body(body: &SubflowJobs, stdin: Vec<u8>, record: &str) -> Result<Attempt, String> {
+    let mut jobs = body.jobs.clone();
+    for job in &mut jobs {
+        job.process.env.push(("RECORD".into(), record.to_string()));
+        if body.inputs.contains(&job.id) {
+            job.fixed_input = Some(stdin.clone());
+        }
+    }
+    let plan = RunPlan {
+        jobs,
+        force: true,
+        max_parallelism: 1,
+    };
+    let (events, receiver) = mpsc::channel();
+    let result = executor::schedule(&plan, &events);
+    let mut stderr = Vec::new();
+    let mut output_attempt = None;
+    for event in receiver.try_iter() {
+        let RunEvent::Finished(id, NodeResult::Ran { attempt, .. } | NodeResult::Failed { attempt, .. }) = event
+        else {
+            continue;
+        };
+        stderr.extend_from_slice(&attempt.stderr);
+        if Some(id) == body.output {
+            output_attempt = Some(attempt);
+        }
+    }
+    let mut attempt = match output_attempt {
+        Some(attempt) => attempt,
+        None => Attempt {
+            exit_code: Some(0),
+            stdout: result?,
+            ..Attempt::default()
+        },
+    };
+    attempt.stderr = stderr;
+    Ok(attempt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::NodeType;
+    use crate::flowchart::{Connection, ConnectionKind, Node};
+    use crate::subflow::Port;
+
+    fn body(commands: &[&str]) -> Subflow {
+        let mut chart = FlowChart::default();
+        for (index, command) in commands.iter().enumerate() {
+            chart.nodes.push(Node::new(index, NodeType::Command, command.to_string()));
+            if index > 0 {
+                chart.connections.push(Connection {
+                    from: index - 1,
+                    to: index,
+                    kind: ConnectionKind::Data,
+                    from_port: 0,
+                    to_port: 0,
+                });
+            }
+        }
+        let port = |name: &str, node| Port { name: name.into(), node, node_port: 0 };
+        Subflow {
+            name: "body".into(),
+            inputs: vec![port("in", 0)],
+            outputs: vec![port("out", commands.len() - 1)],
+            chart,
+        }
+    }
+
+    fn map(settings: &MapSettings, command: &str, input: &[u8]) -> Attempt {
+        run_map(settings, command, input, &[0], &ProcessSettings::default(), 3).unwrap()
+    }
+
+    #[test]
+    fn splits_records() {
+        assert_eq!(RecordSplit::Lines.split(b"a\nb\n").unwrap(), [b"a", b"b"]);
+        assert_eq!(RecordSplit::Nul.split(b"a\0b").unwrap(), [b"a", b"b"]);
+        assert!(RecordSplit::Lines.split(b"").unwrap().is_empty());
+        let items = RecordSplit::JsonArray.split(br#"["a", 1, {"k": true}]"#).unwrap();
+        assert_eq!(items, [&b"a"[..], b"1", br#"{"k":true}"#]);
+        assert!(RecordSplit::JsonArray.split(b"{}").is_err());
+    }
+
+    #[test]
+    fn runs_the_command_per_record_in_input_order() {
+        let out = map(&MapSettings::default(), "sleep 0.$((3 - ${#RECORD})); tr a-z A-Z", b"a\nbb\nccc\n");
+        assert_eq!(out.stdout, b"A\nBB\nCCC\n");
+        assert_eq!(out.exit_code, Some(0));
+    }
+
+    #[test]
+    fn fails_with_the_code_of_the_first_failing_record() {
+        let out = map(&MapSettings::default(), "[ \"$RECORD\" != b ] || exit 3", b"a\nb\nc\n");
+        assert_eq!(out.exit_code, Some(3));
+        assert!(String::from_utf8_lossy(&out.stderr).contains("Record 2 failed"));
+    }
+
+    #[test]
+    fn runs_a_subflow_body_per_record() {
+        let settings = MapSettings {
+            body: Some(Box::new(body(&["tr a-z A-Z", "sed \"s/$/-$RECORD/\""]))),
+            ..MapSettings::default()
+        };
+        settings.check().unwrap();
+        assert_eq!(map(&settings, "", b"ab\ncd\n").stdout, b"AB-ab\nCD-cd\n");
+    }
+
+    #[test]
+    fn a_body_keeps_its_exit_code_and_stderr() {
+        let settings = MapSettings {
+            body: Some(Box::new(body(&["cat", "echo oops >&2; exit 4"]))),
+            ..MapSettings::default()
+        };
+        let out = map(&settings, "", b"x\n");
+        assert_eq!(out.exit_code, Some(4));
+        assert!(String::from_utf8_lossy(&out.stderr).contains("oops"));
+        let lenient = run_map(&settings, "", b"x\n", &[0, 4], &ProcessSettings::default(), 1).unwrap();
+        assert_eq!(lenient.exit_code, Some(4));
+        assert!(!String::from_utf8_lossy(&lenient.stderr).contains("failed"));
+    }
+
+    #[test]
+    fn the_body_chart_is_part_of_the_hash() {
+        let hash = |settings: &MapSettings| {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            settings.hash(&mut hasher);
+            hasher.finish()
+        };
+        let with = |command| MapSettings {
+            body: Some(Box::new(body(&["cat", command]))),
+            ..MapSettings::default()
+        };
+        assert_eq!(hash(&with("sort")), hash(&with("sort")));
+        assert_ne!(hash(&with("sort")), hash(&with("sort -r")));
+    }
+}
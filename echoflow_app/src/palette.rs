@@ -0,0 +1,163 @@
+use crate::app::PipelineApp;
+use crate::commands::FlowChartCommand;
+use crate::registry::registry;
+use fuzzy_matcher::FuzzyMatcher;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use std::path::{Path, PathBuf};
+
+/// How many flow files the palette remembers.
+const RECENT_LIMIT: usize = 10;
+
+/// The command palette while it is open.
+#[derive(Default)]
+pub struct Palette {
+    pub query: String,
+    /// Index of the highlighted match.
+    pub selected: usize,
+}
+
+/// Something the palette offers.
+pub struct PaletteItem {
+    /// What kind of item it is, shown next to the label.
+    pub group: &'static str,
+    pub label: String,
+    pub command: FlowChartCommand,
+}
+
+/// Everything the palette offers right now: commands, toolbox presets and
+/// entries, the nodes of the active tab, and recent flow files.
+pub fn items(app: &PipelineApp) -> Vec<PaletteItem> {
+    let mut items: Vec<PaletteItem> = FlowChartCommand::actions()
+        .into_iter()
+        .filter(|command| !matches!(command, FlowChartCommand::OpenCommandPalette))
+        .map(|command| PaletteItem { group: "Command", label: command.label(), command })
+        .collect();
+    for (index, preset) in app.presets.iter().enumerate() {
+        items.push(PaletteItem {
+            group: "Add",
+            label: preset.title(),
+            command: FlowChartCommand::AddPreset(index),
+        });
+    }
+    for (index, entry) in registry().entries().iter().enumerate() {
+        items.push(PaletteItem {
+            group: "Add",
+            label: entry.name.clone(),
+            command: FlowChartCommand::AddToolboxEntry(index),
+        });
+    }
+    if let Some(chart) = app.flowchart.chart_at_path(&app.active_path()) {
+        for node in &chart.nodes {
+            items.push(PaletteItem {
+                group: "Node",
+                label: format!("{} {}", node.id, node.label()),
+                command: FlowChartCommand::FocusNode(node.id),
+            });
+        }
+    }
+    for path in &app.recent_flows {
+        items.push(PaletteItem {
+            group: "Recent",
+            label: path.clone(),
+            command: FlowChartCommand::OpenRecentFlow(path.clone()),
+        });
+    }
+    items
+}
+
+/// The items matching `query` fuzzily, best match first. An empty query
+/// keeps every item in order.
+pub fn search(items: Vec<PaletteItem>, query: &str) -> Vec<PaletteItem> {
+    let query = query.trim();
+    if query.is_empty() {
+        return items;
+    }
+    let matcher = SkimMatcherV2::default().ignore_case();
+    let mut scored: Vec<(i64, PaletteItem)> = items
+        .into_iter()
+        .filter_map(|item| {
+            let text = format!("{} {}", item.group, item.label);
+            matcher.fuzzy_match(&text, query).map(|score| (score, item))
+        })
+        .collect();
+    scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    scored.into_iter().map(|(_, item)| item).collect()
+}
+
+/// Where the recently used flow files are remembered.
+pub fn default_recent_path() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("echoflow")
+        .join("recent.json")
+}
+
+/// Reads the recent flow files, most recent first. A missing or unreadable
+/// list is empty.
+pub fn load_recent(path: &Path) -> Vec<String> {
+    std::fs::read(path)
+        .ok()
+        .and_then(|data| serde_json::from_slice(&data).ok())
+        .unwrap_or_default()
+}
+
+/// Moves `flow` to the front of the recent flow files and writes the list.
+pub fn remember_recent(path: &Path, recent: &mut Vec<String>, flow: &str) -> Result<(), String> {
+    recent.retain(|entry| entry != flow);
+    recent.insert(0, flow.to_string());
+    recent.truncate(RECENT_LIMIT);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    let data = serde_json::to_vec_pretty(recent).map_err(|e| e.to_string())?;
+    std::fs::write(path, data).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(group: &'static str, label: &str) -> PaletteItem {
+        PaletteItem {
+            group,
+            label: label.into(),
+            command: FlowChartCommand::FocusNode(0),
+        }
+    }
+
+    fn labels(items: &[PaletteItem]) -> Vec<&str> {
+        items.iter().map(|item| item.label.as_str()).collect()
+    }
+
+    #[test]
+    fn search_ranks_fuzzy_matches_and_drops_the_rest() {
+        let items = || {
+            vec![
+                item("Command", "Save flow"),
+                item("Command", "Run pipeline"),
+                item("Recent", "runs.json"),
+            ]
+        };
+        assert_eq!(labels(&search(items(), "  ")), ["Save flow", "Run pipeline", "runs.json"]);
+        assert_eq!(labels(&search(items(), "rnpip")), ["Run pipeline"]);
+        assert_eq!(labels(&search(items(), "recent runs")), ["runs.json"]);
+        assert!(search(items(), "zzz").is_empty());
+    }
+
+    #[test]
+    fn recent_flows_move_to_the_front_and_are_capped() {
+        let dir = std::env::temp_dir().join(format!("echoflow-recent-{}", std::process::id()));
+        let path = dir.join("recent.json");
+        assert!(load_recent(&path).is_empty());
+        let mut recent = Vec::new();
+        for index in 0..12 {
+            remember_recent(&path, &mut recent, &format!("flow{}.json", index)).unwrap();
+        }
+        remember_recent(&path, &mut recent, "flow5.json").unwrap();
+        assert_eq!(recent.len(), RECENT_LIMIT);
+        assert_eq!(recent[..3], ["flow5.json", "flow11.json", "flow10.json"]);
+        assert_eq!(load_recent(&path), recent);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,165 @@
+use crate::executor::{NodeType, ProcessSettings};
+use crate::flowchart::{FlowChart, Node};
+use crate::map::MapSettings;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// A toolbox button from the user's preset file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Preset {
+    pub name: String,
+    #[serde(default = "default_category")]
+    pub category: String,
+    /// Shown before the name, typically an emoji.
+    #[serde(default)]
+    pub icon: String,
+    /// Tooltip for the button.
+    #[serde(default)]
+    pub description: String,
+    /// Command the new node starts with, to be filled in by the user.
+    #[serde(default)]
+    pub command: String,
+    /// Type and settings of the new node; a plain command when left out.
+    #[serde(default)]
+    pub node_type: NodeType,
+    /// Environment (`env`) and working directory (`cwd`) of the new node.
+    #[serde(default, flatten)]
+    pub process: ProcessSettings,
+}
+
+fn default_category() -> String {
+    "Commands".into()
+}
+
+impl Preset {
+    fn command(name: &str, icon: &str, command: &str) -> Self {
+        Self {
+            name: name.into(),
+            category: default_category(),
+            icon: icon.into(),
+            description: command.into(),
+            command: command.into(),
+            node_type: NodeType::Command,
+            process: ProcessSettings::default(),
+        }
+    }
+
+    /// A preset that recreates `node` with its current settings.
+    pub fn from_node(node: &Node) -> Self {
+        Self {
+            name: node.label(),
+            category: "My presets".into(),
+            icon: String::new(),
+            description: String::new(),
+            command: node.command.clone(),
+            node_type: node.node_type.clone(),
+            process: node.process.clone(),
+        }
+    }
+
+    /// Name with the icon in front, for buttons.
+    pub fn title(&self) -> String {
+        if self.icon.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{} {}", self.icon, self.name)
+        }
+    }
+
+    /// Adds a node made from the preset to the chart.
+    pub fn instantiate(&self, chart: &mut FlowChart) {
+        let node = chart.add_node_of_type(self.node_type.clone(), &self.command);
+        node.process = self.process.clone();
+    }
+}
+
+/// The presets offered before the user has a preset file of their own.
+pub fn default_presets() -> Vec<Preset> {
+    vec![
+        Preset::command("Echo", "💬", "echo Hello World"),
+        Preset::command("List Directory", "📂", "ls -la"),
+        Preset::command("Grep", "🔍", "grep 'pattern'"),
+        Preset::command("Sort", "↕", "sort"),
+        Preset::command("Word Count", "#", "wc -w"),
+        Preset {
+            node_type: NodeType::Map(MapSettings::default()),
+            ..Preset::command("Map Over Lines", "🔁", "echo \"item: $RECORD\"")
+        },
+    ]
+}
+
+/// Where the preset file lives unless the user picks another one.
+pub fn default_presets_path() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("echoflow")
+        .join("presets.json")
+}
+
+/// Reads the presets in a preset file. Without a file, the default presets apply.
+pub fn load_presets(path: &Path) -> Result<Vec<Preset>, String> {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(default_presets()),
+        Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+    };
+    serde_json::from_slice(&data).map_err(|e| format!("Invalid preset file {}: {}", path.display(), e))
+}
+
+/// Writes every preset to a preset file.
+pub fn save_presets(path: &Path, presets: &[Preset]) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    let data = serde_json::to_vec_pretty(presets).map_err(|e| e.to_string())?;
+    std::fs::write(path, data).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transform::Transform;
+
+    #[test]
+    fn a_preset_needs_only_a_name() {
+        let presets: Vec<Preset> = serde_json::from_str(
+            r#"[{"name": "Logs", "command": "tail -f x", "env": {"LANG": "C", "A": "1"}, "cwd": "/tmp"}]"#,
+        )
+        .unwrap();
+        let preset = &presets[0];
+        assert_eq!(preset.category, "Commands");
+        assert_eq!(preset.title(), "Logs");
+        assert!(matches!(preset.node_type, NodeType::Command));
+        assert_eq!(preset.process.env, [("A".into(), "1".into()), ("LANG".into(), "C".into())]);
+        assert_eq!(preset.process.cwd, "/tmp");
+    }
+
+    #[test]
+    fn a_node_saved_as_a_preset_comes_back_the_same() {
+        let mut chart = FlowChart::default();
+        let node = chart.add_node_of_type(NodeType::Transform(Transform::CountLines), "");
+        node.process.cwd = "/srv".into();
+        let preset = Preset::from_node(node);
+        assert_eq!(preset.category, "My presets");
+
+        let dir = std::env::temp_dir().join(format!("echoflow-presets-{}", std::process::id()));
+        let path = dir.join("presets.json");
+        save_presets(&path, &[preset]).unwrap();
+        let loaded = load_presets(&path).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+
+        loaded[0].instantiate(&mut chart);
+        let copy = chart.nodes.last().unwrap();
+        assert!(matches!(copy.node_type, NodeType::Transform(Transform::CountLines)));
+        assert_eq!(copy.process.cwd, "/srv");
+        assert_eq!(chart.nodes.len(), 2);
+    }
+
+    #[test]
+    fn without_a_file_the_defaults_apply() {
+        let presets = load_presets(Path::new("/nonexistent/echoflow/presets.json")).unwrap();
+        assert_eq!(presets.len(), default_presets().len());
+        assert!(matches!(presets.last().unwrap().node_type, NodeType::Map(_)));
+    }
+}
@@ -0,0 +1,303 @@
+use crate::json::{self, JsonInput};
+use crate::table::{self, Table, TableFormat};
+use eframe::egui;
+use egui_extras::{Column, TableBuilder};
+use serde_json::Value;
+use std::fmt::Write;
+use std::sync::Arc;
+
+/// How many bytes of a binary buffer are rendered in the hex dump.
+const HEX_DUMP_LIMIT: usize = 4096;
+
+/// How a node's output is shown in the side panel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputView {
+    Text,
+    Table,
+    Json,
+}
+
+impl OutputView {
+    pub const ALL: [OutputView; 3] = [OutputView::Text, OutputView::Table, OutputView::Json];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            OutputView::Text => "Text",
+            OutputView::Table => "Table",
+            OutputView::Json => "JSON tree",
+        }
+    }
+}
+
+/// How many items of one JSON array or object are listed in the tree.
+const JSON_CHILD_LIMIT: usize = 500;
+
+/// Show a byte buffer: as text when it is valid UTF-8, otherwise as a hex dump
+/// headed by its size and detected MIME type.
+pub fn show_bytes(ui: &mut egui::Ui, bytes: &[u8]) {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => {
+            ui.code(text);
+        }
+        Err(_) => {
+            ui.label(format!("{} ({})", format_size(bytes.len()), detect_mime(bytes)));
+            egui::ScrollArea::vertical()
+                .id_source(bytes.as_ptr())
+                .max_height(300.0)
+                .show(ui, |ui| {
+                    ui.code(hex_dump(bytes, HEX_DUMP_LIMIT));
+                });
+        }
+    }
+}
+
+/// Show JSON or JSON Lines as a collapsible tree, falling back to
+/// `show_bytes` when the buffer does not parse. The parse is kept in egui's
+/// memory under `id`, along with a hash of the buffer, so it is only redone
+/// when the content changes.
+pub fn show_json(ui: &mut egui::Ui, id: egui::Id, bytes: &[u8]) {
+    let id = id.with("json_tree");
+    let content = egui::util::hash(bytes);
+    let values = match ui.data(|d| d.get_temp::<(u64, Option<Arc<Vec<Value>>>)>(id)) {
+        Some((hash, values)) if hash == content => values,
+        _ => {
+            let values = json::parse_values(bytes, JsonInput::Auto).ok().map(Arc::new);
+            ui.data_mut(|d| d.insert_temp(id, (content, values.clone())));
+            values
+        }
+    };
+    let Some(values) = values.filter(|values| !values.is_empty()) else {
+        show_bytes(ui, bytes);
+        return;
+    };
+    egui::ScrollArea::vertical()
+        .id_source(id)
+        .max_height(400.0)
+        .show(ui, |ui| {
+            if let [value] = values.as_slice() {
+                json_tree(ui, id, "value", value, true);
+            } else {
+                ui.label(format!("{} values", values.len()));
+                for (index, value) in values.iter().enumerate().take(JSON_CHILD_LIMIT) {
+                    json_tree(ui, id.with(index), &index.to_string(), value, false);
+                }
+                if values.len() > JSON_CHILD_LIMIT {
+                    ui.weak(format!("… {} more", values.len() - JSON_CHILD_LIMIT));
+                }
+            }
+        });
+}
+
+/// Show a table in a grid whose columns sort when their heading is
+/// clicked, headed by its row count. The sort order is remembered under
+/// `id`, so it survives re-runs that change the output. The parse and the
+/// sorted order are kept under `id` too, and redone only when the content,
+/// format or sort changes.
+pub fn show_table(ui: &mut egui::Ui, id: egui::Id, bytes: &[u8], format: TableFormat, header: bool) {
+    let parse_id = id.with("table");
+    let content = egui::util::hash((bytes, format, header));
+    let parsed = match ui.data(|d| d.get_temp::<(u64, Arc<Result<Table, String>>)>(parse_id)) {
+        Some((hash, parsed)) if hash == content => parsed,
+        _ => {
+            let parsed = Arc::new(table::parse_table(bytes, format, header));
+            ui.data_mut(|d| d.insert_temp(parse_id, (content, parsed.clone())));
+            parsed
+        }
+    };
+    let table = match parsed.as_ref() {
+        Ok(table) if !table.rows.is_empty() || table.header.is_some() => table,
+        Ok(_) => {
+            ui.weak("(no rows)");
+            return;
+        }
+        Err(e) => {
+            ui.colored_label(egui::Color32::RED, e);
+            show_bytes(ui, bytes);
+            return;
+        }
+    };
+    let width = table.width();
+    ui.label(format!("{} rows × {} columns ({})", table.rows.len(), width, format.label()));
+
+    // Sorted row order, cached alongside the parse for the current sort.
+    let sort_id = id.with("sort");
+    let mut sort: Option<(usize, bool)> = ui.data(|d| d.get_temp(sort_id)).flatten();
+    let order_id = id.with("order");
+    let order_key = egui::util::hash((content, sort));
+    let order = match ui.data(|d| d.get_temp::<(u64, Arc<Vec<usize>>)>(order_id)) {
+        Some((hash, order)) if hash == order_key => order,
+        _ => {
+            let order = match sort {
+                Some((column, descending)) => table.row_order(column, table.is_numeric_column(column), descending),
+                None => (0..table.rows.len()).collect(),
+            };
+            let order = Arc::new(order);
+            ui.data_mut(|d| d.insert_temp(order_id, (order_key, order.clone())));
+            order
+        }
+    };
+
+    let row_height = ui.text_style_height(&egui::TextStyle::Monospace) + 4.0;
+    ui.push_id(id, |ui| {
+        TableBuilder::new(ui)
+            .striped(true)
+            .max_scroll_height(400.0)
+            .columns(Column::auto().resizable(true).at_least(40.0), width)
+            .header(row_height, |mut row| {
+                for column in 0..width {
+                    row.col(|ui| {
+                        let name = table
+                            .header
+                            .as_ref()
+                            .and_then(|header| header.get(column))
+                            .cloned()
+                            .unwrap_or_else(|| (column + 1).to_string());
+                        let arrow = match sort {
+                            Some((sorted, false)) if sorted == column => " ⏶",
+                            Some((sorted, true)) if sorted == column => " ⏷",
+                            _ => "",
+                        };
+                        if ui.button(format!("{}{}", name, arrow)).clicked() {
+                            // Ascending, then descending, then back to input order.
+                            sort = match sort {
+                                Some((sorted, false)) if sorted == column => Some((column, true)),
+                                Some((sorted, true)) if sorted == column => None,
+                                _ => Some((column, false)),
+                            };
+                        }
+                    });
+                }
+            })
+            .body(|body| {
+                body.rows(row_height, order.len(), |mut row| {
+                    let cells = &table.rows[order[row.index()]];
+                    for column in 0..width {
+                        row.col(|ui| {
+                            ui.monospace(cells.get(column).map_or("", String::as_str));
+                        });
+                    }
+                });
+            });
+    });
+    ui.data_mut(|d| d.insert_temp(sort_id, sort));
+}
+
+fn json_tree(ui: &mut egui::Ui, id: egui::Id, key: &str, value: &Value, open: bool) {
+    let children: Vec<(String, &Value)> = match value {
+        Value::Array(items) => items.iter().enumerate().map(|(i, v)| (i.to_string(), v)).collect(),
+        Value::Object(map) => map.iter().map(|(k, v)| (k.clone(), v)).collect(),
+        leaf => {
+            ui.horizontal(|ui| {
+                ui.label(format!("{}:", key));
+                ui.monospace(leaf.to_string());
+            });
+            return;
+        }
+    };
+    let heading = match value {
+        Value::Array(_) => format!("{} [{}]", key, children.len()),
+        _ => format!("{} {{{}}}", key, children.len()),
+    };
+    egui::CollapsingHeader::new(heading)
+        .id_source(id)
+        .default_open(open)
+        .show(ui, |ui| {
+            for (child_key, child) in children.iter().take(JSON_CHILD_LIMIT) {
+                json_tree(ui, id.with(child_key), child_key, child, false);
+            }
+            if children.len() > JSON_CHILD_LIMIT {
+                ui.weak(format!("… {} more", children.len() - JSON_CHILD_LIMIT));
+            }
+        });
+}
+
+/// Best-effort MIME type detection from the leading magic bytes.
+pub fn detect_mime(bytes: &[u8]) -> &'static str {
+    infer::get(bytes)
+        .map(|kind| kind.mime_type())
+        .unwrap_or("application/octet-stream")
+}
+
+/// Human-readable byte count, e.g. `1.5 KiB`.
+pub fn format_size(len: usize) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if len < 1024 {
+        return format!("{} bytes", len);
+    }
+    let mut size = len as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Classic `hexdump -C` style rendering of the first `limit` bytes.
+pub fn hex_dump(bytes: &[u8], limit: usize) -> String {
+    let mut out = String::new();
+    for (row, chunk) in bytes[..bytes.len().min(limit)].chunks(16).enumerate() {
+        let _ = write!(out, "{:08x}  ", row * 16);
+        for i in 0..16 {
+            match chunk.get(i) {
+                Some(byte) => {
+                    let _ = write!(out, "{:02x} ", byte);
+                }
+                None => out.push_str("   "),
+            }
+            if i == 7 {
+                out.push(' ');
+            }
+        }
+        out.push_str(" |");
+        for &byte in chunk {
+            out.push(if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            });
+        }
+        out.push_str("|\n");
+    }
+    if bytes.len() > limit {
+        let _ = write!(out, "... {} more bytes", bytes.len() - limit);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_are_human_readable() {
+        assert_eq!(format_size(0), "0 bytes");
+        assert_eq!(format_size(1023), "1023 bytes");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(5 << 20), "5.0 MiB");
+        assert_eq!(format_size(usize::MAX), format!("{:.1} TiB", usize::MAX as f64 / (1u64 << 40) as f64));
+    }
+
+    #[test]
+    fn hex_dumps_pad_the_last_row() {
+        let dump = hex_dump(b"0123456789abcdef\x00\xffA", 4096);
+        assert_eq!(
+            dump,
+            "00000000  30 31 32 33 34 35 36 37  38 39 61 62 63 64 65 66  |0123456789abcdef|\n\
+             00000010  00 ff 41                                          |..A|\n"
+        );
+    }
+
+    #[test]
+    fn hex_dumps_stop_at_the_limit() {
+        let dump = hex_dump(&[0; 40], 16);
+        assert_eq!(dump.lines().count(), 2);
+        assert!(dump.ends_with("... 24 more bytes"), "{}", dump);
+    }
+
+    #[test]
+    fn detects_mime_types_from_magic_bytes() {
+        assert_eq!(detect_mime(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), "image/png");
+        assert_eq!(detect_mime(b"\x01\x02\x03"), "application/octet-stream");
+    }
+}
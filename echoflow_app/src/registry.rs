@@ -0,0 +1,293 @@
+use crate::executor::{Attempt, NodeType};
+use crate::io_nodes::{Sink, Source};
+use crate::json::{JsonOp, JsonSettings};
+use crate::script::ScriptSettings;
+use crate::table::{TableOp, TableSettings};
+use crate::transform::Transform;
+use echoflow_node_api::{Config, NodeKind, Ports};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+/// A node of a kind from the registry: which kind, and its settings.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PluginNode {
+    /// The kind's `NodeKind::id`.
+    pub kind: String,
+    pub config: Config,
+}
+
+/// A button in the toolbox.
+pub struct ToolboxEntry {
+    pub name: String,
+    pub category: String,
+    pub description: String,
+    /// Builds the node's type and command.
+    make: Box<dyn Fn() -> (NodeType, String) + Send + Sync>,
+}
+
+/// Every node type the toolbox offers besides the user's presets: the
+/// built-in nodes, and the node kinds compiled in through cargo features.
+#[derive(Default)]
+pub struct Registry {
+    kinds: Vec<Box<dyn NodeKind>>,
+    entries: Vec<ToolboxEntry>,
+    /// Plugins that were found but could not be loaded, and why.
+    pub load_errors: Vec<String>,
+}
+
+/// The registry of this build.
+pub fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(Registry::with_builtins)
+}
+
+/// Node kinds compiled in through cargo features. Shipping a new kind is
+/// an optional dependency, a feature enabling it, and a line here.
+fn feature_kinds() -> Vec<Box<dyn NodeKind>> {
+    vec![
+        #[cfg(feature = "base64")]
+        Box::new(echoflow_base64::Base64),
+        #[cfg(test)]
+        Box::new(crate::executor::tests::Panics),
+    ]
+}
+
+impl PluginNode {
+    pub fn new(kind: &dyn NodeKind) -> Self {
+        Self {
+            kind: kind.id().to_string(),
+            config: Config::from_schema(&kind.config_schema()),
+        }
+    }
+
+    /// The kind from the registry, or an error naming the missing kind.
+    pub fn kind(&self) -> Result<&'static dyn NodeKind, String> {
+        registry().kind(&self.kind).ok_or_else(|| {
+            format!(
+                "Node kind '{}' is not built into this echoflow; enable the cargo feature that provides it.",
+                self.kind
+            )
+        })
+    }
+
+    pub fn summary(&self) -> String {
+        match self.kind() {
+            Ok(kind) => kind.summary(&self.config),
+            Err(_) => format!("{} (missing)", self.kind),
+        }
+    }
+
+    pub fn is_cacheable(&self) -> bool {
+        self.kind().is_ok_and(|kind| kind.ports() == Ports::Transform)
+    }
+
+    pub fn execute(&self, input: Option<&[u8]>) -> Result<Attempt, String> {
+        let kind = self.kind()?;
+        let input = input.unwrap_or_default();
+        let output = match kind.ports() {
+            Ports::Source => kind.execute(&self.config, &[])?,
+            Ports::Transform => kind.execute(&self.config, input)?,
+            Ports::Sink => {
+                let mut output = kind.execute(&self.config, input)?;
+                output.stdout = input.to_vec();
+                output
+            }
+        };
+        Ok(Attempt {
+            exit_code: Some(output.exit_code),
+            stdout: output.stdout,
+            stderr: output.stderr,
+            ..Attempt::default()
+        })
+    }
+}
+
+impl ToolboxEntry {
+    pub fn new(
+        name: impl Into<String>,
+        category: impl Into<String>,
+        make: impl Fn() -> (NodeType, String) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            category: category.into(),
+            description: String::new(),
+            make: Box::new(make),
+        }
+    }
+
+    fn describe(mut self, description: impl Into<String>) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    /// A fresh node type and command for a new node.
+    pub fn make(&self) -> (NodeType, String) {
+        (self.make)()
+    }
+}
+
+impl Registry {
+    fn with_builtins() -> Self {
+        let mut registry = Self::default();
+        registry.add_entry(ToolboxEntry::new("Text Input", "Input and output", || {
+            let text = String::new();
+            (NodeType::Source(Source::Text { text }), String::new())
+        }));
+        registry.add_entry(ToolboxEntry::new("Read File", "Input and output", || {
+            let path = "input.txt".into();
+            (NodeType::Source(Source::File { path }), String::new())
+        }));
+        registry.add_entry(ToolboxEntry::new("Write File", "Input and output", || {
+            let path = "output.txt".into();
+            (NodeType::Sink(Sink::Write { path }), String::new())
+        }));
+        for (index, transform) in Transform::choices().into_iter().enumerate() {
+            registry.add_entry(ToolboxEntry::new(transform.label(), "Built-in", move || {
+                (NodeType::Transform(Transform::choices()[index].clone()), String::new())
+            }));
+        }
+        registry.add_entry(ToolboxEntry::new("Script (Rhai)", "Built-in", || {
+            (NodeType::Script(ScriptSettings::default()), String::new())
+        }));
+        for (index, op) in JsonOp::choices().into_iter().enumerate() {
+            registry.add_entry(ToolboxEntry::new(format!("JSON: {}", op.label()), "Built-in", move || {
+                let op = JsonOp::choices()[index].clone();
+                (NodeType::Json(JsonSettings { op, ..JsonSettings::default() }), String::new())
+            }));
+        }
+        for (index, op) in TableOp::choices().into_iter().enumerate() {
+            registry.add_entry(ToolboxEntry::new(format!("Table: {}", op.label()), "Built-in", move || {
+                let op = TableOp::choices()[index].clone();
+                (NodeType::Table(TableSettings { op, ..TableSettings::default() }), String::new())
+            }));
+        }
+
+        for kind in feature_kinds() {
+            registry.register(kind);
+        }
+        #[cfg(feature = "wasm")]
+        {
+            let (kinds, errors) = crate::wasm::load_plugins(&crate::wasm::plugins_dir());
+            registry.load_errors = errors;
+            for kind in kinds {
+                registry.register(kind);
+            }
+        }
+        registry
+    }
+
+    /// Adds a node kind and its toolbox button, unless a kind with the
+    /// same id is already registered.
+    pub fn register(&mut self, kind: Box<dyn NodeKind>) {
+        let id = kind.id();
+        if self.kind(id).is_some() {
+            self.load_errors.push(format!("Node kind '{}' is registered twice; keeping the first.", id));
+            return;
+        }
+        self.add_entry(
+            ToolboxEntry::new(kind.name(), kind.category(), move || {
+                let node = registry().kind(id).map(PluginNode::new).expect("registered kinds stay registered");
+                (NodeType::Plugin(node), String::new())
+            })
+            .describe(kind.description()),
+        );
+        self.kinds.push(kind);
+    }
+
+    pub fn add_entry(&mut self, entry: ToolboxEntry) {
+        self.entries.push(entry);
+    }
+
+    pub fn kind(&self, id: &str) -> Option<&dyn NodeKind> {
+        self.kinds.iter().find(|kind| kind.id() == id).map(|kind| kind.as_ref())
+    }
+
+    pub fn kinds(&self) -> impl Iterator<Item = &dyn NodeKind> {
+        self.kinds.iter().map(|kind| kind.as_ref())
+    }
+
+    pub fn entries(&self) -> &[ToolboxEntry] {
+        &self.entries
+    }
+
+    /// Indices of the toolbox buttons grouped by category, categories in
+    /// the order they were first added.
+    pub fn categories(&self) -> Vec<(&str, Vec<usize>)> {
+        let mut categories: Vec<(&str, Vec<usize>)> = Vec::new();
+        for (index, entry) in self.entries.iter().enumerate() {
+            match categories.iter_mut().find(|(name, _)| *name == entry.category) {
+                Some((_, entries)) => entries.push(index),
+                None => categories.push((&entry.category, vec![index])),
+            }
+        }
+        categories
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use echoflow_node_api::{ConfigField, Output};
+
+    struct Named(&'static str, &'static str);
+
+    impl NodeKind for Named {
+        fn id(&self) -> &'static str {
+            self.0
+        }
+
+        fn name(&self) -> &'static str {
+            self.1
+        }
+
+        fn config_schema(&self) -> Vec<ConfigField> {
+            Vec::new()
+        }
+
+        fn execute(&self, _config: &Config, _input: &[u8]) -> Result<Output, String> {
+            Ok(Output::new(self.1.into()))
+        }
+    }
+
+    #[test]
+    fn a_kind_registered_twice_keeps_the_first() {
+        let mut registry = Registry::default();
+        registry.register(Box::new(Named("test.kind", "First")));
+        registry.register(Box::new(Named("test.kind", "Second")));
+        assert_eq!(registry.kinds().count(), 1);
+        assert_eq!(registry.kind("test.kind").unwrap().name(), "First");
+        assert_eq!(registry.entries().len(), 1);
+        assert_eq!(registry.load_errors, ["Node kind 'test.kind' is registered twice; keeping the first."]);
+    }
+
+    #[test]
+    fn categories_keep_the_order_they_first_appear_in() {
+        let mut registry = Registry::default();
+        for (name, category) in [("a", "Text"), ("b", "Files"), ("c", "Text")] {
+            registry.add_entry(ToolboxEntry::new(name, category, || (NodeType::Command, String::new())));
+        }
+        assert_eq!(registry.categories(), [("Text", vec![0, 2]), ("Files", vec![1])]);
+    }
+
+    #[test]
+    fn the_builtins_offer_every_built_in_node() {
+        let names: Vec<&str> = registry().entries().iter().map(|entry| entry.name.as_str()).collect();
+        for name in ["Text Input", "Count lines", "Script (Rhai)", "JSON: Flatten", "Table: Group and count"] {
+            assert!(names.contains(&name), "{} is missing from {:?}", name, names);
+        }
+    }
+
+    #[test]
+    fn nodes_of_missing_kinds_say_so() {
+        let node = PluginNode {
+            kind: "nobody.nothing".into(),
+            config: Config::default(),
+        };
+        let error = node.kind().err().unwrap();
+        assert!(error.contains("'nobody.nothing' is not built into this echoflow"), "{}", error);
+        assert_eq!(node.summary(), "nobody.nothing (missing)");
+        assert!(!node.is_cacheable());
+        assert!(node.execute(None).is_err());
+    }
+}
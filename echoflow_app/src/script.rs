@@ -0,0 +1,239 @@
+use crate::executor::{Attempt, split_lines};
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{AST, Dynamic, Engine, EvalAltResult, ParseError, Position, Scope};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+
+/// Operations one evaluation may take before it is stopped, so a runaway
+/// loop fails the node instead of hanging the run.
+const MAX_OPERATIONS: u64 = 50_000_000;
+
+/// A Rhai script run in-process over the node's input.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ScriptSettings {
+    pub mode: ScriptMode,
+    pub script: String,
+    /// Offer `read_file(path)` and `write_file(path, text)` to the script.
+    /// Without it the script cannot touch the filesystem.
+    pub allow_files: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ScriptMode {
+    /// The script runs once per line with `line` and `index` (0-based) set.
+    /// Returning a string emits it, an array emits each element as a line,
+    /// and returning nothing (`()`) drops the line.
+    PerLine,
+    /// The script runs once with the whole input in `input`; its result is
+    /// the output.
+    WholeInput,
+}
+
+impl Default for ScriptSettings {
+    fn default() -> Self {
+        Self {
+            mode: ScriptMode::PerLine,
+            script: "line.to_upper()".into(),
+            allow_files: false,
+        }
+    }
+}
+
+impl ScriptMode {
+    pub const ALL: [ScriptMode; 2] = [ScriptMode::PerLine, ScriptMode::WholeInput];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ScriptMode::PerLine => "Per line",
+            ScriptMode::WholeInput => "Whole input",
+        }
+    }
+}
+
+impl ScriptSettings {
+    /// Short description shown on the node: the first line of the script.
+    pub fn summary(&self) -> String {
+        let first: String = self.script.lines().next().unwrap_or_default().chars().take(24).collect();
+        format!("rhai: {}", first)
+    }
+
+    /// Fails with the line of the first syntax error, if any.
+    pub fn check(&self) -> Result<(), String> {
+        self.engine(Arc::default())
+            .compile(&self.script)
+            .map(|_| ())
+            .map_err(|e| parse_message(&e))
+    }
+
+    pub fn apply(&self, input: &[u8]) -> Result<Attempt, String> {
+        let printed = Arc::new(Mutex::new(Vec::new()));
+        let engine = self.engine(printed.clone());
+        let ast = engine.compile(&self.script).map_err(|e| parse_message(&e))?;
+        let mut stdout = Vec::with_capacity(input.len());
+        match self.mode {
+            ScriptMode::PerLine => {
+                for (index, line) in split_lines(input).into_iter().enumerate() {
+                    let mut scope = Scope::new();
+                    scope.push("line", String::from_utf8_lossy(line).into_owned());
+                    scope.push("index", index as i64);
+                    let result = eval(&engine, &mut scope, &ast)
+                        .map_err(|e| format!("{} (input line {})", e, index + 1))?;
+                    write_result(&mut stdout, result, true);
+                }
+            }
+            ScriptMode::WholeInput => {
+                let mut scope = Scope::new();
+                scope.push("input", String::from_utf8_lossy(input).into_owned());
+                let result = eval(&engine, &mut scope, &ast)?;
+                write_result(&mut stdout, result, false);
+            }
+        }
+        let stderr = std::mem::take(&mut *printed.lock().unwrap_or_else(|e| e.into_inner()));
+        Ok(Attempt {
+            exit_code: Some(0),
+            stdout,
+            stderr,
+            ..Attempt::default()
+        })
+    }
+
+    /// An engine that cannot import modules, stops runaway scripts, sends
+    /// `print` and `debug` to `printed` (shown as the node's stderr), and
+    /// only reaches files when the node allows it.
+    fn engine(&self, printed: Arc<Mutex<Vec<u8>>>) -> Engine {
+        let mut engine = Engine::new();
+        engine.set_module_resolver(DummyModuleResolver::new());
+        engine.set_max_operations(MAX_OPERATIONS);
+        let debug_printed = printed.clone();
+        engine.on_print(move |text| {
+            let mut out = printed.lock().unwrap_or_else(|e| e.into_inner());
+            out.extend_from_slice(text.as_bytes());
+            out.push(b'\n');
+        });
+        engine.on_debug(move |text, _, position| {
+            let mut out = debug_printed.lock().unwrap_or_else(|e| e.into_inner());
+            out.extend_from_slice(format!("{}{}\n", at_line(position), text).as_bytes());
+        });
+        if self.allow_files {
+            engine.register_fn("read_file", |path: &str| -> Result<String, Box<EvalAltResult>> {
+                std::fs::read_to_string(path).map_err(|e| format!("Failed to read '{}': {}", path, e).into())
+            });
+            engine.register_fn("write_file", |path: &str, text: &str| -> Result<(), Box<EvalAltResult>> {
+                std::fs::write(path, text).map_err(|e| format!("Failed to write '{}': {}", path, e).into())
+            });
+        }
+        engine
+    }
+}
+
+/// The script line an error message from this module points at.
+pub fn error_line(message: &str) -> Option<usize> {
+    let rest = &message[message.find("Script line ")? + "Script line ".len()..];
+    rest[..rest.find(':')?].parse().ok()
+}
+
+fn eval(engine: &Engine, scope: &mut Scope, ast: &AST) -> Result<Dynamic, String> {
+    engine
+        .eval_ast_with_scope::<Dynamic>(scope, ast)
+        .map_err(|mut e| {
+            let line = at_line(e.position());
+            e.clear_position();
+            format!("{}{}", line, e)
+        })
+}
+
+fn parse_message(error: &ParseError) -> String {
+    format!("{}{}", at_line(error.position()), error.err_type())
+}
+
+fn at_line(position: Position) -> String {
+    position
+        .line()
+        .map_or_else(|| "Script: ".to_string(), |line| format!("Script line {}: ", line))
+}
+
+fn write_result(out: &mut Vec<u8>, result: Dynamic, per_line: bool) {
+    if result.is_unit() {
+        return;
+    }
+    let lines = if result.is_array() {
+        result.cast::<rhai::Array>().into_iter().map(|item| item.to_string()).collect()
+    } else {
+        vec![result.to_string()]
+    };
+    for line in lines {
+        out.extend_from_slice(line.as_bytes());
+        if per_line || !line.ends_with('\n') {
+            out.push(b'\n');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn script(mode: ScriptMode, script: &str) -> ScriptSettings {
+        ScriptSettings {
+            mode,
+            script: script.into(),
+            allow_files: false,
+        }
+    }
+
+    fn run(settings: &ScriptSettings, input: &str) -> Result<(String, String), String> {
+        let attempt = settings.apply(input.as_bytes())?;
+        Ok((String::from_utf8(attempt.stdout).unwrap(), String::from_utf8(attempt.stderr).unwrap()))
+    }
+
+    #[test]
+    fn per_line_results_emit_drop_or_split_lines() {
+        let settings = script(
+            ScriptMode::PerLine,
+            r#"if line == "skip" { () } else if index == 2 { [line, line] } else { line.to_upper() }"#,
+        );
+        assert_eq!(run(&settings, "a\nskip\nc\n").unwrap().0, "A\nc\nc\n");
+    }
+
+    #[test]
+    fn whole_input_results_get_one_trailing_newline() {
+        let count = script(ScriptMode::WholeInput, "input.split(\"\\n\").len()");
+        assert_eq!(run(&count, "a\nb").unwrap().0, "2\n");
+        let same = script(ScriptMode::WholeInput, "input");
+        assert_eq!(run(&same, "a\n").unwrap().0, "a\n");
+    }
+
+    #[test]
+    fn prints_go_to_stderr() {
+        let settings = script(ScriptMode::PerLine, "print(`saw ${line}`); line");
+        assert_eq!(run(&settings, "x\n").unwrap(), ("x\n".into(), "saw x\n".into()));
+    }
+
+    #[test]
+    fn errors_name_the_script_line_and_input_line() {
+        let broken = script(ScriptMode::PerLine, "let x = 1;\nlet = ;");
+        let error = broken.check().unwrap_err();
+        assert_eq!(error_line(&error), Some(2));
+
+        let failing = script(ScriptMode::PerLine, "if index == 1 {\n  throw \"bad\";\n}\nline");
+        let error = run(&failing, "a\nb\n").unwrap_err();
+        assert!(error.starts_with("Script line 2: "), "{}", error);
+        assert!(error.ends_with("(input line 2)"), "{}", error);
+    }
+
+    #[test]
+    fn runaway_scripts_are_stopped() {
+        let forever = script(ScriptMode::WholeInput, "loop {}");
+        assert!(run(&forever, "").is_err());
+    }
+
+    #[test]
+    fn files_are_out_of_reach_unless_allowed() {
+        let mut settings = script(ScriptMode::WholeInput, r#"read_file("/nonexistent/echoflow")"#);
+        let denied = run(&settings, "").unwrap_err();
+        assert!(denied.contains("read_file"), "{}", denied);
+        settings.allow_files = true;
+        let missing = run(&settings, "").unwrap_err();
+        assert!(missing.contains("Failed to read '/nonexistent/echoflow'"), "{}", missing);
+    }
+}
@@ -0,0 +1,336 @@
+use crate::executor::NodeType;
+use crate::flowchart::{ConnectionKind, FlowChart, Node, NodeStatus};
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+/// A reusable group of nodes shown as a single node. Edges into and out of
+/// the group attach to named ports, each backed by one of the inner nodes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Subflow {
+    pub name: String,
+    /// Ports fed from outside; the data goes into the port's inner node.
+    pub inputs: Vec<Port>,
+    /// Ports read from outside; they carry the output of the port's inner node.
+    pub outputs: Vec<Port>,
+    pub chart: FlowChart,
+}
+
+/// A named entry or exit point of a subflow.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Port {
+    pub name: String,
+    /// ID of the inner node behind the port.
+    pub node: usize,
+    /// Port of that node, when it is itself a subflow.
+    #[serde(default)]
+    pub node_port: usize,
+}
+
+/// Which list of ports of a subflow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortSide {
+    Input,
+    Output,
+}
+
+// The inner chart is left out: subflow nodes never run themselves, their
+// inner nodes are expanded into jobs with their own cache keys.
+impl Hash for Subflow {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        self.inputs.hash(state);
+        self.outputs.hash(state);
+    }
+}
+
+impl Subflow {
+    /// Overall status of the nodes inside: running or failed if any of them
+    /// is, idle if none ran, otherwise succeeded (or cached/skipped when all
+    /// of them were).
+    pub fn status(&self) -> NodeStatus {
+        let statuses: Vec<NodeStatus> = self.chart.nodes.iter().map(|n| n.status).collect();
+        let any = |status| statuses.contains(&status);
+        let all = |status| statuses.iter().all(|s| *s == status);
+        if statuses.is_empty() || all(NodeStatus::Idle) {
+            NodeStatus::Idle
+        } else if any(NodeStatus::Running) {
+            NodeStatus::Running
+        } else if any(NodeStatus::Failed) {
+            NodeStatus::Failed
+        } else if any(NodeStatus::Queued) {
+            NodeStatus::Queued
+        } else if all(NodeStatus::Cached) {
+            NodeStatus::Cached
+        } else if all(NodeStatus::Skipped) {
+            NodeStatus::Skipped
+        } else {
+            NodeStatus::Succeeded
+        }
+    }
+
+    /// The inner node behind the first output port, whose output is shown
+    /// as the subflow node's output.
+    pub fn output_node(&self) -> Option<&Node> {
+        self.outputs
+            .first()
+            .and_then(|port| self.chart.nodes.iter().find(|n| n.id == port.node))
+    }
+}
+
+impl FlowChart {
+    /// Replaces the selected nodes with a single subflow node holding them,
+    /// placed at their centroid. Data edges crossing the selection are
+    /// rewired through ports: one input port per selected node fed from
+    /// outside, one output port per selected node read from outside.
+    /// Control edges into the selection gate the whole subflow.
+    pub fn collapse_selection(&mut self) -> Result<usize, String> {
+        let selection = self.selection.clone();
+        if selection.is_empty() {
+            return Err("Select the nodes to collapse first (shift-click adds to the selection).".into());
+        }
+        // A node both fed by and feeding the selection would end up in a
+        // cycle with the subflow.
+        let below: BTreeSet<usize> = selection
+            .iter()
+            .flat_map(|&id| self.descendants_of(id))
+            .filter(|id| !selection.contains(id))
+            .collect();
+        if let Some(between) = below
+            .into_iter()
+            .find(|&id| self.descendants_of(id).iter().any(|d| selection.contains(d)))
+        {
+            return Err(format!(
+                "Node {} lies between selected nodes; select it too so the subflow does not form a cycle.",
+                between
+            ));
+        }
+        let (inner, outer): (Vec<Node>, Vec<Node>) = std::mem::take(&mut self.nodes)
+            .into_iter()
+            .partition(|n| selection.contains(&n.id));
+        self.nodes = outer;
+
+        let id = self.next_id;
+        self.next_id += 1;
+        let centroid = inner
+            .iter()
+            .fold(egui::Vec2::ZERO, |sum, n| sum + n.pos.to_vec2())
+            / inner.len() as f32;
+        let mut chart = FlowChart::default();
+        chart.next_id = inner.iter().map(|n| n.id).max().unwrap_or(0) + 1;
+        chart.nodes = inner;
+        let mut subflow = Subflow {
+            name: format!("Subflow {}", id),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            chart,
+        };
+
+        for mut conn in std::mem::take(&mut self.connections) {
+            match (selection.contains(&conn.from), selection.contains(&conn.to)) {
+                (true, true) => subflow.chart.connections.push(conn),
+                (false, true) => {
+                    conn.to_port = match conn.kind {
+                        ConnectionKind::Data => {
+                            port_for(&mut subflow.inputs, "in", conn.to, conn.to_port)
+                        }
+                        _ => 0,
+                    };
+                    conn.to = id;
+                    self.connections.push(conn);
+                }
+                (true, false) => {
+                    conn.from_port = port_for(&mut subflow.outputs, "out", conn.from, conn.from_port);
+                    conn.from = id;
+                    self.connections.push(conn);
+                }
+                (false, false) => self.connections.push(conn),
+            }
+        }
+
+        let mut node = Node::new(id, NodeType::Subflow(Box::new(subflow)), String::new());
+        node.pos = centroid.to_pos2();
+        self.nodes.push(node);
+        if self.connection_start.is_some_and(|(start, _)| selection.contains(&start)) {
+            self.connection_start = None;
+        }
+        self.selection = BTreeSet::from([id]);
+        self.selected_node = Some(id);
+        self.refresh_subflows();
+        Ok(id)
+    }
+
+    /// Removes a port of a subflow node along with the edges attached to it.
+    pub fn remove_subflow_port(&mut self, id: usize, side: PortSide, index: usize) {
+        let Some(node) = self.nodes.iter_mut().find(|n| n.id == id) else {
+            return;
+        };
+        let NodeType::Subflow(subflow) = &mut node.node_type else {
+            return;
+        };
+        let ports = match side {
+            PortSide::Input => &mut subflow.inputs,
+            PortSide::Output => &mut subflow.outputs,
+        };
+        if index >= ports.len() {
+            return;
+        }
+        ports.remove(index);
+        self.connections.retain_mut(|conn| {
+            let port = match side {
+                PortSide::Input if conn.to == id && conn.kind == ConnectionKind::Data => {
+                    &mut conn.to_port
+                }
+                PortSide::Output if conn.from == id => &mut conn.from_port,
+                _ => return true,
+            };
+            if *port == index {
+                return false;
+            }
+            if *port > index {
+                *port -= 1;
+            }
+            true
+        });
+    }
+}
+
+/// Index of the port backed by the given inner node, adding one if needed.
+fn port_for(ports: &mut Vec<Port>, prefix: &str, node: usize, node_port: usize) -> usize {
+    if let Some(index) = ports
+        .iter()
+        .position(|p| p.node == node && p.node_port == node_port)
+    {
+        return index;
+    }
+    ports.push(Port {
+        name: format!("{}{}", prefix, ports.len() + 1),
+        node,
+        node_port,
+    });
+    ports.len() - 1
+}
+
+/// Where the subflow library lives unless the user picks another file.
+pub fn default_library_path() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("echoflow")
+        .join("library.json")
+}
+
+/// Reads the subflows saved in a library file. A missing file is an empty library.
+pub fn load_library(path: &Path) -> Result<Vec<Subflow>, String> {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+    };
+    serde_json::from_slice(&data).map_err(|e| format!("Invalid library {}: {}", path.display(), e))
+}
+
+/// Adds a subflow to a library file, replacing any entry with the same name.
+pub fn save_to_library(path: &Path, subflow: &Subflow) -> Result<(), String> {
+    let mut library = load_library(path)?;
+    match library.iter_mut().find(|entry| entry.name == subflow.name) {
+        Some(entry) => *entry = subflow.clone(),
+        None => library.push(subflow.clone()),
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    let data = serde_json::to_vec_pretty(&library).map_err(|e| e.to_string())?;
+    std::fs::write(path, data).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flowchart::RunScope;
+    use crate::flowchart::tests::{flow, run};
+
+    fn subflow(chart: &FlowChart, id: usize) -> &Subflow {
+        match &chart.node_at_path(&[id]).unwrap().node_type {
+            NodeType::Subflow(subflow) => subflow,
+            _ => panic!("node {} is not a subflow", id),
+        }
+    }
+
+    #[test]
+    fn collapsing_keeps_what_the_flow_does() {
+        let mut chart = flow(&["echo a", "tr a b", "tr b c", "cat"], &[(1, 2), (2, 3), (3, 4)]);
+        chart.selection = BTreeSet::from([2, 3]);
+        let id = chart.collapse_selection().unwrap();
+        assert_eq!(chart.nodes.len(), 3);
+        let ports = |ports: &[Port]| ports.iter().map(|p| (p.name.clone(), p.node)).collect::<Vec<_>>();
+        assert_eq!(ports(&subflow(&chart, id).inputs), [("in1".into(), 2)]);
+        assert_eq!(ports(&subflow(&chart, id).outputs), [("out1".into(), 3)]);
+        assert_eq!(subflow(&chart, id).chart.connections.len(), 1);
+
+        assert_eq!(run(&mut chart, RunScope::All).unwrap(), b"c\n");
+        assert_eq!(subflow(&chart, id).status(), NodeStatus::Succeeded);
+        assert_eq!(subflow(&chart, id).output_node().unwrap().output, b"c\n");
+    }
+
+    #[test]
+    fn collapsing_around_an_outside_node_would_make_a_cycle() {
+        let mut chart = flow(&["echo a", "cat", "cat"], &[(1, 2), (2, 3)]);
+        chart.selection = BTreeSet::from([1, 3]);
+        let error = chart.collapse_selection().unwrap_err();
+        assert!(error.starts_with("Node 2 lies between"), "{}", error);
+        assert_eq!(chart.nodes.len(), 3);
+    }
+
+    #[test]
+    fn removing_a_port_drops_its_edges_and_renumbers_the_rest() {
+        let mut chart = flow(&["echo a", "echo b", "cat", "cat"], &[(1, 3), (2, 4)]);
+        chart.selection = BTreeSet::from([1, 2]);
+        let id = chart.collapse_selection().unwrap();
+        assert_eq!(subflow(&chart, id).outputs.len(), 2);
+        chart.remove_subflow_port(id, PortSide::Output, 0);
+        assert_eq!(subflow(&chart, id).outputs.len(), 1);
+        let edges: Vec<_> = chart.connections.iter().map(|c| (c.from, c.from_port, c.to)).collect();
+        assert_eq!(edges, [(id, 0, 4)]);
+    }
+
+    #[test]
+    fn status_sums_up_the_inner_nodes() {
+        let mut sub = Subflow {
+            name: "s".into(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            chart: flow(&["true", "true"], &[]),
+        };
+        assert_eq!(sub.status(), NodeStatus::Idle);
+        sub.chart.nodes[0].status = NodeStatus::Cached;
+        sub.chart.nodes[1].status = NodeStatus::Cached;
+        assert_eq!(sub.status(), NodeStatus::Cached);
+        sub.chart.nodes[1].status = NodeStatus::Succeeded;
+        assert_eq!(sub.status(), NodeStatus::Succeeded);
+        sub.chart.nodes[0].status = NodeStatus::Failed;
+        assert_eq!(sub.status(), NodeStatus::Failed);
+    }
+
+    #[test]
+    fn the_library_replaces_entries_by_name() {
+        let dir = std::env::temp_dir().join(format!("echoflow-library-{}", std::process::id()));
+        let path = dir.join("library.json");
+        assert!(load_library(&path).unwrap().is_empty());
+        let mut sub = Subflow {
+            name: "upper".into(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            chart: flow(&["tr a-z A-Z"], &[]),
+        };
+        save_to_library(&path, &sub).unwrap();
+        sub.chart.nodes[0].command = "tr a-z A-Z | rev".into();
+        save_to_library(&path, &sub).unwrap();
+        let library = load_library(&path).unwrap();
+        assert_eq!(library.len(), 1);
+        assert_eq!(library[0].chart.nodes[0].command, "tr a-z A-Z | rev");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,564 @@
+use crate::executor::Attempt;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// Settings of a table node: how its input is laid out and what to do
+/// with the rows. The output keeps the input's layout.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TableSettings {
+    pub format: TableFormat,
+    /// The first row names the columns and is kept at the top of the output.
+    pub header: bool,
+    pub op: TableOp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TableFormat {
+    Csv,
+    Tsv,
+    /// Columns separated by runs of spaces, like the output of `ps` or
+    /// `ls -l`. Written back aligned, like `column -t`.
+    Columns,
+}
+
+/// What a table node does. Columns are named by their header or by their
+/// 1-based number.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TableOp {
+    /// Keep the listed columns (comma-separated), in the listed order.
+    Select { columns: String },
+    /// Keep the rows whose cell in `column` passes the test against `value`.
+    Filter {
+        column: String,
+        test: RowTest,
+        value: String,
+    },
+    Sort {
+        column: String,
+        numeric: bool,
+        reverse: bool,
+    },
+    /// One row per distinct combination of the listed columns, with the
+    /// number of rows that had it, most frequent first.
+    GroupCount { columns: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RowTest {
+    Equal,
+    NotEqual,
+    /// Compares as numbers when both sides are numbers, as text otherwise.
+    Less,
+    Greater,
+    Contains,
+    /// The value is a regex the cell must match.
+    Matches,
+}
+
+/// Rows of text cells, with the header row split off when there is one.
+#[derive(Debug, Clone, Default)]
+pub struct Table {
+    pub header: Option<Vec<String>>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Default for TableSettings {
+    fn default() -> Self {
+        Self {
+            format: TableFormat::Csv,
+            header: true,
+            op: TableOp::Select {
+                columns: "1".into(),
+            },
+        }
+    }
+}
+
+impl TableFormat {
+    pub const ALL: [TableFormat; 3] = [TableFormat::Csv, TableFormat::Tsv, TableFormat::Columns];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            TableFormat::Csv => "CSV",
+            TableFormat::Tsv => "TSV",
+            TableFormat::Columns => "Aligned columns",
+        }
+    }
+
+    /// Guesses the layout from the first non-empty line.
+    pub fn sniff(bytes: &[u8]) -> TableFormat {
+        let first = bytes
+            .split(|&b| b == b'\n')
+            .find(|line| !line.iter().all(u8::is_ascii_whitespace))
+            .unwrap_or_default();
+        if first.contains(&b'\t') {
+            TableFormat::Tsv
+        } else if first.contains(&b',') {
+            TableFormat::Csv
+        } else {
+            TableFormat::Columns
+        }
+    }
+}
+
+impl RowTest {
+    pub const ALL: [RowTest; 6] = [
+        RowTest::Equal,
+        RowTest::NotEqual,
+        RowTest::Less,
+        RowTest::Greater,
+        RowTest::Contains,
+        RowTest::Matches,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            RowTest::Equal => "==",
+            RowTest::NotEqual => "!=",
+            RowTest::Less => "<",
+            RowTest::Greater => ">",
+            RowTest::Contains => "contains",
+            RowTest::Matches => "matches",
+        }
+    }
+}
+
+impl TableOp {
+    /// One of each operation, with default settings, for pickers.
+    pub fn choices() -> [TableOp; 4] {
+        [
+            TableOp::Select {
+                columns: "1".into(),
+            },
+            TableOp::Filter {
+                column: "1".into(),
+                test: RowTest::Equal,
+                value: String::new(),
+            },
+            TableOp::Sort {
+                column: "1".into(),
+                numeric: false,
+                reverse: false,
+            },
+            TableOp::GroupCount {
+                columns: "1".into(),
+            },
+        ]
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            TableOp::Select { .. } => "Select columns",
+            TableOp::Filter { .. } => "Filter rows",
+            TableOp::Sort { .. } => "Sort by column",
+            TableOp::GroupCount { .. } => "Group and count",
+        }
+    }
+}
+
+impl TableSettings {
+    /// Short description shown on the node.
+    pub fn summary(&self) -> String {
+        match &self.op {
+            TableOp::Select { columns } => format!("columns: {}", columns),
+            TableOp::Filter {
+                column,
+                test,
+                value,
+            } => format!("rows where {} {} {}", column, test.label(), value),
+            TableOp::Sort {
+                column, reverse, ..
+            } => format!("sort by {}{}", column, if *reverse { " desc" } else { "" }),
+            TableOp::GroupCount { columns } => format!("count by {}", columns),
+        }
+    }
+
+    /// Fails if no column is named or the filter regex is invalid. Column
+    /// names can only be checked against the data when the node runs.
+    pub fn check(&self) -> Result<(), String> {
+        let columns = match &self.op {
+            TableOp::Select { columns } | TableOp::GroupCount { columns } => columns,
+            TableOp::Filter { column, .. } | TableOp::Sort { column, .. } => column,
+        };
+        if split_columns(columns).next().is_none() {
+            return Err("No column is set.".into());
+        }
+        if let TableOp::Filter {
+            test: RowTest::Matches,
+            value,
+            ..
+        } = &self.op
+        {
+            Regex::new(value).map_err(|e| format!("Invalid pattern: {}", e))?;
+        }
+        Ok(())
+    }
+
+    pub fn apply(&self, input: &[u8]) -> Result<Attempt, String> {
+        let mut table = parse_table(input, self.format, self.header)?;
+        match &self.op {
+            TableOp::Select { columns } => {
+                let indexes = table.column_indexes(columns)?;
+                let pick = |row: &Vec<String>| {
+                    indexes
+                        .iter()
+                        .map(|&i| row.get(i).cloned().unwrap_or_default())
+                        .collect()
+                };
+                table.header = table.header.as_ref().map(pick);
+                table.rows = table.rows.iter().map(pick).collect();
+            }
+            TableOp::Filter {
+                column,
+                test,
+                value,
+            } => {
+                let index = table.column_index(column)?;
+                let regex = match test {
+                    RowTest::Matches => Some(Regex::new(value).map_err(|e| format!("Invalid pattern: {}", e))?),
+                    _ => None,
+                };
+                table.rows.retain(|row| {
+                    let cell = row.get(index).map_or("", String::as_str);
+                    match test {
+                        RowTest::Equal => cell == value,
+                        RowTest::NotEqual => cell != value,
+                        RowTest::Less => compare_cells(cell, value, true) == Ordering::Less,
+                        RowTest::Greater => compare_cells(cell, value, true) == Ordering::Greater,
+                        RowTest::Contains => cell.contains(value.as_str()),
+                        RowTest::Matches => regex.as_ref().is_some_and(|regex| regex.is_match(cell)),
+                    }
+                });
+            }
+            TableOp::Sort {
+                column,
+                numeric,
+                reverse,
+            } => {
+                let index = table.column_index(column)?;
+                let order = table.row_order(index, *numeric, *reverse);
+                let mut rows: Vec<Option<Vec<String>>> = table.rows.into_iter().map(Some).collect();
+                table.rows = order.into_iter().filter_map(|row| rows[row].take()).collect();
+            }
+            TableOp::GroupCount { columns } => {
+                let indexes = table.column_indexes(columns)?;
+                let mut counts: HashMap<Vec<String>, usize> = HashMap::new();
+                for row in &table.rows {
+                    let key = indexes
+                        .iter()
+                        .map(|&i| row.get(i).cloned().unwrap_or_default())
+                        .collect();
+                    *counts.entry(key).or_default() += 1;
+                }
+                let mut groups: Vec<_> = counts.into_iter().collect();
+                groups.sort_by(|(a_key, a), (b_key, b)| b.cmp(a).then_with(|| a_key.cmp(b_key)));
+                table.header = table.header.map(|header| {
+                    let mut names: Vec<String> = indexes
+                        .iter()
+                        .map(|&i| header.get(i).cloned().unwrap_or_default())
+                        .collect();
+                    names.push("count".into());
+                    names
+                });
+                table.rows = groups
+                    .into_iter()
+                    .map(|(mut key, count)| {
+                        key.push(count.to_string());
+                        key
+                    })
+                    .collect();
+            }
+        }
+        Ok(Attempt {
+            exit_code: Some(0),
+            stdout: write_table(&table, self.format)?,
+            ..Attempt::default()
+        })
+    }
+}
+
+impl Table {
+    /// Number of columns of the widest row.
+    pub fn width(&self) -> usize {
+        self.header
+            .iter()
+            .chain(&self.rows)
+            .map(Vec::len)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Finds a column by header name, or else by 1-based number.
+    fn column_index(&self, column: &str) -> Result<usize, String> {
+        let column = column.trim();
+        if let Some(index) = self
+            .header
+            .as_ref()
+            .and_then(|header| header.iter().position(|name| name == column))
+        {
+            return Ok(index);
+        }
+        match column.parse::<usize>() {
+            Ok(number) if number >= 1 => Ok(number - 1),
+            _ => Err(format!("No column named '{}'.", column)),
+        }
+    }
+
+    fn column_indexes(&self, columns: &str) -> Result<Vec<usize>, String> {
+        split_columns(columns).map(|column| self.column_index(column)).collect()
+    }
+
+    /// Row indexes in the order of a stable sort by one column; missing
+    /// cells sort as empty.
+    pub fn row_order(&self, index: usize, numeric: bool, reverse: bool) -> Vec<usize> {
+        let cell = |row: usize| self.rows[row].get(index).map_or("", String::as_str);
+        let mut order: Vec<usize> = (0..self.rows.len()).collect();
+        order.sort_by(|&a, &b| {
+            let order = compare_cells(cell(a), cell(b), numeric);
+            if reverse { order.reverse() } else { order }
+        });
+        order
+    }
+
+    /// Whether every non-empty cell of the column is a number.
+    pub fn is_numeric_column(&self, index: usize) -> bool {
+        self.rows.iter().all(|row| {
+            row.get(index)
+                .is_none_or(|cell| cell.trim().is_empty() || cell.trim().parse::<f64>().is_ok())
+        })
+    }
+}
+
+/// Splits text into a table. Rows may have different lengths; bytes that
+/// are not UTF-8 are replaced.
+pub fn parse_table(input: &[u8], format: TableFormat, header: bool) -> Result<Table, String> {
+    let mut rows: Vec<Vec<String>> = match format {
+        TableFormat::Csv | TableFormat::Tsv => {
+            let delimiter = if format == TableFormat::Csv { b',' } else { b'\t' };
+            csv::ReaderBuilder::new()
+                .delimiter(delimiter)
+                .has_headers(false)
+                .flexible(true)
+                .from_reader(input)
+                .byte_records()
+                .map(|record| {
+                    record
+                        .map(|record| {
+                            record
+                                .iter()
+                                .map(|cell| String::from_utf8_lossy(cell).into_owned())
+                                .collect()
+                        })
+                        .map_err(|e| format!("Input is not valid {}: {}", format.label(), e))
+                })
+                .collect::<Result<_, _>>()?
+        }
+        TableFormat::Columns => String::from_utf8_lossy(input)
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| line.split_whitespace().map(str::to_string).collect())
+            .collect(),
+    };
+    let header = if header && !rows.is_empty() {
+        Some(rows.remove(0))
+    } else {
+        None
+    };
+    Ok(Table { header, rows })
+}
+
+fn write_table(table: &Table, format: TableFormat) -> Result<Vec<u8>, String> {
+    let all_rows = table.header.iter().chain(&table.rows);
+    match format {
+        TableFormat::Csv | TableFormat::Tsv => {
+            let delimiter = if format == TableFormat::Csv { b',' } else { b'\t' };
+            let mut writer = csv::WriterBuilder::new()
+                .delimiter(delimiter)
+                .flexible(true)
+                .from_writer(Vec::new());
+            for row in all_rows {
+                writer
+                    .write_record(row)
+                    .map_err(|e| format!("Failed to write {}: {}", format.label(), e))?;
+            }
+            writer
+                .into_inner()
+                .map_err(|e| format!("Failed to write {}: {}", format.label(), e))
+        }
+        TableFormat::Columns => {
+            let mut widths = vec![0; table.width()];
+            for row in all_rows.clone() {
+                for (width, cell) in widths.iter_mut().zip(row) {
+                    *width = (*width).max(cell.chars().count());
+                }
+            }
+            let mut out = String::new();
+            for row in all_rows {
+                let mut line = String::new();
+                for (index, cell) in row.iter().enumerate() {
+                    if index + 1 == row.len() {
+                        line.push_str(cell);
+                    } else {
+                        let pad = widths[index] - cell.chars().count();
+                        line.push_str(cell);
+                        line.push_str(&" ".repeat(pad + 2));
+                    }
+                }
+                out.push_str(&line);
+                out.push('\n');
+            }
+            Ok(out.into_bytes())
+        }
+    }
+}
+
+fn split_columns(columns: &str) -> impl Iterator<Item = &str> {
+    columns.split(',').map(str::trim).filter(|column| !column.is_empty())
+}
+
+/// Compares two cells as numbers when asked and both parse, as text otherwise.
+fn compare_cells(a: &str, b: &str, numeric: bool) -> Ordering {
+    if numeric
+        && let (Ok(x), Ok(y)) = (a.trim().parse::<f64>(), b.trim().parse::<f64>())
+    {
+        return x.total_cmp(&y);
+    }
+    a.cmp(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PEOPLE: &str = "name,age,city\nann,31,Oslo\nbob,9,Rome\ncid,100,Oslo\n";
+
+    fn run(op: TableOp, format: TableFormat, input: &str) -> String {
+        let settings = TableSettings {
+            format,
+            header: true,
+            op,
+        };
+        String::from_utf8(settings.apply(input.as_bytes()).unwrap().stdout).unwrap()
+    }
+
+    #[test]
+    fn selects_columns_by_name_or_number() {
+        let select = TableOp::Select {
+            columns: "city, 1".into(),
+        };
+        assert_eq!(
+            run(select, TableFormat::Csv, PEOPLE),
+            "city,name\nOslo,ann\nRome,bob\nOslo,cid\n"
+        );
+    }
+
+    #[test]
+    fn unknown_columns_fail() {
+        let settings = TableSettings {
+            op: TableOp::Select {
+                columns: "height".into(),
+            },
+            ..TableSettings::default()
+        };
+        assert_eq!(settings.apply(PEOPLE.as_bytes()).unwrap_err(), "No column named 'height'.");
+    }
+
+    #[test]
+    fn filters_rows() {
+        let filter = |test, value: &str| TableOp::Filter {
+            column: "age".into(),
+            test,
+            value: value.into(),
+        };
+        assert_eq!(
+            run(filter(RowTest::Greater, "10"), TableFormat::Csv, PEOPLE),
+            "name,age,city\nann,31,Oslo\ncid,100,Oslo\n"
+        );
+        assert_eq!(
+            run(filter(RowTest::Matches, "^\\d$"), TableFormat::Csv, PEOPLE),
+            "name,age,city\nbob,9,Rome\n"
+        );
+    }
+
+    #[test]
+    fn sorts_numerically_or_as_text() {
+        let sort = |numeric, reverse| TableOp::Sort {
+            column: "age".into(),
+            numeric,
+            reverse,
+        };
+        assert_eq!(
+            run(sort(true, false), TableFormat::Csv, PEOPLE),
+            "name,age,city\nbob,9,Rome\nann,31,Oslo\ncid,100,Oslo\n"
+        );
+        assert_eq!(
+            run(sort(false, true), TableFormat::Csv, PEOPLE),
+            "name,age,city\nbob,9,Rome\nann,31,Oslo\ncid,100,Oslo\n"
+        );
+    }
+
+    #[test]
+    fn groups_and_counts_most_frequent_first() {
+        let group = TableOp::GroupCount {
+            columns: "city".into(),
+        };
+        assert_eq!(
+            run(group, TableFormat::Tsv, &PEOPLE.replace(',', "\t")),
+            "city\tcount\nOslo\t2\nRome\t1\n"
+        );
+    }
+
+    #[test]
+    fn aligned_columns_round_trip() {
+        let select = TableOp::Select {
+            columns: "1,3".into(),
+        };
+        let input = "PID  TTY   CMD\n1    ?     init\n4242 pts/0 bash\n";
+        assert_eq!(
+            run(select, TableFormat::Columns, input),
+            "PID   CMD\n1     init\n4242  bash\n"
+        );
+    }
+
+    #[test]
+    fn csv_quoting_survives() {
+        let select = TableOp::Select {
+            columns: "2".into(),
+        };
+        let input = "a,b\n1,\"x, y\"\n";
+        assert_eq!(run(select, TableFormat::Csv, input), "b\n\"x, y\"\n");
+    }
+
+    #[test]
+    fn sniffs_the_format() {
+        assert_eq!(TableFormat::sniff(b"\na\tb,c\n"), TableFormat::Tsv);
+        assert_eq!(TableFormat::sniff(b"a,b\n"), TableFormat::Csv);
+        assert_eq!(TableFormat::sniff(b"a  b\n"), TableFormat::Columns);
+    }
+
+    #[test]
+    fn check_needs_a_column_and_a_valid_regex() {
+        let check = |op| {
+            TableSettings {
+                op,
+                ..TableSettings::default()
+            }
+            .check()
+        };
+        assert!(check(TableOp::Select { columns: " , ".into() }).is_err());
+        assert!(check(TableOp::Filter {
+            column: "1".into(),
+            test: RowTest::Matches,
+            value: "(".into(),
+        })
+        .is_err());
+        assert!(check(TableOp::Filter {
+            column: "1".into(),
+            test: RowTest::Contains,
+            value: "(".into(),
+        })
+        .is_ok());
+    }
+}
@@ -0,0 +1,481 @@
+use crate::executor::{Attempt, split_lines};
+use regex::bytes::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+/// A line-oriented text transform run in-process, standing in for the
+/// coreutils command of the same name. Lines are handled as raw bytes, so
+/// input that is not UTF-8 passes through intact.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Transform {
+    /// Keep the lines matching a regex (`grep`). Like `grep`, exits with
+    /// code 1 when no line is kept.
+    Filter {
+        pattern: String,
+        invert: bool,
+        ignore_case: bool,
+    },
+    /// Replace regex matches in every line (`sed s/…/…/`). `${1}` or `${name}`
+    /// in the replacement refer to capture groups.
+    Replace {
+        pattern: String,
+        replacement: String,
+        all: bool,
+        ignore_case: bool,
+    },
+    Sort {
+        /// Compare the leading numbers of the lines (`sort -n`).
+        numeric: bool,
+        reverse: bool,
+        /// Drop repeated lines (`sort -u`).
+        unique: bool,
+    },
+    /// Collapse runs of identical lines (`uniq`), optionally prefixed with
+    /// their count (`uniq -c`).
+    Uniq { count: bool },
+    Head { lines: usize },
+    Tail { lines: usize },
+    /// Number of lines (`wc -l`).
+    CountLines,
+    /// Keep some fields of every line (`cut -d -f`). `\t` in the delimiter
+    /// stands for a tab; an empty delimiter splits on runs of whitespace,
+    /// like `awk`.
+    Cut { delimiter: String, fields: String },
+    Case { mode: CaseMode },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CaseMode {
+    Upper,
+    Lower,
+    /// First letter of every word upper case, the rest lower case.
+    Title,
+}
+
+impl CaseMode {
+    pub const ALL: [CaseMode; 3] = [CaseMode::Upper, CaseMode::Lower, CaseMode::Title];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            CaseMode::Upper => "UPPER CASE",
+            CaseMode::Lower => "lower case",
+            CaseMode::Title => "Title Case",
+        }
+    }
+}
+
+impl Transform {
+    /// One of each transform, with default settings, for the toolbox and pickers.
+    pub fn choices() -> [Transform; 9] {
+        [
+            Transform::Filter {
+                pattern: String::new(),
+                invert: false,
+                ignore_case: false,
+            },
+            Transform::Replace {
+                pattern: String::new(),
+                replacement: String::new(),
+                all: true,
+                ignore_case: false,
+            },
+            Transform::Sort {
+                numeric: false,
+                reverse: false,
+                unique: false,
+            },
+            Transform::Uniq { count: false },
+            Transform::Head { lines: 10 },
+            Transform::Tail { lines: 10 },
+            Transform::CountLines,
+            Transform::Cut {
+                delimiter: "\\t".into(),
+                fields: "1".into(),
+            },
+            Transform::Case {
+                mode: CaseMode::Upper,
+            },
+        ]
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Transform::Filter { .. } => "Filter lines",
+            Transform::Replace { .. } => "Replace",
+            Transform::Sort { .. } => "Sort lines",
+            Transform::Uniq { .. } => "Unique lines",
+            Transform::Head { .. } => "First lines",
+            Transform::Tail { .. } => "Last lines",
+            Transform::CountLines => "Count lines",
+            Transform::Cut { .. } => "Cut fields",
+            Transform::Case { .. } => "Change case",
+        }
+    }
+
+    /// Short description shown on the node.
+    pub fn summary(&self) -> String {
+        match self {
+            Transform::Filter {
+                pattern, invert, ..
+            } => format!("{}: {}", if *invert { "drop" } else { "keep" }, pattern),
+            Transform::Replace {
+                pattern,
+                replacement,
+                ..
+            } => format!("{} → {}", pattern, replacement),
+            Transform::Sort {
+                numeric, reverse, ..
+            } => match (numeric, reverse) {
+                (false, false) => "sort".into(),
+                (true, false) => "sort numbers".into(),
+                (false, true) => "sort reversed".into(),
+                (true, true) => "sort numbers reversed".into(),
+            },
+            Transform::Uniq { count: false } => "unique".into(),
+            Transform::Uniq { count: true } => "count repeats".into(),
+            Transform::Head { lines } => format!("first {}", lines),
+            Transform::Tail { lines } => format!("last {}", lines),
+            Transform::CountLines => "count lines".into(),
+            Transform::Cut { fields, .. } => format!("fields {}", fields),
+            Transform::Case { mode } => mode.label().into(),
+        }
+    }
+
+    /// Fails if the settings cannot work, e.g. an invalid regex.
+    pub fn check(&self) -> Result<(), String> {
+        match self {
+            Transform::Filter {
+                pattern,
+                ignore_case,
+                ..
+            }
+            | Transform::Replace {
+                pattern,
+                ignore_case,
+                ..
+            } => build_regex(pattern, *ignore_case).map(|_| ()),
+            Transform::Cut { fields, .. } => parse_fields(fields).map(|_| ()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Runs the transform over the input.
+    pub fn apply(&self, input: &[u8]) -> Result<Attempt, String> {
+        let lines = split_lines(input);
+        let mut exit_code = 0;
+        let out: Vec<Vec<u8>> = match self {
+            Transform::Filter {
+                pattern,
+                invert,
+                ignore_case,
+            } => {
+                let regex = build_regex(pattern, *ignore_case)?;
+                let kept: Vec<Vec<u8>> = lines
+                    .into_iter()
+                    .filter(|line| regex.is_match(line) != *invert)
+                    .map(<[u8]>::to_vec)
+                    .collect();
+                if kept.is_empty() {
+                    exit_code = 1;
+                }
+                kept
+            }
+            Transform::Replace {
+                pattern,
+                replacement,
+                all,
+                ignore_case,
+            } => {
+                let regex = build_regex(pattern, *ignore_case)?;
+                let limit = if *all { 0 } else { 1 };
+                lines
+                    .into_iter()
+                    .map(|line| regex.replacen(line, limit, replacement.as_bytes()).into_owned())
+                    .collect()
+            }
+            Transform::Sort {
+                numeric,
+                reverse,
+                unique,
+            } => {
+                let mut lines = lines;
+                if *numeric {
+                    lines.sort_by(|a, b| compare_numeric(a, b));
+                } else {
+                    lines.sort();
+                }
+                if *reverse {
+                    lines.reverse();
+                }
+                if *unique {
+                    lines.dedup();
+                }
+                lines.into_iter().map(<[u8]>::to_vec).collect()
+            }
+            Transform::Uniq { count } => {
+                let mut runs: Vec<(usize, &[u8])> = Vec::new();
+                for line in lines {
+                    match runs.last_mut() {
+                        Some((n, last)) if *last == line => *n += 1,
+                        _ => runs.push((1, line)),
+                    }
+                }
+                runs.into_iter()
+                    .map(|(n, line)| {
+                        if *count {
+                            let mut out = format!("{:>7} ", n).into_bytes();
+                            out.extend_from_slice(line);
+                            out
+                        } else {
+                            line.to_vec()
+                        }
+                    })
+                    .collect()
+            }
+            Transform::Head { lines: n } => lines.into_iter().take(*n).map(<[u8]>::to_vec).collect(),
+            Transform::Tail { lines: n } => {
+                let skip = lines.len().saturating_sub(*n);
+                lines.into_iter().skip(skip).map(<[u8]>::to_vec).collect()
+            }
+            Transform::CountLines => vec![lines.len().to_string().into_bytes()],
+            Transform::Cut { delimiter, fields } => {
+                let ranges = parse_fields(fields)?;
+                let delimiter = delimiter.replace("\\t", "\t");
+                lines
+                    .into_iter()
+                    .map(|line| cut_line(line, delimiter.as_bytes(), &ranges))
+                    .collect()
+            }
+            Transform::Case { mode } => lines.into_iter().map(|line| change_case(line, *mode)).collect(),
+        };
+        let mut stdout = Vec::with_capacity(input.len());
+        for line in out {
+            stdout.extend_from_slice(&line);
+            stdout.push(b'\n');
+        }
+        Ok(Attempt {
+            exit_code: Some(exit_code),
+            stdout,
+            ..Attempt::default()
+        })
+    }
+}
+
+fn build_regex(pattern: &str, ignore_case: bool) -> Result<Regex, String> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|e| format!("Invalid pattern: {}", e))
+}
+
+/// Orders lines by their leading number, like `sort -n`: lines without one
+/// count as zero, and ties fall back to comparing the bytes.
+fn compare_numeric(a: &[u8], b: &[u8]) -> Ordering {
+    leading_number(a)
+        .total_cmp(&leading_number(b))
+        .then_with(|| a.cmp(b))
+}
+
+/// The number a line starts with, ignoring leading whitespace. Only one
+/// decimal point counts, so a version like `1.2.3` reads as `1.2`.
+fn leading_number(line: &[u8]) -> f64 {
+    let text = String::from_utf8_lossy(line);
+    let text = text.trim_start();
+    let mut seen_point = false;
+    let end = text
+        .char_indices()
+        .find(|&(i, c)| match c {
+            '0'..='9' => false,
+            '.' if !seen_point => {
+                seen_point = true;
+                false
+            }
+            '-' | '+' => i != 0,
+            _ => true,
+        })
+        .map_or(text.len(), |(i, _)| i);
+    text[..end].parse().unwrap_or(0.0)
+}
+
+/// Parses a `cut -f` list such as `1,3-4,6-` into 1-based inclusive ranges.
+fn parse_fields(spec: &str) -> Result<Vec<(usize, usize)>, String> {
+    let invalid = || format!("Invalid field list '{}'; use e.g. 1,3-4,6-", spec);
+    spec.split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let (start, end) = match part.split_once('-') {
+                Some((start, end)) => (start.trim(), end.trim()),
+                None => (part, part),
+            };
+            let start = if start.is_empty() { 1 } else { start.parse().map_err(|_| invalid())? };
+            let end = if end.is_empty() { usize::MAX } else { end.parse().map_err(|_| invalid())? };
+            if start == 0 || end < start {
+                return Err(invalid());
+            }
+            Ok((start, end))
+        })
+        .collect()
+}
+
+fn cut_line(line: &[u8], delimiter: &[u8], ranges: &[(usize, usize)]) -> Vec<u8> {
+    let fields: Vec<&[u8]> = if delimiter.is_empty() {
+        line.split(|b| b.is_ascii_whitespace())
+            .filter(|field| !field.is_empty())
+            .collect()
+    } else {
+        split_on(line, delimiter)
+    };
+    let joiner: &[u8] = if delimiter.is_empty() { b" " } else { delimiter };
+    let mut out = Vec::new();
+    let mut first = true;
+    for (index, field) in fields.iter().enumerate() {
+        let number = index + 1;
+        if ranges.iter().any(|&(start, end)| start <= number && number <= end) {
+            if !first {
+                out.extend_from_slice(joiner);
+            }
+            out.extend_from_slice(field);
+            first = false;
+        }
+    }
+    out
+}
+
+fn split_on<'a>(line: &'a [u8], delimiter: &[u8]) -> Vec<&'a [u8]> {
+    let mut fields = Vec::new();
+    let mut rest = line;
+    while let Some(at) = rest.windows(delimiter.len()).position(|w| w == delimiter) {
+        fields.push(&rest[..at]);
+        rest = &rest[at + delimiter.len()..];
+    }
+    fields.push(rest);
+    fields
+}
+
+/// Changes the case of a line; lines that are not UTF-8 only get their
+/// ASCII letters changed.
+fn change_case(line: &[u8], mode: CaseMode) -> Vec<u8> {
+    let Ok(text) = std::str::from_utf8(line) else {
+        let mut out = line.to_vec();
+        match mode {
+            CaseMode::Upper => out.make_ascii_uppercase(),
+            CaseMode::Lower => out.make_ascii_lowercase(),
+            CaseMode::Title => {
+                let mut start = true;
+                for byte in &mut out {
+                    *byte = if start { byte.to_ascii_uppercase() } else { byte.to_ascii_lowercase() };
+                    start = byte.is_ascii_whitespace();
+                }
+            }
+        }
+        return out;
+    };
+    match mode {
+        CaseMode::Upper => text.to_uppercase(),
+        CaseMode::Lower => text.to_lowercase(),
+        CaseMode::Title => {
+            let mut out = String::with_capacity(text.len());
+            let mut start = true;
+            for c in text.chars() {
+                if start {
+                    out.extend(c.to_uppercase());
+                } else {
+                    out.extend(c.to_lowercase());
+                }
+                start = c.is_whitespace();
+            }
+            out
+        }
+    }
+    .into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(transform: Transform, input: &str) -> (String, Option<i32>) {
+        let attempt = transform.apply(input.as_bytes()).unwrap();
+        (String::from_utf8(attempt.stdout).unwrap(), attempt.exit_code)
+    }
+
+    #[test]
+    fn leading_number_stops_at_the_second_point() {
+        assert_eq!(leading_number(b"1.2.3"), 1.2);
+        assert_eq!(leading_number(b"  -4.5kg"), -4.5);
+        assert_eq!(leading_number(b"+7"), 7.0);
+        assert_eq!(leading_number(b"1-2"), 1.0);
+        assert_eq!(leading_number(b"abc"), 0.0);
+        assert_eq!(leading_number(b"-"), 0.0);
+    }
+
+    #[test]
+    fn numeric_sort_orders_versions_and_ties_by_bytes() {
+        let sort = Transform::Sort {
+            numeric: true,
+            reverse: false,
+            unique: false,
+        };
+        let (out, _) = run(sort, "10\n1.2.3\n2\nb\na\n1.10\n");
+        assert_eq!(out, "a\nb\n1.10\n1.2.3\n2\n10\n");
+    }
+
+    #[test]
+    fn filter_exits_with_one_when_nothing_is_kept() {
+        let filter = |pattern: &str, invert| Transform::Filter {
+            pattern: pattern.into(),
+            invert,
+            ignore_case: true,
+        };
+        assert_eq!(run(filter("^A", false), "apple\nbanana\n"), ("apple\n".into(), Some(0)));
+        assert_eq!(run(filter("^a", true), "apple\nbanana\n"), ("banana\n".into(), Some(0)));
+        assert_eq!(run(filter("x", false), "apple\n"), (String::new(), Some(1)));
+    }
+
+    #[test]
+    fn replace_uses_capture_groups() {
+        let replace = Transform::Replace {
+            pattern: r"(\w+)@(\w+)".into(),
+            replacement: "${2} at ${1}".into(),
+            all: false,
+            ignore_case: false,
+        };
+        let (out, _) = run(replace, "a@b c@d\n");
+        assert_eq!(out, "b at a c@d\n");
+    }
+
+    #[test]
+    fn uniq_counts_runs() {
+        let (out, _) = run(Transform::Uniq { count: true }, "a\na\nb\na\n");
+        assert_eq!(out, "      2 a\n      1 b\n      1 a\n");
+    }
+
+    #[test]
+    fn head_tail_and_count() {
+        let input = "1\n2\n3\n";
+        assert_eq!(run(Transform::Head { lines: 2 }, input).0, "1\n2\n");
+        assert_eq!(run(Transform::Tail { lines: 5 }, input).0, input);
+        assert_eq!(run(Transform::CountLines, input).0, "3\n");
+    }
+
+    #[test]
+    fn cut_fields_and_ranges() {
+        let cut = |delimiter: &str, fields: &str| Transform::Cut {
+            delimiter: delimiter.into(),
+            fields: fields.into(),
+        };
+        assert_eq!(run(cut("\\t", "1,3-"), "a\tb\tc\td\n").0, "a\tc\td\n");
+        assert_eq!(run(cut("", "2"), "  a   b c\n").0, "b\n");
+        assert_eq!(run(cut("::", "-2"), "a::b::c\n").0, "a::b\n");
+        assert!(cut(",", "0").check().is_err());
+        assert!(cut(",", "3-1").check().is_err());
+        assert!(cut(",", "x").check().is_err());
+    }
+
+    #[test]
+    fn title_case_handles_unicode_and_raw_bytes() {
+        assert_eq!(change_case("élan vital".as_bytes(), CaseMode::Title), "Élan Vital".as_bytes());
+        assert_eq!(change_case(b"ab \xff cd", CaseMode::Title), b"Ab \xff Cd");
+    }
+}
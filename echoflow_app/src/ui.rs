@@ -1,14 +1,34 @@
 use crate::app::PipelineApp;
 use crate::commands::FlowChartCommand;
+use crate::executor::{Backoff, MergeMode, NodeType, ProcessSettings};
+use crate::keymap::{self, KeyCombo, Press};
+use crate::map::{MapSettings, RecordSplit};
+use crate::palette;
+use crate::flowchart::{ConnectionKind, FlowChart};
+use crate::io_nodes::{self, Sink, Source};
+use crate::json::{Compare, JsonInput, JsonOp, JsonOutput, JsonSettings};
+use crate::preview::{self, OutputView};
+use crate::registry::{PluginNode, registry};
+use crate::script::{self, ScriptMode, ScriptSettings};
+use crate::subflow::{Port, PortSide};
+use crate::table::{RowTest, TableFormat, TableOp, TableSettings};
+use crate::transform::{CaseMode, Transform};
 use eframe::egui;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 
 impl eframe::App for PipelineApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         // Reset connection mode when the Escape key is pressed.
         if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
-            self.flowchart.connection_start = None;
+            self.active_chart_mut().connection_start = None;
         }
-        
+
+        self.poll_pipeline();
+        if self.flowchart.is_running() {
+            ctx.request_repaint_after(std::time::Duration::from_millis(50));
+        }
+
         self.draw_toolbox_panel(ctx);
         self.draw_top_panel(ctx);
         self.handle_keyboard_shortcuts(ctx);
@@ -16,6 +36,9 @@ impl eframe::App for PipelineApp {
         self.draw_bottom_panel(ctx);
         self.draw_central_panel(ctx);
         self.draw_minimap(ctx);
+        self.draw_preset_window(ctx);
+        self.draw_palette(ctx);
+        self.draw_shortcuts_window(ctx);
     }
 }
 
@@ -23,19 +46,250 @@ impl PipelineApp {
     fn draw_toolbox_panel(&mut self, ctx: &egui::Context) {
         egui::SidePanel::left("toolbox_panel").show(ctx, |ui| {
             ui.heading("Toolbox");
-            let presets = vec![
-                ("Echo", "echo Hello World"),
-                ("List Directory", "ls -la"),
-                ("Grep", "grep 'pattern'"),
-                ("Sort", "sort"),
-                ("Word Count", "wc -w"),
-            ];
-            for (name, command) in presets {
-                if ui.button(name).clicked() {
-                    self.flowchart.add_node_with_command(command);
+            ui.add(
+                egui::TextEdit::singleline(&mut self.toolbox_search)
+                    .hint_text("Search")
+                    .desired_width(f32::INFINITY),
+            );
+            let query = self.toolbox_search.to_lowercase();
+            let matches = |name: &str, description: &str| {
+                name.to_lowercase().contains(&query) || description.to_lowercase().contains(&query)
+            };
+
+            // User presets first, then the registry, grouped by category.
+            let mut groups: Vec<(String, Vec<ToolboxPick>)> = Vec::new();
+            let mut push = |category: &str, pick: ToolboxPick| {
+                match groups.iter_mut().find(|(name, _)| name == category) {
+                    Some((_, picks)) => picks.push(pick),
+                    None => groups.push((category.to_string(), vec![pick])),
+                }
+            };
+            for (index, preset) in self.presets.iter().enumerate() {
+                if matches(&preset.name, &preset.description) {
+                    push(&preset.category, ToolboxPick::Preset(index));
+                }
+            }
+            for (category, entries) in registry().categories() {
+                for index in entries {
+                    let entry = &registry().entries()[index];
+                    if matches(&entry.name, &entry.description) {
+                        push(category, ToolboxPick::Entry(index));
+                    }
+                }
+            }
+
+            let mut picked = None;
+            let mut remove = None;
+            egui::ScrollArea::vertical()
+                .id_source("toolbox_scroll")
+                .max_height(ui.available_height() * 0.7)
+                .show(ui, |ui| {
+                for (category, picks) in &groups {
+                    egui::CollapsingHeader::new(category.as_str())
+                        .default_open(true)
+                        .open(if query.is_empty() { None } else { Some(true) })
+                        .show(ui, |ui| {
+                            for pick in picks {
+                                let (title, description) = match pick {
+                                    ToolboxPick::Preset(index) => {
+                                        let preset = &self.presets[*index];
+                                        (preset.title(), preset.description.as_str())
+                                    }
+                                    ToolboxPick::Entry(index) => {
+                                        let entry = &registry().entries()[*index];
+                                        (entry.name.clone(), entry.description.as_str())
+                                    }
+                                };
+                                let mut button = ui.button(title);
+                                if !description.is_empty() {
+                                    button = button.on_hover_text(description);
+                                }
+                                if let ToolboxPick::Preset(index) = pick {
+                                    button.context_menu(|ui| {
+                                        if ui.button("Remove preset").clicked() {
+                                            remove = Some(*index);
+                                            ui.close_menu();
+                                        }
+                                    });
+                                }
+                                if button.clicked() {
+                                    picked = Some(*pick);
+                                }
+                            }
+                        });
+                }
+                if groups.is_empty() {
+                    ui.weak("Nothing matches.");
                 }
+            });
+            match picked {
+                Some(ToolboxPick::Preset(index)) => {
+                    self.execute_command(FlowChartCommand::AddPreset(index));
+                }
+                Some(ToolboxPick::Entry(index)) => {
+                    self.execute_command(FlowChartCommand::AddToolboxEntry(index));
+                }
+                None => {}
+            }
+            if let Some(index) = remove {
+                self.remove_preset(index);
+            }
+            ui.horizontal(|ui| {
+                let path = ui
+                    .add(egui::TextEdit::singleline(&mut self.presets_path).desired_width(100.0))
+                    .on_hover_text("Preset file");
+                if path.changed() {
+                    // The presets shown came from the old file; reload before saving.
+                    self.presets_loaded = false;
+                }
+                if ui.button("Reload").clicked() {
+                    self.reload_presets();
+                }
+            });
+            for problem in &registry().load_errors {
+                ui.colored_label(egui::Color32::RED, problem);
+            }
+            ui.separator();
+            ui.heading("Library");
+            let mut instantiate = None;
+            for (index, subflow) in self.library.iter().enumerate() {
+                if ui.button(&subflow.name).clicked() {
+                    instantiate = Some(index);
+                }
+            }
+            if self.library.is_empty() {
+                ui.weak("Saved subflows appear here.");
             }
+            ui.add(egui::TextEdit::singleline(&mut self.library_path).desired_width(140.0))
+                .on_hover_text("Library file");
+            if ui.button("Reload").clicked() {
+                self.reload_library();
+            }
+            if let Some(index) = instantiate {
+                let subflow = Box::new(self.library[index].clone());
+                self.active_chart_mut().add_node_of_type(NodeType::Subflow(subflow), "");
+            }
+        });
+    }
+
+    /// Lets the user name a preset made from the selected node before it
+    /// is added to the toolbox.
+    fn draw_preset_window(&mut self, ctx: &egui::Context) {
+        let Some(draft) = &mut self.preset_draft else {
+            return;
+        };
+        let mut open = true;
+        let mut save = false;
+        egui::Window::new("Save as preset")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("preset_form").num_columns(2).show(ui, |ui| {
+                    ui.label("Name:");
+                    ui.text_edit_singleline(&mut draft.name);
+                    ui.end_row();
+                    ui.label("Category:");
+                    ui.text_edit_singleline(&mut draft.category);
+                    ui.end_row();
+                    ui.label("Icon:");
+                    ui.text_edit_singleline(&mut draft.icon);
+                    ui.end_row();
+                    ui.label("Description:");
+                    ui.text_edit_singleline(&mut draft.description);
+                    ui.end_row();
+                });
+                ui.horizontal(|ui| {
+                    let named = !draft.name.trim().is_empty();
+                    if ui.add_enabled(named, egui::Button::new("Save")).clicked() {
+                        save = true;
+                    }
+                    if self.presets.iter().any(|preset| preset.name == draft.name) {
+                        ui.weak("Replaces the preset of the same name.");
+                    }
+                });
+            });
+        if save {
+            if let Some(draft) = self.preset_draft.take() {
+                self.save_preset(draft);
+            }
+        } else if !open {
+            self.preset_draft = None;
+        }
+    }
+
+    /// Fuzzy search over commands, presets, nodes and recent flow files;
+    /// the picked item runs through `execute_command`.
+    fn draw_palette(&mut self, ctx: &egui::Context) {
+        let Some(mut state) = self.palette.take() else {
+            return;
+        };
+        let items = palette::items(self);
+        let (up, down, enter, escape) = ctx.input_mut(|i| {
+            (
+                i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp),
+                i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown),
+                i.consume_key(egui::Modifiers::NONE, egui::Key::Enter),
+                i.consume_key(egui::Modifiers::NONE, egui::Key::Escape),
+            )
         });
+        let mut picked = None;
+        egui::Window::new("Command palette")
+            .title_bar(false)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 60.0))
+            .default_width(420.0)
+            .show(ctx, |ui| {
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut state.query)
+                        .hint_text("Command, preset, node or recent file")
+                        .desired_width(f32::INFINITY),
+                );
+                response.request_focus();
+                if response.changed() {
+                    state.selected = 0;
+                }
+                let matches = palette::search(items, &state.query);
+                if down {
+                    state.selected += 1;
+                }
+                if up {
+                    state.selected = state.selected.saturating_sub(1);
+                }
+                state.selected = state.selected.min(matches.len().saturating_sub(1));
+                if enter {
+                    picked = matches.get(state.selected).map(|item| item.command.clone());
+                }
+                egui::ScrollArea::vertical().max_height(320.0).show(ui, |ui| {
+                    for (index, item) in matches.iter().enumerate() {
+                        let response = ui
+                            .horizontal(|ui| {
+                                ui.add_sized([60.0, 16.0], egui::Label::new(egui::RichText::new(item.group).weak()));
+                                let response = ui.selectable_label(index == state.selected, &item.label);
+                                let keys = self.keymap.keys_for(&item.command);
+                                if !keys.is_empty() {
+                                    ui.weak(keys.join(", "));
+                                }
+                                response
+                            })
+                            .inner;
+                        if index == state.selected && (up || down) {
+                            response.scroll_to_me(None);
+                        }
+                        if response.clicked() {
+                            picked = Some(item.command.clone());
+                        }
+                    }
+                    if matches.is_empty() {
+                        ui.weak("Nothing matches.");
+                    }
+                });
+            });
+        match picked {
+            Some(command) => self.execute_command(command),
+            None if !escape => self.palette = Some(state),
+            None => {}
+        }
     }
 
     fn draw_top_panel(&mut self, ctx: &egui::Context) {
@@ -47,9 +301,24 @@ impl PipelineApp {
                 if ui.button("Run Pipeline").clicked() {
                     self.execute_command(FlowChartCommand::RunPipeline);
                 }
+                if ui.button("Force Re-run").clicked() {
+                    self.execute_command(FlowChartCommand::ForceRunPipeline);
+                }
+                ui.add(
+                    egui::DragValue::new(&mut self.flowchart.max_parallelism)
+                        .clamp_range(1..=64)
+                        .prefix("Parallel jobs: "),
+                );
                 if ui.button("Delete Selected Node").clicked() {
                     self.execute_command(FlowChartCommand::DeleteSelectedNode);
                 }
+                if ui
+                    .button("Collapse Selection")
+                    .on_hover_text("Shift-click nodes to select several")
+                    .clicked()
+                {
+                    self.execute_command(FlowChartCommand::CollapseSelection);
+                }
                 if ui.button("Pan Left").clicked() {
                     self.execute_command(FlowChartCommand::PanLeft);
                 }
@@ -68,79 +337,1013 @@ impl PipelineApp {
                 if ui.button("Zoom Out").clicked() {
                     self.execute_command(FlowChartCommand::ZoomOut);
                 }
+                if ui.button("Fit").on_hover_text("Zoom to fit all nodes").clicked() {
+                    self.execute_command(FlowChartCommand::ZoomToFit);
+                }
+                if ui.button("Fit Selection").clicked() {
+                    self.execute_command(FlowChartCommand::ZoomToSelection);
+                }
+                if ui.button("Reset View").clicked() {
+                    self.execute_command(FlowChartCommand::ResetView);
+                }
+                ui.checkbox(&mut self.show_minimap, "Minimap");
+                ui.checkbox(&mut self.canvas.show_grid, "Grid");
+                ui.checkbox(&mut self.canvas.snap_to_grid, "Snap")
+                    .on_hover_text("Snap dragged nodes to the grid. Pan with the middle mouse button or Space+drag.");
+                let palette_keys = self.keymap.keys_for(&FlowChartCommand::OpenCommandPalette).join(", ");
+                if ui.button("Commands").on_hover_text(palette_keys).clicked() {
+                    self.execute_command(FlowChartCommand::OpenCommandPalette);
+                }
+                if ui.button("Shortcuts").clicked() {
+                    self.execute_command(FlowChartCommand::ShowShortcuts);
+                }
+                if !self.keymap.pending.is_empty() {
+                    ui.weak(format!("{} …", keymap::sequence_label(&self.keymap.pending)));
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Flow file:");
+                ui.text_edit_singleline(&mut self.flow_path);
+                if ui.button("Save Flow").clicked() {
+                    self.execute_command(FlowChartCommand::SaveFlow);
+                }
+                if ui.button("Open Flow").clicked() {
+                    self.execute_command(FlowChartCommand::OpenFlow);
+                }
             });
+            self.draw_tab_bar(ui);
         });
     }
 
-    fn handle_keyboard_shortcuts(&mut self, ctx: &egui::Context) {
-        if ctx.input(|i| i.key_pressed(egui::Key::N)) {
-            self.execute_command(FlowChartCommand::AddNode);
-        }
-        if ctx.input(|i| i.key_pressed(egui::Key::R)) {
-            self.execute_command(FlowChartCommand::RunPipeline);
-        }
-        if ctx.input(|i| i.key_pressed(egui::Key::Delete)) {
-            self.execute_command(FlowChartCommand::DeleteSelectedNode);
-        }
-        if ctx.input(|i| i.key_pressed(egui::Key::ArrowLeft)) {
-            self.execute_command(FlowChartCommand::PanLeft);
-        }
-        if ctx.input(|i| i.key_pressed(egui::Key::ArrowRight)) {
-            self.execute_command(FlowChartCommand::PanRight);
-        }
-        if ctx.input(|i| i.key_pressed(egui::Key::ArrowUp)) {
-            self.execute_command(FlowChartCommand::PanUp);
-        }
-        if ctx.input(|i| i.key_pressed(egui::Key::ArrowDown)) {
-            self.execute_command(FlowChartCommand::PanDown);
+    /// One tab for the main flow plus one per opened subflow.
+    fn draw_tab_bar(&mut self, ui: &mut egui::Ui) {
+        self.close_stale_tabs();
+        let mut close = None;
+        ui.horizontal(|ui| {
+            if ui.selectable_label(self.active_tab.is_none(), "Main flow").clicked() {
+                self.active_tab = None;
+            }
+            for (tab, path) in self.open_subflows.iter().enumerate() {
+                let name = match self.flowchart.node_at_path(path).map(|n| &n.node_type) {
+                    Some(NodeType::Subflow(subflow)) => subflow.name.clone(),
+                    _ => continue,
+                };
+                if ui.selectable_label(self.active_tab == Some(tab), name).clicked() {
+                    self.active_tab = Some(tab);
+                }
+                if ui.small_button("✖").clicked() {
+                    close = Some(tab);
+                }
+            }
+        });
+        if let Some(tab) = close {
+            self.open_subflows.remove(tab);
+            self.active_tab = match self.active_tab {
+                Some(active) if active == tab => None,
+                Some(active) if active > tab => Some(active - 1),
+                other => other,
+            };
         }
-        if ctx.input(|i| i.key_pressed(egui::Key::Equals)) {
-            self.execute_command(FlowChartCommand::ZoomIn);
+    }
+
+    /// Runs the commands bound to the keys pressed this frame. Keys that
+    /// complete or continue a shortcut are taken out of the input, so
+    /// widgets do not see them; plain keys go to a focused text field.
+    fn handle_keyboard_shortcuts(&mut self, ctx: &egui::Context) {
+        let typing = ctx.wants_keyboard_input() || self.palette.is_some();
+        let keymap = &mut self.keymap;
+        let mut fired = Vec::new();
+        // A plain key taken for a chord, e.g. the U of `Ctrl+K U`, also
+        // arrives as the text event that follows it; drop that too so the
+        // letter is not typed into the focused field.
+        let mut drop_text = false;
+        ctx.input_mut(|i| {
+            i.events.retain(|event| {
+                let (key, repeat, modifiers) = match event {
+                    egui::Event::Key { key, pressed: true, repeat, modifiers, .. } => (key, repeat, modifiers),
+                    egui::Event::Text(_) if drop_text => {
+                        drop_text = false;
+                        return false;
+                    }
+                    _ => return true,
+                };
+                let combo = KeyCombo::new(*key, *modifiers);
+                drop_text = false;
+                match keymap.press(combo, typing, *repeat) {
+                    Press::Fired(command) => fired.push(command),
+                    Press::Pending => {}
+                    Press::Unbound => return true,
+                }
+                drop_text = combo.is_typing();
+                false
+            });
+        });
+        for command in fired {
+            self.execute_command(command);
         }
-        if ctx.input(|i| i.key_pressed(egui::Key::Minus)) {
-            self.execute_command(FlowChartCommand::ZoomOut);
+    }
+
+    /// Every command with the keys bound to it.
+    fn draw_shortcuts_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_shortcuts;
+        let mut reload = false;
+        egui::Window::new("Keyboard shortcuts")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                egui::ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+                    egui::Grid::new("shortcuts_grid").num_columns(2).striped(true).show(ui, |ui| {
+                        for command in FlowChartCommand::actions() {
+                            let keys = self.keymap.keys_for(&command);
+                            ui.label(command.label());
+                            if keys.is_empty() {
+                                ui.weak("unbound");
+                            } else {
+                                ui.monospace(keys.join(", "));
+                            }
+                            ui.end_row();
+                        }
+                    });
+                });
+                ui.separator();
+                ui.label("Text fields get plain keys first; shortcuts with Ctrl or Alt work everywhere.");
+                ui.horizontal(|ui| {
+                    ui.label("Keymap file:");
+                    ui.text_edit_singleline(&mut self.keymap_path);
+                    if ui
+                        .button("Reload")
+                        .on_hover_text("Maps command IDs to key lists, e.g. {\"run_pipeline\": [\"Ctrl+Enter\"]}")
+                        .clicked()
+                    {
+                        reload = true;
+                    }
+                });
+            });
+        self.show_shortcuts = open;
+        if reload {
+            self.reload_keymap();
         }
     }
 
     fn draw_side_panel(&mut self, ctx: &egui::Context) {
-        if let Some(selected_id) = self.flowchart.selected_node {
+        let library: Vec<String> = self.library.iter().map(|subflow| subflow.name.clone()).collect();
+        let mut map_body = None;
+        let chart = self.active_chart_mut();
+        if let Some(selected_id) = chart.selected_node {
+            let mut command = None;
+            let mut move_input_up = None;
+            let mut remove_port = None;
+            let mut error = None;
+            let inputs = chart.upstream_of(selected_id);
             egui::SidePanel::right("side_panel").show(ctx, |ui| {
-                if let Some(node) = self
-                    .flowchart
-                    .nodes
-                    .iter_mut()
-                    .find(|n| n.id == selected_id)
-                {
+                if let Some(node) = chart.nodes.iter_mut().find(|n| n.id == selected_id) {
                     ui.heading(format!("Node {}", node.id));
-                    ui.label("Command:");
-                    ui.text_edit_singleline(&mut node.command);
+                    if !matches!(node.node_type, NodeType::Subflow(_)) {
+                        egui::ComboBox::from_label("Type")
+                            .selected_text(node.node_type.label())
+                            .show_ui(ui, |ui| {
+                                let choices = [
+                                    NodeType::Command,
+                                    NodeType::Map(MapSettings::default()),
+                                    NodeType::Source(Source::choices()[0].clone()),
+                                    NodeType::Sink(Sink::choices()[0].clone()),
+                                    NodeType::Transform(Transform::choices()[0].clone()),
+                                    NodeType::Json(JsonSettings::default()),
+                                    NodeType::Table(TableSettings::default()),
+                                    NodeType::Script(ScriptSettings::default()),
+                                ];
+                                let plugins = registry().kinds().map(|kind| NodeType::Plugin(PluginNode::new(kind)));
+                                for choice in choices.into_iter().chain(plugins) {
+                                    let selected = match (&choice, &node.node_type) {
+                                        (NodeType::Plugin(a), NodeType::Plugin(b)) => a.kind == b.kind,
+                                        (a, b) => std::mem::discriminant(a) == std::mem::discriminant(b),
+                                    };
+                                    let label = choice.label();
+                                    if ui.selectable_label(selected, label).clicked() && !selected {
+                                        node.node_type = choice;
+                                    }
+                                }
+                            });
+                    }
+                    match &mut node.node_type {
+                        NodeType::Command => {
+                            ui.label("Command:");
+                        }
+                        NodeType::Map(settings) => {
+                            egui::ComboBox::from_label("Split input into")
+                                .selected_text(settings.split.label())
+                                .show_ui(ui, |ui| {
+                                    for split in RecordSplit::ALL {
+                                        ui.selectable_value(&mut settings.split, split, split.label());
+                                    }
+                                });
+                            ui.add(
+                                egui::DragValue::new(&mut settings.parallelism)
+                                    .clamp_range(1..=64)
+                                    .prefix("Records in parallel: "),
+                            );
+                            let body_name = settings.body.as_ref().map_or("Command", |body| body.name.as_str());
+                            egui::ComboBox::from_label("Per record run")
+                                .selected_text(body_name)
+                                .show_ui(ui, |ui| {
+                                    if ui.selectable_label(settings.body.is_none(), "Command").clicked() {
+                                        settings.body = None;
+                                    }
+                                    for (index, name) in library.iter().enumerate() {
+                                        if ui.selectable_label(false, name).clicked() {
+                                            map_body = Some(index);
+                                        }
+                                    }
+                                });
+                            match &settings.body {
+                                Some(body) => {
+                                    ui.label(format!(
+                                        "Each record goes to the input ports of '{}' and into $RECORD; \
+                                         its first output port gives the record's output.",
+                                        body.name
+                                    ));
+                                }
+                                None => {
+                                    ui.label("Command per record (record on stdin and in $RECORD):");
+                                }
+                            }
+                        }
+                        NodeType::Subflow(subflow) => {
+                            ui.horizontal(|ui| {
+                                ui.label("Name:");
+                                ui.text_edit_singleline(&mut subflow.name);
+                            });
+                            ui.horizontal(|ui| {
+                                if ui.button("Open").clicked() {
+                                    command = Some(FlowChartCommand::OpenSelectedSubflow);
+                                }
+                                if ui.button("Save to library").clicked() {
+                                    command = Some(FlowChartCommand::SaveSelectedSubflowToLibrary);
+                                }
+                            });
+                            let inner = &subflow.chart;
+                            let removed = edit_ports(ui, "Input ports", "in", &mut subflow.inputs, inner);
+                            if let Some(index) = removed {
+                                remove_port = Some((PortSide::Input, index));
+                            }
+                            let removed = edit_ports(ui, "Output ports", "out", &mut subflow.outputs, inner);
+                            if let Some(index) = removed {
+                                remove_port = Some((PortSide::Output, index));
+                            }
+                            let incoming = chart
+                                .connections
+                                .iter_mut()
+                                .filter(|c| c.to == selected_id && c.kind == ConnectionKind::Data);
+                            for (index, conn) in incoming.enumerate() {
+                                ui.horizontal(|ui| {
+                                    ui.label(format!("From node {} into", conn.from));
+                                    let ports = &subflow.inputs;
+                                    port_combo(ui, ("in_edge", index), &mut conn.to_port, ports);
+                                });
+                            }
+                        }
+                        NodeType::Source(source) => {
+                            egui::ComboBox::from_label("Read from")
+                                .selected_text(source.label())
+                                .show_ui(ui, |ui| {
+                                    for choice in Source::choices() {
+                                        let selected = std::mem::discriminant(&choice)
+                                            == std::mem::discriminant(source);
+                                        let label = choice.label();
+                                        if ui.selectable_label(selected, label).clicked() && !selected {
+                                            *source = choice;
+                                        }
+                                    }
+                                });
+                            match source {
+                                Source::File { path } => {
+                                    ui.label("Path:");
+                                    ui.text_edit_singleline(path);
+                                }
+                                Source::Glob { pattern } => {
+                                    ui.label("Pattern (e.g. logs/*.log):");
+                                    ui.text_edit_singleline(pattern);
+                                }
+                                Source::Stdin => {
+                                    ui.label("Reads what is piped into echoflow.");
+                                }
+                                Source::Text { text } => {
+                                    ui.horizontal(|ui| {
+                                        ui.label(preview::format_size(text.len()));
+                                        if ui.button("Load from clipboard").clicked() {
+                                            match io_nodes::clipboard_text() {
+                                                Ok(pasted) => *text = pasted,
+                                                Err(e) => error = Some(e),
+                                            }
+                                        }
+                                        if !text.is_empty() && !text.ends_with('\n') {
+                                            ui.weak("no trailing newline");
+                                        }
+                                    });
+                                    // Lock focus so Tab inserts a tab instead of leaving the editor.
+                                    ui.add(
+                                        egui::TextEdit::multiline(text)
+                                            .code_editor()
+                                            .lock_focus(true)
+                                            .desired_width(f32::INFINITY)
+                                            .desired_rows(10),
+                                    );
+                                }
+                            }
+                            // Only the settings part is cached: files appear and vanish
+                            // without the settings changing.
+                            let check = cached_check(ui, selected_id, source, || source.check_settings());
+                            show_check(ui, check.and_then(|()| source.check_files()));
+                        }
+                        NodeType::Sink(sink) => {
+                            egui::ComboBox::from_label("Write to")
+                                .selected_text(sink.label())
+                                .show_ui(ui, |ui| {
+                                    for choice in Sink::choices() {
+                                        let selected = std::mem::discriminant(&choice)
+                                            == std::mem::discriminant(sink);
+                                        let label = choice.label();
+                                        if ui.selectable_label(selected, label).clicked() && !selected {
+                                            *sink = choice;
+                                        }
+                                    }
+                                });
+                            match sink {
+                                Sink::Write { path } | Sink::Append { path } | Sink::Replace { path } => {
+                                    ui.label("Path:");
+                                    ui.text_edit_singleline(path);
+                                }
+                                Sink::Stdout => {
+                                    ui.label("Writes to echoflow's stdout (see --run).");
+                                }
+                            }
+                            let check = cached_check(ui, selected_id, sink, || sink.check_settings());
+                            show_check(ui, check.and_then(|()| sink.check_files()));
+                        }
+                        NodeType::Transform(transform) => {
+                            egui::ComboBox::from_label("Transform")
+                                .selected_text(transform.label())
+                                .show_ui(ui, |ui| {
+                                    for choice in Transform::choices() {
+                                        let selected = std::mem::discriminant(&choice)
+                                            == std::mem::discriminant(transform);
+                                        let label = choice.label();
+                                        if ui.selectable_label(selected, label).clicked() && !selected {
+                                            *transform = choice;
+                                        }
+                                    }
+                                });
+                            edit_transform(ui, transform);
+                            show_check(ui, cached_check(ui, selected_id, transform, || transform.check()));
+                        }
+                        NodeType::Json(settings) => {
+                            edit_json(ui, settings);
+                            show_check(ui, cached_check(ui, selected_id, settings, || settings.check()));
+                        }
+                        NodeType::Table(settings) => {
+                            edit_table(ui, settings);
+                            show_check(ui, cached_check(ui, selected_id, settings, || settings.check()));
+                        }
+                        NodeType::Script(settings) => {
+                            let check = cached_check(ui, selected_id, settings, || settings.check());
+                            let error_line = match &check {
+                                Err(problem) => script::error_line(problem),
+                                Ok(()) => node.error.as_deref().and_then(script::error_line),
+                            };
+                            edit_script(ui, settings, error_line);
+                            show_check(ui, check);
+                        }
+                        NodeType::Plugin(plugin) => match plugin.kind() {
+                            Ok(kind) => {
+                                kind.editor(ui, &mut plugin.config);
+                                show_check(ui, cached_check(ui, selected_id, plugin, || kind.check(&plugin.config)));
+                            }
+                            Err(problem) => {
+                                ui.colored_label(egui::Color32::RED, problem);
+                            }
+                        },
+                    }
+                    if let NodeType::Command | NodeType::Map(MapSettings { body: None, .. }) = node.node_type {
+                        ui.text_edit_singleline(&mut node.command);
+                        ui.collapsing("Environment", |ui| edit_process(ui, &mut node.process));
+                    }
+                    ui.separator();
+                    ui.horizontal_wrapped(|ui| {
+                        if ui.button("Run up to here").clicked() {
+                            command = Some(FlowChartCommand::RunUpToSelected);
+                        }
+                        if ui.button("Run from here").clicked() {
+                            command = Some(FlowChartCommand::RunFromSelected);
+                        }
+                        if ui.button("Force re-run").clicked() {
+                            command = Some(FlowChartCommand::ForceRerunSelectedNode);
+                        }
+                        if ui.button("Save as preset").clicked() {
+                            command = Some(FlowChartCommand::SaveSelectedNodeAsPreset);
+                        }
+                    });
+                    ui.collapsing("Test input", |ui| {
+                        ui.add(
+                            egui::TextEdit::multiline(&mut node.test_input)
+                                .code_editor()
+                                .desired_rows(4),
+                        );
+                        if ui.button("Run only this node").clicked() {
+                            command = Some(FlowChartCommand::RunSelectedWithTestInput);
+                        }
+                    });
+                    ui.separator();
+                    ui.label("Inputs (merged in this order):");
+                    for (index, from) in inputs.iter().enumerate() {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("{}. from node {}", index + 1, from));
+                            if index > 0 && ui.small_button("⬆").clicked() {
+                                move_input_up = Some(index);
+                            }
+                        });
+                    }
+                    egui::ComboBox::from_label("Merge mode")
+                        .selected_text(node.merge_mode.label())
+                        .show_ui(ui, |ui| {
+                            for mode in MergeMode::choices() {
+                                let selected = std::mem::discriminant(&mode)
+                                    == std::mem::discriminant(&node.merge_mode);
+                                let label = mode.label();
+                                if ui.selectable_label(selected, label).clicked() && !selected {
+                                    node.merge_mode = mode;
+                                }
+                            }
+                        });
+                    if let MergeMode::Zip { separator } = &mut node.merge_mode {
+                        ui.horizontal(|ui| {
+                            ui.label("Separator:");
+                            ui.text_edit_singleline(separator);
+                        });
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label("Success exit codes:");
+                        edit_exit_codes(ui, &mut node.success_codes, false);
+                    });
+                    ui.collapsing("Retry", |ui| {
+                        let retry = &mut node.retry;
+                        ui.add(
+                            egui::DragValue::new(&mut retry.max_attempts)
+                                .clamp_range(1..=100)
+                                .prefix("Max attempts: "),
+                        );
+                        ui.horizontal(|ui| {
+                            ui.radio_value(&mut retry.backoff, Backoff::Fixed, "Fixed");
+                            ui.radio_value(&mut retry.backoff, Backoff::Exponential, "Exponential");
+                        });
+                        let mut delay_ms = retry.delay.as_millis() as u64;
+                        if ui
+                            .add(
+                                egui::DragValue::new(&mut delay_ms)
+                                    .clamp_range(0..=600_000)
+                                    .prefix("Delay: ")
+                                    .suffix(" ms"),
+                            )
+                            .changed()
+                        {
+                            retry.delay = std::time::Duration::from_millis(delay_ms);
+                        }
+                        ui.horizontal(|ui| {
+                            ui.label("Retry on exit codes (empty = any):");
+                            edit_exit_codes(ui, &mut retry.retry_on, true);
+                        });
+                    });
+                    let outgoing: Vec<_> = chart
+                        .connections
+                        .iter_mut()
+                        .filter(|c| c.from == selected_id)
+                        .collect();
+                    if !outgoing.is_empty() {
+                        ui.label("Outputs:");
+                    }
+                    for (index, conn) in outgoing.into_iter().enumerate() {
+                        egui::ComboBox::from_id_source(("edge_kind", index))
+                            .selected_text(conn.kind.label())
+                            .show_ui(ui, |ui| {
+                                for kind in ConnectionKind::ALL {
+                                    ui.selectable_value(&mut conn.kind, kind, kind.label());
+                                }
+                            });
+                        if let NodeType::Subflow(subflow) = &node.node_type {
+                            ui.horizontal(|ui| {
+                                ui.label("from port");
+                                port_combo(ui, ("out_edge", index), &mut conn.from_port, &subflow.outputs);
+                            });
+                        }
+                        ui.label(format!("to node {}", conn.to));
+                    }
                     ui.separator();
-                    ui.label("Intermediate Output:");
-                    ui.code(&node.output);
+                    if let Some(code) = node.exit_code() {
+                        ui.label(format!("Exit code: {}", code));
+                    }
+                    if node.attempts.len() > 1 {
+                        ui.collapsing(format!("Run record ({} attempts)", node.attempts.len()), |ui| {
+                            for (index, attempt) in node.attempts.iter().enumerate() {
+                                let exit = attempt
+                                    .exit_code
+                                    .map_or("no exit code".to_string(), |code| format!("exit {}", code));
+                                ui.collapsing(
+                                    format!(
+                                        "Attempt {}: {}, {:.2} s",
+                                        index + 1,
+                                        exit,
+                                        attempt.duration.as_secs_f32()
+                                    ),
+                                    |ui| {
+                                        ui.label("stdout:");
+                                        preview::show_bytes(ui, &attempt.stdout);
+                                        ui.label("stderr:");
+                                        preview::show_bytes(ui, &attempt.stderr);
+                                    },
+                                );
+                            }
+                        });
+                    }
+                    if let Some(error) = &node.error {
+                        ui.colored_label(egui::Color32::RED, error);
+                    }
+                    let view_id = egui::Id::new(("output_view", selected_id));
+                    let mut view = ui.data(|d| d.get_temp(view_id)).unwrap_or(match node.node_type {
+                        NodeType::Json(_) => OutputView::Json,
+                        NodeType::Table(_) => OutputView::Table,
+                        _ => OutputView::Text,
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Intermediate Output:");
+                        for choice in OutputView::ALL {
+                            ui.selectable_value(&mut view, choice, choice.label());
+                        }
+                    });
+                    ui.data_mut(|d| d.insert_temp(view_id, view));
+                    match (view, &node.node_type) {
+                        (OutputView::Text, _) => preview::show_bytes(ui, &node.output),
+                        (OutputView::Json, _) => preview::show_json(ui, view_id, &node.output),
+                        (OutputView::Table, NodeType::Table(settings)) => {
+                            preview::show_table(ui, view_id, &node.output, settings.format, settings.header)
+                        }
+                        (OutputView::Table, _) => {
+                            let format = TableFormat::sniff(&node.output);
+                            preview::show_table(ui, view_id, &node.output, format, true)
+                        }
+                    }
                 }
             });
+            if let Some(index) = move_input_up {
+                chart.move_input_up(selected_id, index);
+            }
+            if let Some((side, index)) = remove_port {
+                chart.remove_subflow_port(selected_id, side, index);
+            }
+            if let Some(index) = map_body {
+                let body = Box::new(self.library[index].clone());
+                if let Some(node) = self.active_chart_mut().nodes.iter_mut().find(|n| n.id == selected_id)
+                    && let NodeType::Map(settings) = &mut node.node_type
+                {
+                    settings.body = Some(body);
+                }
+            }
+            if error.is_some() {
+                self.pipeline_error = error;
+            }
+            if let Some(command) = command {
+                self.execute_command(command);
+            }
         }
     }
 
     fn draw_bottom_panel(&mut self, ctx: &egui::Context) {
         egui::TopBottomPanel::bottom("output_panel").show(ctx, |ui| {
             ui.heading("Pipeline Final Output");
-            ui.label(&self.pipeline_output);
+            if let Some(error) = &self.pipeline_error {
+                ui.colored_label(egui::Color32::RED, error);
+            } else {
+                preview::show_bytes(ui, &self.pipeline_output);
+            }
         });
     }
 
     fn draw_central_panel(&mut self, ctx: &egui::Context) {
         egui::CentralPanel::default().show(ctx, |ui| {
-            self.flowchart.draw(ui);
+            let options = self.canvas;
+            self.active_chart_mut().draw(ui, options);
         });
     }
 
+    /// The minimap in the bottom-right corner, with a grip on its top-left
+    /// corner to resize it.
     fn draw_minimap(&mut self, ctx: &egui::Context) {
+        if !self.show_minimap {
+            return;
+        }
+        let mut size = self.minimap_size;
         egui::Area::new("minimap".into())
             .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-10.0, -10.0))
             .show(ctx, |ui| {
-                ui.set_min_size(egui::vec2(220.0, 170.0));
-                self.flowchart.draw_minimap(ui);
+                let rect = self.active_chart_mut().draw_minimap(ui, size);
+                let grip = egui::Rect::from_min_size(rect.min, egui::vec2(12.0, 12.0));
+                let response = ui
+                    .interact(grip, ui.id().with("minimap_resize"), egui::Sense::drag())
+                    .on_hover_cursor(egui::CursorIcon::ResizeNorthWest);
+                if response.dragged() {
+                    size = (size - response.drag_delta()).clamp(egui::vec2(120.0, 90.0), egui::vec2(600.0, 450.0));
+                }
+                let stroke = egui::Stroke::new(1.0, egui::Color32::LIGHT_GRAY);
+                for inset in [3.0, 7.0] {
+                    ui.painter().line_segment(
+                        [grip.min + egui::vec2(inset, 2.0), grip.min + egui::vec2(2.0, inset)],
+                        stroke,
+                    );
+                }
             });
+        self.minimap_size = size;
     }
-} 
\ No newline at end of file
+}
+
+/// Edits a list of exit codes as comma-separated text. The list is only
+/// updated while the text parses, so half-typed input is not lost; unless
+/// `allow_empty`, an empty list is not taken either.
+fn edit_exit_codes(ui: &mut egui::Ui, codes: &mut Vec<i32>, allow_empty: bool) {
+    let id = ui.next_auto_id();
+    let mut text = ui
+        .data(|d| d.get_temp::<String>(id))
+        .unwrap_or_else(|| {
+            codes
+                .iter()
+                .map(|code| code.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        });
+    let response = ui.text_edit_singleline(&mut text);
+    if response.changed() {
+        let parsed: Result<Vec<i32>, _> = text
+            .split(',')
+            .map(str::trim)
+            .filter(|code| !code.is_empty())
+            .map(str::parse)
+            .collect();
+        if let Ok(parsed) = parsed
+            && (allow_empty || !parsed.is_empty())
+        {
+            *codes = parsed;
+        }
+    }
+    if !allow_empty && text.trim().is_empty() {
+        ui.colored_label(egui::Color32::YELLOW, "Enter at least one code");
+    }
+    if response.has_focus() {
+        ui.data_mut(|d| d.insert_temp(id, text));
+    } else {
+        ui.data_mut(|d| d.remove::<String>(id));
+    }
+}
+
+/// Edits the environment variables and working directory of a node's commands.
+fn edit_process(ui: &mut egui::Ui, process: &mut ProcessSettings) {
+    ui.horizontal(|ui| {
+        ui.label("Working directory:");
+        ui.add(egui::TextEdit::singleline(&mut process.cwd).hint_text("echoflow's"));
+    });
+    let mut remove = None;
+    for (index, (key, value)) in process.env.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(key).hint_text("NAME").desired_width(80.0));
+            ui.label("=");
+            ui.add(egui::TextEdit::singleline(value).desired_width(120.0));
+            if ui.small_button("✖").clicked() {
+                remove = Some(index);
+            }
+        });
+    }
+    if let Some(index) = remove {
+        process.env.remove(index);
+    }
+    if ui.button("Add variable").clicked() {
+        process.env.push((String::new(), String::new()));
+    }
+}
+
+/// A toolbox button: a user preset or a registry entry, by index.
+#[derive(Clone, Copy)]
+enum ToolboxPick {
+    Preset(usize),
+    Entry(usize),
+}
+
+/// Form for the settings of a built-in transform, in place of command-line flags.
+fn edit_transform(ui: &mut egui::Ui, transform: &mut Transform) {
+    match transform {
+        Transform::Filter {
+            pattern,
+            invert,
+            ignore_case,
+        } => {
+            ui.label("Regex:");
+            ui.text_edit_singleline(pattern);
+            ui.checkbox(invert, "Keep lines that do not match");
+            ui.checkbox(ignore_case, "Ignore case");
+        }
+        Transform::Replace {
+            pattern,
+            replacement,
+            all,
+            ignore_case,
+        } => {
+            ui.label("Regex:");
+            ui.text_edit_singleline(pattern);
+            ui.label("Replacement (${1} for the first group):");
+            ui.text_edit_singleline(replacement);
+            ui.checkbox(all, "Every match in a line");
+            ui.checkbox(ignore_case, "Ignore case");
+        }
+        Transform::Sort {
+            numeric,
+            reverse,
+            unique,
+        } => {
+            ui.checkbox(numeric, "Compare as numbers");
+            ui.checkbox(reverse, "Reverse order");
+            ui.checkbox(unique, "Drop repeated lines");
+        }
+        Transform::Uniq { count } => {
+            ui.checkbox(count, "Prefix each line with its count");
+        }
+        Transform::Head { lines } | Transform::Tail { lines } => {
+            ui.add(egui::DragValue::new(lines).prefix("Lines: "));
+        }
+        Transform::CountLines => {
+            ui.label("Outputs the number of input lines.");
+        }
+        Transform::Cut { delimiter, fields } => {
+            ui.horizontal(|ui| {
+                ui.label("Delimiter:");
+                ui.text_edit_singleline(delimiter)
+                    .on_hover_text("Empty splits on runs of whitespace");
+            });
+            ui.horizontal(|ui| {
+                ui.label("Fields:");
+                ui.text_edit_singleline(fields)
+                    .on_hover_text("e.g. 1,3-4,6-");
+            });
+        }
+        Transform::Case { mode } => {
+            for choice in CaseMode::ALL {
+                ui.radio_value(mode, choice, choice.label());
+            }
+        }
+    }
+}
+
+/// Form for the settings of a JSON node.
+fn edit_json(ui: &mut egui::Ui, settings: &mut JsonSettings) {
+    egui::ComboBox::from_label("Operation")
+        .selected_text(settings.op.label())
+        .show_ui(ui, |ui| {
+            for choice in JsonOp::choices() {
+                let selected = std::mem::discriminant(&choice) == std::mem::discriminant(&settings.op);
+                let label = choice.label();
+                if ui.selectable_label(selected, label).clicked() && !selected {
+                    settings.op = choice;
+                }
+            }
+        });
+    match &mut settings.op {
+        JsonOp::Select { path } => {
+            ui.label("Path:");
+            ui.text_edit_singleline(path)
+                .on_hover_text("e.g. .items[].name, .a[0], .[\"odd key\"]");
+        }
+        JsonOp::Filter {
+            path,
+            compare,
+            value,
+        } => {
+            ui.label("Where:");
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(path);
+                egui::ComboBox::from_id_source("json_compare")
+                    .selected_text(compare.label())
+                    .show_ui(ui, |ui| {
+                        for choice in Compare::ALL {
+                            ui.selectable_value(compare, choice, choice.label());
+                        }
+                    });
+            });
+            if *compare != Compare::Exists {
+                ui.text_edit_singleline(value)
+                    .on_hover_text("Read as JSON when it parses (42, true, \"x\"), as text otherwise");
+            }
+            ui.weak("Arrays are filtered item by item.");
+        }
+        JsonOp::Project { fields } => {
+            ui.label("Fields:");
+            ui.text_edit_singleline(fields)
+                .on_hover_text("Comma-separated paths, e.g. name, owner.login");
+        }
+        JsonOp::Flatten { separator } => {
+            ui.horizontal(|ui| {
+                ui.label("Key separator:");
+                ui.text_edit_singleline(separator);
+            });
+        }
+        JsonOp::Format => {
+            ui.label("Writes the input values back out unchanged.");
+        }
+    }
+    egui::ComboBox::from_label("Input")
+        .selected_text(settings.input.label())
+        .show_ui(ui, |ui| {
+            for choice in JsonInput::ALL {
+                ui.selectable_value(&mut settings.input, choice, choice.label());
+            }
+        });
+    egui::ComboBox::from_label("Output")
+        .selected_text(settings.output.label())
+        .show_ui(ui, |ui| {
+            for choice in JsonOutput::ALL {
+                ui.selectable_value(&mut settings.output, choice, choice.label());
+            }
+        });
+}
+
+/// Form for the settings of a table node.
+fn edit_table(ui: &mut egui::Ui, settings: &mut TableSettings) {
+    egui::ComboBox::from_label("Operation")
+        .selected_text(settings.op.label())
+        .show_ui(ui, |ui| {
+            for choice in TableOp::choices() {
+                let selected = std::mem::discriminant(&choice) == std::mem::discriminant(&settings.op);
+                let label = choice.label();
+                if ui.selectable_label(selected, label).clicked() && !selected {
+                    settings.op = choice;
+                }
+            }
+        });
+    let column_hint = "A header name or a 1-based column number";
+    match &mut settings.op {
+        TableOp::Select { columns } | TableOp::GroupCount { columns } => {
+            ui.label("Columns:");
+            ui.text_edit_singleline(columns)
+                .on_hover_text(format!("Comma-separated; each is {}", column_hint.to_lowercase()));
+        }
+        TableOp::Filter {
+            column,
+            test,
+            value,
+        } => {
+            ui.label("Where column:");
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(column).on_hover_text(column_hint);
+                egui::ComboBox::from_id_source("table_test")
+                    .selected_text(test.label())
+                    .show_ui(ui, |ui| {
+                        for choice in RowTest::ALL {
+                            ui.selectable_value(test, choice, choice.label());
+                        }
+                    });
+            });
+            ui.text_edit_singleline(value);
+        }
+        TableOp::Sort {
+            column,
+            numeric,
+            reverse,
+        } => {
+            ui.label("Column:");
+            ui.text_edit_singleline(column).on_hover_text(column_hint);
+            ui.checkbox(numeric, "Compare as numbers");
+            ui.checkbox(reverse, "Descending");
+        }
+    }
+    egui::ComboBox::from_label("Layout")
+        .selected_text(settings.format.label())
+        .show_ui(ui, |ui| {
+            for choice in TableFormat::ALL {
+                ui.selectable_value(&mut settings.format, choice, choice.label());
+            }
+        });
+    ui.checkbox(&mut settings.header, "First row is a header");
+}
+
+/// Form for a script node: a code editor with line numbers, the line of the
+/// last error marked in red.
+fn edit_script(ui: &mut egui::Ui, settings: &mut ScriptSettings, error_line: Option<usize>) {
+    ui.horizontal(|ui| {
+        for mode in ScriptMode::ALL {
+            ui.radio_value(&mut settings.mode, mode, mode.label());
+        }
+    });
+    ui.weak(match settings.mode {
+        ScriptMode::PerLine => "`line` and `index` are set; return a string, an array of lines, or () to drop the line.",
+        ScriptMode::WholeInput => "`input` holds everything; the result is the output.",
+    });
+    ui.checkbox(&mut settings.allow_files, "Allow read_file / write_file");
+    let lines = settings.script.lines().count().max(1);
+    ui.horizontal_top(|ui| {
+        ui.vertical(|ui| {
+            // Line up with the text edit's inner margin.
+            ui.spacing_mut().item_spacing.y = 0.0;
+            ui.add_space(2.0);
+            for line in 1..=lines {
+                let number = egui::RichText::new(format!("{:>3}", line)).monospace();
+                if Some(line) == error_line {
+                    ui.label(number.color(egui::Color32::RED).strong());
+                } else {
+                    ui.label(number.weak());
+                }
+            }
+        });
+        ui.add(
+            egui::TextEdit::multiline(&mut settings.script)
+                .code_editor()
+                .lock_focus(true)
+                .desired_rows(lines.max(6))
+                .desired_width(f32::INFINITY),
+        );
+    });
+}
+
+/// The result of a node's pre-run check, re-run only when the settings it
+/// checks change rather than on every frame.
+fn cached_check(
+    ui: &egui::Ui,
+    node_id: usize,
+    settings: &impl Hash,
+    check: impl FnOnce() -> Result<(), String>,
+) -> Result<(), String> {
+    let mut hasher = DefaultHasher::new();
+    settings.hash(&mut hasher);
+    let key = hasher.finish();
+    let id = egui::Id::new(("node_check", node_id));
+    if let Some((cached_key, result)) = ui.data(|d| d.get_temp::<(u64, Result<(), String>)>(id))
+        && cached_key == key
+    {
+        return result;
+    }
+    let result = check();
+    ui.data_mut(|d| d.insert_temp(id, (key, result.clone())));
+    result
+}
+
+/// Shows why a node would fail its pre-run check, if it would.
+fn show_check(ui: &mut egui::Ui, check: Result<(), String>) {
+    if let Err(problem) = check {
+        ui.colored_label(egui::Color32::RED, problem);
+    }
+}
+
+/// Edits a subflow's ports: their names and the inner node behind each.
+/// Returns the index of a port the user asked to remove.
+fn edit_ports(
+    ui: &mut egui::Ui,
+    heading: &str,
+    prefix: &str,
+    ports: &mut Vec<Port>,
+    chart: &FlowChart,
+) -> Option<usize> {
+    let mut remove = None;
+    ui.label(heading);
+    for (index, port) in ports.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut port.name).desired_width(60.0));
+            egui::ComboBox::from_id_source((heading, index))
+                .selected_text(format!("node {}", port.node))
+                .show_ui(ui, |ui| {
+                    for node in &chart.nodes {
+                        let label = format!("{}: {}", node.id, node.label());
+                        if ui.selectable_value(&mut port.node, node.id, label).clicked() {
+                            port.node_port = 0;
+                        }
+                    }
+                });
+            if ui.small_button("✖").clicked() {
+                remove = Some(index);
+            }
+        });
+    }
+    if let Some(first) = chart.nodes.first()
+        && ui.small_button(format!("Add {}", heading.to_lowercase().trim_end_matches('s'))).clicked()
+    {
+        ports.push(Port {
+            name: format!("{}{}", prefix, ports.len() + 1),
+            node: first.id,
+            node_port: 0,
+        });
+    }
+    remove
+}
+
+/// Picks one of a subflow's ports for the end of an edge.
+fn port_combo(ui: &mut egui::Ui, id_source: impl std::hash::Hash, port: &mut usize, ports: &[Port]) {
+    let name = ports.get(*port).map_or("(none)", |p| p.name.as_str());
+    egui::ComboBox::from_id_source(id_source)
+        .selected_text(name)
+        .show_ui(ui, |ui| {
+            for (index, candidate) in ports.iter().enumerate() {
+                ui.selectable_value(port, index, &candidate.name);
+            }
+        });
+}
@@ -0,0 +1,357 @@
+use echoflow_node_api::{Config, ConfigField, FieldKind, NodeKind, Output, Ports};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use wasmtime::{Engine, Linker, Module, Store, StoreLimits, StoreLimitsBuilder, Trap};
+use wasmtime_wasi::pipe::{MemoryInputPipe, MemoryOutputPipe};
+use wasmtime_wasi::preview1::{self, WasiP1Ctx};
+use wasmtime_wasi::{DirPerms, FilePerms, I32Exit, WasiCtxBuilder};
+
+/// Where WebAssembly plugins are loaded from: `$ECHOFLOW_PLUGINS`, or
+/// `plugins` in echoflow's config directory.
+pub fn plugins_dir() -> PathBuf {
+    match std::env::var_os("ECHOFLOW_PLUGINS") {
+        Some(dir) => PathBuf::from(dir),
+        None => dirs::config_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("echoflow")
+            .join("plugins"),
+    }
+}
+
+/// Loads every `<name>.wasm` in `dir` that has a `<name>.json` manifest
+/// next to it. Plugins that fail to load are reported and skipped; a
+/// missing directory holds no plugins.
+pub fn load_plugins(dir: &Path) -> (Vec<Box<dyn NodeKind>>, Vec<String>) {
+    let mut kinds: Vec<Box<dyn NodeKind>> = Vec::new();
+    let mut errors = Vec::new();
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return (kinds, errors);
+    };
+    let mut modules: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "wasm"))
+        .collect();
+    modules.sort();
+    for path in modules {
+        match WasmKind::load(&path) {
+            Ok(kind) => kinds.push(Box::new(kind)),
+            Err(e) => errors.push(format!("{}: {}", path.display(), e)),
+        }
+    }
+    (kinds, errors)
+}
+
+/// The `<name>.json` file describing a plugin module.
+#[derive(Deserialize)]
+struct Manifest {
+    id: String,
+    name: String,
+    #[serde(default = "default_category")]
+    category: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    ports: PortsSpec,
+    /// Settings shown in the side panel, passed to the module as
+    /// environment variables named after their keys.
+    #[serde(default)]
+    settings: Vec<SettingSpec>,
+    /// Instructions (roughly) a run may execute before it is stopped.
+    #[serde(default = "default_fuel")]
+    max_fuel: u64,
+    #[serde(default = "default_memory_mb")]
+    max_memory_mb: usize,
+    /// Most a run may write to stdout, and again to stderr, before the
+    /// writes fail.
+    #[serde(default = "default_output_mb")]
+    max_output_mb: usize,
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum PortsSpec {
+    Source,
+    #[default]
+    Transform,
+    Sink,
+}
+
+#[derive(Deserialize)]
+struct SettingSpec {
+    key: String,
+    #[serde(default)]
+    label: String,
+    #[serde(default)]
+    kind: SettingKind,
+    #[serde(default)]
+    default: String,
+    #[serde(default)]
+    help: String,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "lowercase", tag = "type")]
+enum SettingKind {
+    #[default]
+    Text,
+    Multiline,
+    Number {
+        #[serde(default)]
+        min: f64,
+        #[serde(default = "default_max")]
+        max: f64,
+    },
+    Toggle,
+    Choice { choices: Vec<String> },
+}
+
+fn default_category() -> String {
+    "Plugins".into()
+}
+
+fn default_fuel() -> u64 {
+    10_000_000_000
+}
+
+fn default_memory_mb() -> usize {
+    256
+}
+
+fn default_output_mb() -> usize {
+    32
+}
+
+fn default_max() -> f64 {
+    f64::MAX
+}
+
+/// Config keys every plugin node has, for granting file access.
+const GRANT_DIR: &str = "grant_dir";
+const GRANT_WRITE: &str = "grant_write";
+
+/// A node kind backed by a WASI command module: the node's input is its
+/// stdin and its stdout is the node's output.
+struct WasmKind {
+    id: &'static str,
+    name: &'static str,
+    category: &'static str,
+    description: &'static str,
+    ports: Ports,
+    schema: Vec<ConfigField>,
+    setting_keys: Vec<&'static str>,
+    module: Module,
+    max_fuel: u64,
+    max_memory: usize,
+    max_output: usize,
+}
+
+/// What a running module's store holds.
+struct State {
+    wasi: WasiP1Ctx,
+    limits: StoreLimits,
+}
+
+/// The engine shared by all plugins, with fuel metering on.
+fn engine() -> &'static Engine {
+    static ENGINE: OnceLock<Engine> = OnceLock::new();
+    ENGINE.get_or_init(|| {
+        let mut config = wasmtime::Config::new();
+        config.consume_fuel(true);
+        Engine::new(&config).expect("fuel metering is supported on every target")
+    })
+}
+
+/// Plugins are loaded once per process, so leaking their strings gives the
+/// `'static` lifetimes `NodeKind` asks for.
+fn leak(text: String) -> &'static str {
+    Box::leak(text.into_boxed_str())
+}
+
+impl WasmKind {
+    fn load(path: &Path) -> Result<Self, String> {
+        let manifest_path = path.with_extension("json");
+        let manifest = std::fs::read(&manifest_path)
+            .map_err(|e| format!("Failed to read manifest '{}': {}", manifest_path.display(), e))?;
+        let manifest: Manifest = serde_json::from_slice(&manifest)
+            .map_err(|e| format!("Invalid manifest '{}': {}", manifest_path.display(), e))?;
+        let module = Module::from_file(engine(), path).map_err(|e| format!("Invalid module: {:#}", e))?;
+        if module.get_export("_start").is_none() {
+            return Err("The module is not a WASI command (it has no _start export).".into());
+        }
+
+        let mut schema: Vec<ConfigField> = manifest
+            .settings
+            .into_iter()
+            .map(|setting| {
+                let label = if setting.label.is_empty() { setting.key.clone() } else { setting.label };
+                ConfigField {
+                    key: leak(setting.key),
+                    label: leak(label),
+                    kind: match setting.kind {
+                        SettingKind::Text => FieldKind::Text,
+                        SettingKind::Multiline => FieldKind::MultilineText,
+                        SettingKind::Number { min, max } => FieldKind::Number { min, max },
+                        SettingKind::Toggle => FieldKind::Toggle,
+                        SettingKind::Choice { choices } => {
+                            let choices: Vec<&'static str> = choices.into_iter().map(leak).collect();
+                            FieldKind::Choice(Box::leak(choices.into_boxed_slice()))
+                        }
+                    },
+                    default: leak(setting.default),
+                    help: leak(setting.help),
+                }
+            })
+            .collect();
+        let setting_keys = schema.iter().map(|field| field.key).collect();
+        schema.push(ConfigField {
+            key: GRANT_DIR,
+            label: "Grant directory",
+            kind: FieldKind::Text,
+            default: "",
+            help: "Host directory the module may use, at the same path. Empty: no file access at all.",
+        });
+        schema.push(ConfigField {
+            key: GRANT_WRITE,
+            label: "Allow writing there",
+            kind: FieldKind::Toggle,
+            default: "false",
+            help: "",
+        });
+
+        Ok(Self {
+            id: leak(manifest.id),
+            name: leak(manifest.name),
+            category: leak(manifest.category),
+            description: leak(manifest.description),
+            ports: match manifest.ports {
+                PortsSpec::Source => Ports::Source,
+                PortsSpec::Transform => Ports::Transform,
+                PortsSpec::Sink => Ports::Sink,
+            },
+            schema,
+            setting_keys,
+            module,
+            max_fuel: manifest.max_fuel,
+            max_memory: manifest.max_memory_mb.saturating_mul(1 << 20),
+            max_output: manifest.max_output_mb.saturating_mul(1 << 20),
+        })
+    }
+}
+
+impl NodeKind for WasmKind {
+    fn id(&self) -> &'static str {
+        self.id
+    }
+
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn category(&self) -> &'static str {
+        self.category
+    }
+
+    fn description(&self) -> &'static str {
+        self.description
+    }
+
+    fn ports(&self) -> Ports {
+        self.ports
+    }
+
+    fn config_schema(&self) -> Vec<ConfigField> {
+        self.schema.clone()
+    }
+
+    fn check(&self, config: &Config) -> Result<(), String> {
+        let dir = config.get(GRANT_DIR).trim();
+        if !dir.is_empty() && !Path::new(dir).is_dir() {
+            return Err(format!("Granted directory '{}' does not exist.", dir));
+        }
+        Ok(())
+    }
+
+    fn execute(&self, config: &Config, input: &[u8]) -> Result<Output, String> {
+        let stdout = MemoryOutputPipe::new(self.max_output);
+        let stderr = MemoryOutputPipe::new(self.max_output);
+        let mut wasi = WasiCtxBuilder::new();
+        wasi.stdin(MemoryInputPipe::new(input.to_vec()))
+            .stdout(stdout.clone())
+            .stderr(stderr.clone())
+            .args(&[self.name]);
+        for key in &self.setting_keys {
+            wasi.env(key, config.get(key));
+        }
+        let dir = config.get(GRANT_DIR).trim();
+        if !dir.is_empty() {
+            let (dir_perms, file_perms) = if config.flag(GRANT_WRITE) {
+                (DirPerms::all(), FilePerms::all())
+            } else {
+                (DirPerms::READ, FilePerms::READ)
+            };
+            wasi.preopened_dir(dir, dir, dir_perms, file_perms)
+                .map_err(|e| format!("Failed to grant '{}': {}", dir, e))?;
+        }
+
+        let state = State {
+            wasi: wasi.build_p1(),
+            limits: StoreLimitsBuilder::new().memory_size(self.max_memory).build(),
+        };
+        let mut store = Store::new(engine(), state);
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(self.max_fuel).map_err(|e| e.to_string())?;
+        let mut linker = Linker::new(engine());
+        preview1::add_to_linker_sync(&mut linker, |state: &mut State| &mut state.wasi)
+            .map_err(|e| e.to_string())?;
+        let instance = linker
+            .instantiate(&mut store, &self.module)
+            .map_err(|e| format!("Failed to start the module: {:#}", e))?;
+        let start = instance
+            .get_typed_func::<(), ()>(&mut store, "_start")
+            .map_err(|e| e.to_string())?;
+
+        let exit_code = match start.call(&mut store, ()) {
+            Ok(()) => 0,
+            Err(e) => match e.downcast_ref::<I32Exit>() {
+                Some(exit) => exit.0,
+                None if e.downcast_ref::<Trap>() == Some(&Trap::OutOfFuel) => {
+                    return Err(format!("The module ran out of fuel after {} units.", self.max_fuel));
+                }
+                None => return Err(format!("The module crashed: {:#}", e)),
+            },
+        };
+        Ok(Output {
+            stdout: stdout.contents().to_vec(),
+            stderr: stderr.contents().to_vec(),
+            exit_code,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest_limits_have_defaults() {
+        let manifest: Manifest = serde_json::from_str(r#"{"id": "x.y", "name": "Y"}"#).unwrap();
+        assert_eq!(manifest.category, "Plugins");
+        assert_eq!(manifest.max_memory_mb, 256);
+        assert_eq!(manifest.max_output_mb, 32);
+        let manifest: Manifest =
+            serde_json::from_str(r#"{"id": "x.y", "name": "Y", "max_output_mb": 4, "ports": "source"}"#).unwrap();
+        assert_eq!(manifest.max_output_mb, 4);
+        assert!(matches!(manifest.ports, PortsSpec::Source));
+    }
+
+    #[test]
+    fn settings_read_their_kind_from_a_type_tag() {
+        let setting: SettingSpec =
+            serde_json::from_str(r#"{"key": "mode", "kind": {"type": "choice", "choices": ["a", "b"]}}"#).unwrap();
+        assert!(matches!(setting.kind, SettingKind::Choice { choices } if choices == ["a", "b"]));
+        let setting: SettingSpec = serde_json::from_str(r#"{"key": "n", "kind": {"type": "number"}}"#).unwrap();
+        assert!(matches!(setting.kind, SettingKind::Number { min, max } if min == 0.0 && max == f64::MAX));
+    }
+}